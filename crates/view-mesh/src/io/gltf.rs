@@ -0,0 +1,767 @@
+use std::{cell::Cell, collections::HashMap};
+
+use base64::Engine;
+use cgmath::{Matrix, Matrix3, Matrix4, Quaternion, SquareMatrix, Vector3, Vector4};
+use serde::Deserialize;
+
+use super::{ExportedMesh, ImportedMesh, Progress, ReadError, SurfaceMaterial, file_name};
+use crate::mesh::Attributes;
+
+const GLB_MAGIC: &[u8] = b"glTF";
+const CHUNK_JSON: u32 = 0x4E4F534A;
+const CHUNK_BIN: u32 = 0x004E4942;
+
+const COMPONENT_U32: u32 = 5125;
+const COMPONENT_F32: u32 = 5126;
+const TARGET_ARRAY_BUFFER: u32 = 34962;
+const TARGET_ELEMENT_ARRAY_BUFFER: u32 = 34963;
+
+const MODE_TRIANGLES: u32 = 4;
+const MODE_TRIANGLE_STRIP: u32 = 5;
+const MODE_TRIANGLE_FAN: u32 = 6;
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct Document {
+    scene: Option<usize>,
+    scenes: Vec<Scene>,
+    nodes: Vec<Node>,
+    meshes: Vec<Mesh>,
+    materials: Vec<Material>,
+    accessors: Vec<Accessor>,
+    #[serde(rename = "bufferViews")]
+    buffer_views: Vec<BufferView>,
+    buffers: Vec<Buffer>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct Scene {
+    nodes: Vec<usize>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct Node {
+    name: Option<String>,
+    children: Vec<usize>,
+    mesh: Option<usize>,
+    matrix: Option<[f64; 16]>,
+    translation: Option<[f64; 3]>,
+    rotation: Option<[f64; 4]>,
+    scale: Option<[f64; 3]>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct Mesh {
+    name: Option<String>,
+    primitives: Vec<Primitive>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct Primitive {
+    attributes: HashMap<String, usize>,
+    indices: Option<usize>,
+    material: Option<usize>,
+    mode: Option<u32>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "camelCase")]
+struct Material {
+    pbr_metallic_roughness: Option<PbrMetallicRoughness>,
+    alpha_mode: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "camelCase")]
+struct PbrMetallicRoughness {
+    base_color_factor: Option<[f32; 4]>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "camelCase")]
+struct Accessor {
+    buffer_view: Option<usize>,
+    byte_offset: usize,
+    component_type: u32,
+    normalized: bool,
+    count: usize,
+    #[serde(rename = "type")]
+    ty: String,
+    sparse: Option<serde_json::Value>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "camelCase")]
+struct BufferView {
+    buffer: usize,
+    byte_offset: usize,
+    byte_length: usize,
+    byte_stride: Option<usize>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "camelCase")]
+struct Buffer {
+    uri: Option<String>,
+    byte_length: usize,
+}
+
+/// Whether `bytes` is a binary glTF container.
+pub fn is_binary(bytes: &[u8]) -> bool {
+    bytes.starts_with(GLB_MAGIC)
+}
+
+/// Reads a glTF 2.0 file, either JSON or binary. Every node of the default scene holding a
+/// mesh, or having such nodes below it, becomes an [`ImportedMesh`] with the node transform
+/// applied to its points and [`ImportedMesh::parent`] pointing to its parent node. Parents
+/// always come before their children. External buffers are looked up by file name in
+/// `resources`.
+pub fn read_gltf(
+    bytes: &[u8],
+    resources: &HashMap<String, Vec<u8>>,
+    progress: &Progress,
+) -> Result<Vec<ImportedMesh>, ReadError> {
+    let (json, bin) = if is_binary(bytes) {
+        split_glb(bytes)?
+    } else {
+        (bytes, None)
+    };
+    let doc: Document = serde_json::from_slice(json).map_err(|e| {
+        let message = format!("invalid gltf json: {}", e);
+        match e.line() {
+            0 => ReadError::from(message),
+            // the position is kept apart from the message
+            line => {
+                let message = message
+                    .rsplit_once(" at line ")
+                    .map_or(&*message, |(m, _)| m);
+                ReadError::at_line(line, message)
+            }
+        }
+    })?;
+
+    let buffers = doc
+        .buffers
+        .iter()
+        .enumerate()
+        .map(|(idx, buffer)| load_buffer(buffer, idx, bin, resources))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let roots = match doc.scene.or((!doc.scenes.is_empty()).then_some(0)) {
+        Some(scene) => doc
+            .scenes
+            .get(scene)
+            .ok_or("gltf scene does not exist")?
+            .nodes
+            .clone(),
+        None => {
+            // without scenes every node that is nobody's child is a root
+            let children: Vec<usize> = doc.nodes.iter().flat_map(|n| n.children.clone()).collect();
+            Vec::from_iter((0..doc.nodes.len()).filter(|idx| !children.contains(idx)))
+        }
+    };
+
+    let reader = Reader {
+        doc: &doc,
+        buffers: &buffers,
+        progress,
+        n_read: Cell::new(0),
+    };
+    let mut meshes = vec![];
+    let mut visited = vec![false; doc.nodes.len()];
+    for root in roots {
+        reader.read_node(root, None, Matrix4::identity(), &mut visited, &mut meshes)?;
+    }
+    if meshes.iter().all(|mesh| mesh.triangles.is_empty()) {
+        return Err("gltf file contains no triangle".into());
+    }
+    Ok(meshes)
+}
+
+/// Splits a GLB container into its JSON chunk and its optional binary chunk.
+fn split_glb(bytes: &[u8]) -> Result<(&[u8], Option<&[u8]>), String> {
+    let u32_at = |pos: usize| {
+        bytes
+            .get(pos..pos + 4)
+            .map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+            .ok_or("unexpected end of glb data")
+    };
+    if u32_at(4)? != 2 {
+        return Err("only glTF 2.0 is supported".to_owned());
+    }
+    let length = (u32_at(8)? as usize).min(bytes.len());
+    let mut json = None;
+    let mut bin = None;
+    let mut pos = 12;
+    while pos + 8 <= length {
+        let chunk_length = u32_at(pos)? as usize;
+        let chunk_type = u32_at(pos + 4)?;
+        let end = (pos + 8)
+            .checked_add(chunk_length)
+            .ok_or("unexpected end of glb data")?;
+        let chunk = bytes
+            .get(pos + 8..end)
+            .ok_or("unexpected end of glb data")?;
+        match chunk_type {
+            CHUNK_JSON if json.is_none() => json = Some(chunk),
+            CHUNK_BIN if bin.is_none() => bin = Some(chunk),
+            _ => {}
+        }
+        pos = end;
+    }
+    Ok((json.ok_or("missing glb json chunk")?, bin))
+}
+
+fn load_buffer(
+    buffer: &Buffer,
+    idx: usize,
+    bin: Option<&[u8]>,
+    resources: &HashMap<String, Vec<u8>>,
+) -> Result<Vec<u8>, String> {
+    let data = match &buffer.uri {
+        // the first buffer of a GLB without uri is its binary chunk
+        None if idx == 0 => bin.ok_or("missing glb binary chunk")?.to_vec(),
+        None => return Err(format!("gltf buffer {} has no data", idx)),
+        Some(uri) if uri.starts_with("data:") => {
+            let (_, payload) = uri
+                .split_once(";base64,")
+                .ok_or("unsupported gltf data uri")?;
+            base64::engine::general_purpose::STANDARD
+                .decode(payload)
+                .map_err(|_| "invalid base64 in gltf data uri")?
+        }
+        Some(uri) => {
+            let name = file_name(uri).replace("%20", " ");
+            resources
+                .get(&name)
+                .ok_or_else(|| format!("missing gltf buffer file {}", name))?
+                .clone()
+        }
+    };
+    if data.len() < buffer.byte_length {
+        return Err(format!("gltf buffer {} is too short", idx));
+    }
+    Ok(data)
+}
+
+struct Reader<'a> {
+    doc: &'a Document,
+    buffers: &'a [Vec<u8>],
+    progress: &'a Progress<'a>,
+    /// number of nodes read so far
+    n_read: Cell<usize>,
+}
+
+impl Reader<'_> {
+    /// Reads `node` and its children depth first. Returns whether the node was kept, i.e.
+    /// whether it or one of its descendants has a mesh.
+    fn read_node(
+        &self,
+        idx: usize,
+        parent: Option<usize>,
+        parent_transform: Matrix4<f64>,
+        visited: &mut [bool],
+        meshes: &mut Vec<ImportedMesh>,
+    ) -> Result<bool, String> {
+        let node = self.doc.nodes.get(idx).ok_or("gltf node does not exist")?;
+        if std::mem::replace(&mut visited[idx], true) {
+            return Err("gltf node is referenced twice".to_owned());
+        }
+        let transform = parent_transform * local_transform(node);
+
+        let mut mesh = match node.mesh {
+            Some(mesh) => self.read_mesh(mesh, &transform)?,
+            None => ImportedMesh::default(),
+        };
+        self.n_read.set(self.n_read.get() + 1);
+        self.progress.update(self.n_read.get(), self.doc.nodes.len());
+        mesh.name = node
+            .name
+            .clone()
+            .or_else(|| {
+                node.mesh
+                    .and_then(|m| self.doc.meshes.get(m))
+                    .and_then(|m| m.name.clone())
+            })
+            .or_else(|| Some(format!("node{}", idx)));
+        mesh.parent = parent;
+        let has_triangles = !mesh.triangles.is_empty();
+        let pos = meshes.len();
+        meshes.push(mesh);
+
+        let mut kept = has_triangles;
+        for &child in &node.children {
+            kept |= self.read_node(child, Some(pos), transform, visited, meshes)?;
+        }
+        if !kept {
+            meshes.truncate(pos);
+        }
+        Ok(kept)
+    }
+
+    /// Reads the triangles of every primitive of a mesh into a single mesh in world space.
+    fn read_mesh(&self, idx: usize, transform: &Matrix4<f64>) -> Result<ImportedMesh, String> {
+        let mesh = self.doc.meshes.get(idx).ok_or("gltf mesh does not exist")?;
+        let normal_matrix = Matrix3::from_cols(
+            transform.x.truncate(),
+            transform.y.truncate(),
+            transform.z.truncate(),
+        );
+        // mirroring transforms turn the triangles inside out
+        let mirrored = normal_matrix.determinant() < 0.0;
+        let normal_matrix = normal_matrix
+            .invert()
+            .map_or(normal_matrix, |m| m.transpose());
+
+        let mut points = vec![];
+        let mut triangles = vec![];
+        let mut normals = Some(vec![]);
+        let mut colors = vec![];
+        let mut has_vertex_colors = false;
+        let mut materials = vec![];
+        for primitive in &mesh.primitives {
+            let mode = primitive.mode.unwrap_or(MODE_TRIANGLES);
+            if !matches!(
+                mode,
+                MODE_TRIANGLES | MODE_TRIANGLE_STRIP | MODE_TRIANGLE_FAN
+            ) {
+                continue;
+            }
+            let Some(&position) = primitive.attributes.get("POSITION") else {
+                continue;
+            };
+            let (positions, components) = self.read_accessor(position)?;
+            if components != 3 {
+                return Err("gltf positions are not 3D vectors".to_owned());
+            }
+            let n_points = positions.len() / 3;
+            let offset = points.len() / 3;
+            for p in positions.chunks(3) {
+                let p = transform * Vector4::new(p[0], p[1], p[2], 1.0);
+                points.extend_from_slice(&[p.x, p.y, p.z]);
+            }
+
+            let indices = match primitive.indices {
+                Some(indices) => {
+                    let (indices, _) = self.read_accessor(indices)?;
+                    Vec::from_iter(indices.into_iter().map(|idx| idx as usize))
+                }
+                None => Vec::from_iter(0..n_points),
+            };
+            if indices.iter().any(|&idx| idx >= n_points) {
+                return Err("gltf primitive refers to a missing vertex".to_owned());
+            }
+            for mut tri in primitive_triangles(&indices, mode) {
+                if mirrored {
+                    tri.swap(1, 2);
+                }
+                triangles.extend(tri.iter().map(|idx| idx + offset));
+            }
+
+            match (normals.as_mut(), primitive.attributes.get("NORMAL")) {
+                (Some(normals), Some(&accessor)) => {
+                    let (values, _) = self.read_accessor(accessor)?;
+                    if values.len() != positions.len() {
+                        return Err("gltf normals do not match the positions".to_owned());
+                    }
+                    for n in values.chunks(3) {
+                        let n = normal_matrix * Vector3::new(n[0], n[1], n[2]);
+                        let n = n / n.x.hypot(n.y).hypot(n.z).max(f64::MIN_POSITIVE);
+                        normals.extend_from_slice(&[n.x as f32, n.y as f32, n.z as f32]);
+                    }
+                }
+                _ => normals = None,
+            }
+
+            let material = primitive.material.and_then(|m| self.doc.materials.get(m));
+            let factor = base_color(material);
+            match primitive.attributes.get("COLOR_0") {
+                Some(&accessor) => {
+                    has_vertex_colors = true;
+                    let (values, components) = self.read_accessor(accessor)?;
+                    if !matches!(components, 3 | 4) || values.len() / components != n_points {
+                        return Err("gltf colors do not match the positions".to_owned());
+                    }
+                    for c in values.chunks(components) {
+                        colors.extend((0..3).map(|k| c[k] as f32 * factor[k]));
+                    }
+                }
+                None => {
+                    for _ in 0..n_points {
+                        colors.extend_from_slice(&factor[..3]);
+                    }
+                }
+            }
+            materials.push(primitive.material);
+        }
+
+        // primitives with different materials are told apart with vertex colors
+        let uniform = materials.windows(2).all(|w| w[0] == w[1]);
+        let material = materials
+            .first()
+            .map(|&m| surface_material(m.and_then(|m| self.doc.materials.get(m))));
+        // glTF vertices are already split along the seams, every corner takes the normal of
+        // its vertex
+        let normals = normals.map(|normals| {
+            Vec::from_iter(
+                triangles
+                    .iter()
+                    .flat_map(|&idx| normals[idx * 3..idx * 3 + 3].iter().copied()),
+            )
+        });
+        Ok(ImportedMesh {
+            points,
+            triangles,
+            attributes: Attributes {
+                colors: (has_vertex_colors || !uniform).then_some(colors),
+                normals,
+                ..Default::default()
+            },
+            material,
+            ..Default::default()
+        })
+    }
+
+    /// Reads the elements of an accessor as a flat list of values, along with the number
+    /// of components of each element.
+    fn read_accessor(&self, idx: usize) -> Result<(Vec<f64>, usize), String> {
+        let accessor = self
+            .doc
+            .accessors
+            .get(idx)
+            .ok_or("gltf accessor does not exist")?;
+        if accessor.sparse.is_some() {
+            return Err("sparse gltf accessors are not supported".to_owned());
+        }
+        let components = match accessor.ty.as_str() {
+            "SCALAR" => 1,
+            "VEC2" => 2,
+            "VEC3" => 3,
+            "VEC4" | "MAT2" => 4,
+            "MAT3" => 9,
+            "MAT4" => 16,
+            ty => return Err(format!("unknown gltf accessor type {}", ty)),
+        };
+        let size = match accessor.component_type {
+            5120 | 5121 => 1,
+            5122 | 5123 => 2,
+            5125 | 5126 => 4,
+            ty => return Err(format!("unknown gltf component type {}", ty)),
+        };
+        let Some(view) = accessor.buffer_view else {
+            // accessors without buffer view are initialized with zeros, the count is bounded
+            // by the data of the file so that a few bytes cannot ask for any amount of memory
+            let data_length: usize = self.buffers.iter().map(Vec::len).sum();
+            let length = accessor
+                .count
+                .checked_mul(components)
+                .filter(|&length| length.saturating_mul(size) <= data_length)
+                .ok_or("gltf accessor without buffer view is larger than the buffers")?;
+            return Ok((vec![0.0; length], components));
+        };
+        let view = self
+            .doc
+            .buffer_views
+            .get(view)
+            .ok_or("gltf buffer view does not exist")?;
+        let buffer = self
+            .buffers
+            .get(view.buffer)
+            .ok_or("gltf buffer does not exist")?;
+        let data = view
+            .byte_offset
+            .checked_add(view.byte_length)
+            .and_then(|end| buffer.get(view.byte_offset..end))
+            .ok_or("gltf buffer view out of range")?;
+        let stride = view.byte_stride.unwrap_or(components * size);
+        // the whole accessor must fit in the view before anything is reserved for it
+        let end = match accessor.count {
+            0 => Some(accessor.byte_offset),
+            count => (count - 1)
+                .checked_mul(stride)
+                .and_then(|last| last.checked_add(accessor.byte_offset))
+                .and_then(|last| last.checked_add(components * size)),
+        };
+        if end.is_none_or(|end| end > data.len()) {
+            return Err("gltf accessor out of range".to_owned());
+        }
+
+        let mut values = Vec::with_capacity(accessor.count * components);
+        for i in 0..accessor.count {
+            let start = accessor.byte_offset + i * stride;
+            let element = data
+                .get(start..start + components * size)
+                .ok_or("gltf accessor out of range")?;
+            for c in element.chunks(size) {
+                let value = match accessor.component_type {
+                    5120 => c[0] as i8 as f64,
+                    5121 => c[0] as f64,
+                    5122 => i16::from_le_bytes([c[0], c[1]]) as f64,
+                    5123 => u16::from_le_bytes([c[0], c[1]]) as f64,
+                    5125 => u32::from_le_bytes(c.try_into().unwrap()) as f64,
+                    _ => f32::from_le_bytes(c.try_into().unwrap()) as f64,
+                };
+                let value = match (accessor.normalized, accessor.component_type) {
+                    (true, 5120) => (value / 127.0).max(-1.0),
+                    (true, 5121) => value / 255.0,
+                    (true, 5122) => (value / 32767.0).max(-1.0),
+                    (true, 5123) => value / 65535.0,
+                    _ => value,
+                };
+                values.push(value);
+            }
+        }
+        Ok((values, components))
+    }
+}
+
+fn local_transform(node: &Node) -> Matrix4<f64> {
+    if let Some(m) = node.matrix {
+        // glTF matrices are column major, like cgmath ones
+        return Matrix4::new(
+            m[0], m[1], m[2], m[3], m[4], m[5], m[6], m[7], m[8], m[9], m[10], m[11], m[12], m[13],
+            m[14], m[15],
+        );
+    }
+    let [tx, ty, tz] = node.translation.unwrap_or([0.0; 3]);
+    let [x, y, z, w] = node.rotation.unwrap_or([0.0, 0.0, 0.0, 1.0]);
+    let [sx, sy, sz] = node.scale.unwrap_or([1.0; 3]);
+    Matrix4::from_translation(Vector3::new(tx, ty, tz))
+        * Matrix4::from(Quaternion::new(w, x, y, z))
+        * Matrix4::from_nonuniform_scale(sx, sy, sz)
+}
+
+/// Turns the indices of a primitive into a list of triangles, whatever its topology.
+fn primitive_triangles(indices: &[usize], mode: u32) -> Vec<[usize; 3]> {
+    let n = indices.len();
+    match mode {
+        MODE_TRIANGLE_STRIP => Vec::from_iter((0..n.saturating_sub(2)).map(|k| {
+            // every other triangle of a strip is flipped to keep the orientation
+            if k % 2 == 0 {
+                [indices[k], indices[k + 1], indices[k + 2]]
+            } else {
+                [indices[k + 1], indices[k], indices[k + 2]]
+            }
+        })),
+        MODE_TRIANGLE_FAN => Vec::from_iter(
+            (1..n.saturating_sub(1)).map(|k| [indices[0], indices[k], indices[k + 1]]),
+        ),
+        _ => Vec::from_iter(indices.chunks_exact(3).map(|t| [t[0], t[1], t[2]])),
+    }
+}
+
+fn base_color(material: Option<&Material>) -> [f32; 4] {
+    material
+        .and_then(|m| m.pbr_metallic_roughness.as_ref())
+        .and_then(|pbr| pbr.base_color_factor)
+        .unwrap_or([1.0; 4])
+}
+
+fn surface_material(material: Option<&Material>) -> SurfaceMaterial {
+    let [r, g, b, a] = base_color(material);
+    // the alpha of opaque and masked materials does not make them transparent
+    let blend = material.and_then(|m| m.alpha_mode.as_deref()) == Some("BLEND");
+    SurfaceMaterial {
+        ambient: None,
+        diffuse: [r, g, b],
+        specular: None,
+        alpha: if blend { a } else { 1.0 },
+        diffuse_texture: None,
+    }
+}
+
+/// Writes `meshes` as a GLB file holding one node per mesh, the color of each mesh
+/// becoming the base color factor of a non metallic material.
+pub fn write_glb(meshes: &[ExportedMesh]) -> Vec<u8> {
+    let mut bin = vec![];
+    let mut nodes = vec![];
+    let mut gltf_meshes = vec![];
+    let mut materials = vec![];
+    let mut accessors = vec![];
+    let mut buffer_views = vec![];
+    for (k, mesh) in meshes.iter().enumerate() {
+        let mut min = [f32::MAX; 3];
+        let mut max = [f32::MIN; 3];
+        let positions_start = bin.len();
+        for p in mesh.points.chunks_exact(3) {
+            for k in 0..3 {
+                let v = p[k] as f32;
+                min[k] = min[k].min(v);
+                max[k] = max[k].max(v);
+                bin.extend_from_slice(&v.to_le_bytes());
+            }
+        }
+        let indices_start = bin.len();
+        for &idx in mesh.triangles {
+            bin.extend_from_slice(&(idx as u32).to_le_bytes());
+        }
+
+        buffer_views.push(serde_json::json!({
+            "buffer": 0,
+            "byteOffset": positions_start,
+            "byteLength": indices_start - positions_start,
+            "target": TARGET_ARRAY_BUFFER,
+        }));
+        buffer_views.push(serde_json::json!({
+            "buffer": 0,
+            "byteOffset": indices_start,
+            "byteLength": bin.len() - indices_start,
+            "target": TARGET_ELEMENT_ARRAY_BUFFER,
+        }));
+        accessors.push(serde_json::json!({
+            "bufferView": 2 * k,
+            "componentType": COMPONENT_F32,
+            "count": mesh.points.len() / 3,
+            "type": "VEC3",
+            "min": min,
+            "max": max,
+        }));
+        accessors.push(serde_json::json!({
+            "bufferView": 2 * k + 1,
+            "componentType": COMPONENT_U32,
+            "count": mesh.triangles.len(),
+            "type": "SCALAR",
+        }));
+        let [r, g, b] = mesh.color;
+        let blend = mesh.alpha < 1.0;
+        materials.push(serde_json::json!({
+            "pbrMetallicRoughness": {
+                "baseColorFactor": [r, g, b, mesh.alpha],
+                "metallicFactor": 0.0,
+                "roughnessFactor": 0.5,
+            },
+            "alphaMode": if blend { "BLEND" } else { "OPAQUE" },
+            "doubleSided": blend,
+        }));
+        gltf_meshes.push(serde_json::json!({
+            "name": mesh.name,
+            "primitives": [{
+                "attributes": { "POSITION": 2 * k },
+                "indices": 2 * k + 1,
+                "material": k,
+                "mode": MODE_TRIANGLES,
+            }],
+        }));
+        nodes.push(serde_json::json!({ "name": mesh.name, "mesh": k }));
+    }
+
+    let json = serde_json::json!({
+        "asset": { "version": "2.0", "generator": "view" },
+        "scene": 0,
+        "scenes": [{ "nodes": Vec::from_iter(0..meshes.len()) }],
+        "nodes": nodes,
+        "meshes": gltf_meshes,
+        "materials": materials,
+        "accessors": accessors,
+        "bufferViews": buffer_views,
+        "buffers": [{ "byteLength": bin.len() }],
+    });
+    let mut json = json.to_string().into_bytes();
+
+    // chunks are 4 bytes aligned, with spaces for the json and zeros for the binary data
+    json.resize(json.len().next_multiple_of(4), b' ');
+    bin.resize(bin.len().next_multiple_of(4), 0);
+    let length = 12 + 8 + json.len() + 8 + bin.len();
+    let mut bytes = Vec::with_capacity(length);
+    bytes.extend_from_slice(GLB_MAGIC);
+    bytes.extend_from_slice(&2u32.to_le_bytes());
+    bytes.extend_from_slice(&(length as u32).to_le_bytes());
+    for (chunk_type, chunk) in [(CHUNK_JSON, &json), (CHUNK_BIN, &bin)] {
+        bytes.extend_from_slice(&(chunk.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&chunk_type.to_le_bytes());
+        bytes.extend_from_slice(chunk);
+    }
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A triangle whose positions, and the colors given by `colors` if any, are read from a
+    /// data uri buffer holding the 3 positions.
+    fn triangle(position: serde_json::Value, colors: Option<serde_json::Value>) -> Vec<u8> {
+        let bin = Vec::from_iter(
+            [0.0f32, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0]
+                .iter()
+                .flat_map(|v| v.to_le_bytes()),
+        );
+        let uri = format!(
+            "data:application/octet-stream;base64,{}",
+            base64::engine::general_purpose::STANDARD.encode(&bin)
+        );
+        let mut attributes = serde_json::json!({ "POSITION": 0 });
+        let mut accessors = vec![position];
+        if let Some(colors) = colors {
+            attributes["COLOR_0"] = 1.into();
+            accessors.push(colors);
+        }
+        serde_json::to_vec(&serde_json::json!({
+            "asset": { "version": "2.0" },
+            "nodes": [{ "mesh": 0 }],
+            "meshes": [{ "primitives": [{ "attributes": attributes }] }],
+            "accessors": accessors,
+            "bufferViews": [{ "buffer": 0, "byteLength": bin.len() }],
+            "buffers": [{ "uri": uri, "byteLength": bin.len() }],
+        }))
+        .unwrap()
+    }
+
+    fn accessor(view: Option<usize>, count: usize, ty: &str) -> serde_json::Value {
+        serde_json::json!({
+            "bufferView": view,
+            "componentType": COMPONENT_F32,
+            "count": count,
+            "type": ty,
+        })
+    }
+
+    #[test]
+    fn reads_a_triangle() {
+        let bytes = triangle(accessor(Some(0), 3, "VEC3"), None);
+        let meshes = read_gltf(&bytes, &HashMap::new(), &Progress::default()).unwrap();
+        assert_eq!(meshes[0].triangles, [0, 1, 2]);
+    }
+
+    #[test]
+    fn rejects_accessors_larger_than_their_view() {
+        let bytes = triangle(accessor(Some(0), usize::MAX / 2, "VEC3"), None);
+        assert!(read_gltf(&bytes, &HashMap::new(), &Progress::default()).is_err());
+        let bytes = triangle(accessor(None, usize::MAX / 2, "VEC3"), None);
+        assert!(read_gltf(&bytes, &HashMap::new(), &Progress::default()).is_err());
+    }
+
+    #[test]
+    fn rejects_offsets_overflowing_the_buffer() {
+        let mut position = accessor(Some(0), 3, "VEC3");
+        position["byteOffset"] = usize::MAX.into();
+        let bytes = triangle(position, None);
+        assert!(read_gltf(&bytes, &HashMap::new(), &Progress::default()).is_err());
+
+        let bytes = triangle(accessor(Some(0), 3, "VEC3"), None);
+        let mut doc: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        doc["bufferViews"][0]["byteOffset"] = usize::MAX.into();
+        let bytes = serde_json::to_vec(&doc).unwrap();
+        assert!(read_gltf(&bytes, &HashMap::new(), &Progress::default()).is_err());
+
+        let mut glb = Vec::from_iter(GLB_MAGIC.iter().copied());
+        for word in [2, 20, u32::MAX, CHUNK_JSON] {
+            glb.extend(word.to_le_bytes());
+        }
+        assert!(read_gltf(&glb, &HashMap::new(), &Progress::default()).is_err());
+    }
+
+    #[test]
+    fn rejects_colors_not_matching_the_positions() {
+        let colors = accessor(Some(0), 2, "VEC3");
+        let bytes = triangle(accessor(Some(0), 3, "VEC3"), Some(colors));
+        assert!(read_gltf(&bytes, &HashMap::new(), &Progress::default()).is_err());
+        let colors = accessor(Some(0), 3, "VEC2");
+        let bytes = triangle(accessor(Some(0), 3, "VEC3"), Some(colors));
+        assert!(read_gltf(&bytes, &HashMap::new(), &Progress::default()).is_err());
+    }
+}
@@ -0,0 +1,562 @@
+use std::{cell::Cell, collections::HashMap};
+
+use serde::{Deserialize, Serialize};
+
+use crate::mesh::{Attributes, Polygons};
+
+pub mod gltf;
+pub mod obj;
+pub mod ply;
+pub mod stl;
+pub mod zip;
+
+/// A mesh read from a file, with the vertex attributes it carries.
+#[derive(Clone, Debug, Default)]
+pub struct ImportedMesh {
+    /// name of the object in the file, if the format has one
+    pub name: Option<String>,
+    pub points: Vec<f64>,
+    pub triangles: Vec<usize>,
+    /// the polygons `triangles` were made from, if some faces were not triangles
+    pub polygons: Option<Polygons>,
+    pub attributes: Attributes,
+    pub material: Option<SurfaceMaterial>,
+    /// index of the parent mesh among the meshes read from the same file, for formats
+    /// with a node hierarchy
+    pub parent: Option<usize>,
+}
+
+/// Surface properties of a mesh read from a material library.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SurfaceMaterial {
+    pub ambient: Option<[f32; 3]>,
+    pub diffuse: [f32; 3],
+    pub specular: Option<[f32; 3]>,
+    pub alpha: f32,
+    /// file name of the diffuse color texture
+    pub diffuse_texture: Option<String>,
+}
+
+/// A model to write to a file, with the colors it is displayed with.
+#[derive(Clone, Copy)]
+pub struct ExportedMesh<'a> {
+    pub name: &'a str,
+    pub points: &'a [f64],
+    pub triangles: &'a [usize],
+    /// the polygons `triangles` were made from, written instead of the triangles by the
+    /// formats supporting polygonal faces
+    pub polygons: Option<&'a Polygons>,
+    pub color: [f32; 3],
+    pub alpha: f32,
+}
+
+impl ExportedMesh<'_> {
+    /// The polygons of the mesh if it has some, its triangles otherwise.
+    fn faces(&self) -> Vec<&[usize]> {
+        match self.polygons {
+            Some(polygons) => {
+                let mut start = 0;
+                Vec::from_iter(polygons.arities.iter().map(|&arity| {
+                    start += arity;
+                    &polygons.indices[start - arity..start]
+                }))
+            }
+            None => Vec::from_iter(self.triangles.chunks_exact(3)),
+        }
+    }
+}
+
+/// A file produced by an export.
+pub struct ExportedFile {
+    pub name: String,
+    pub mime_type: &'static str,
+    pub bytes: Vec<u8>,
+}
+
+impl From<(Vec<f64>, Vec<usize>)> for ImportedMesh {
+    fn from((points, triangles): (Vec<f64>, Vec<usize>)) -> Self {
+        Self {
+            name: None,
+            points,
+            triangles,
+            polygons: None,
+            attributes: Attributes::default(),
+            material: None,
+            parent: None,
+        }
+    }
+}
+
+impl ImportedMesh {
+    /// Concatenates `meshes` into a single unnamed mesh. Vertex colors, normals and texture
+    /// coordinates are kept if every mesh has them, and so are the scalar fields present in
+    /// every mesh. Materials and the hierarchy are dropped.
+    pub fn merge(meshes: Vec<ImportedMesh>) -> ImportedMesh {
+        let mut merged = ImportedMesh::default();
+        let concat = |get: fn(&Attributes) -> &Option<Vec<f32>>| {
+            meshes
+                .iter()
+                .map(|m| get(&m.attributes).as_deref())
+                .collect::<Option<Vec<_>>>()
+                .map(|parts| parts.concat())
+        };
+        merged.attributes.colors = concat(|a| &a.colors);
+        merged.attributes.normals = concat(|a| &a.normals);
+        merged.attributes.uvs = concat(|a| &a.uvs);
+        let mut polygons = meshes
+            .iter()
+            .any(|m| m.polygons.is_some())
+            .then(Polygons::default);
+        if let Some(first) = meshes.first() {
+            merged.attributes.scalars = first
+                .attributes
+                .scalars
+                .iter()
+                .filter(|(name, _)| {
+                    meshes
+                        .iter()
+                        .all(|m| m.attributes.scalars.iter().any(|(n, _)| n == name))
+                })
+                .map(|(name, _)| (name.clone(), vec![]))
+                .collect();
+        }
+        for mesh in meshes {
+            let offset = merged.points.len() / 3;
+            merged.points.extend_from_slice(&mesh.points);
+            merged
+                .triangles
+                .extend(mesh.triangles.iter().map(|idx| idx + offset));
+            if let Some(polygons) = polygons.as_mut() {
+                match &mesh.polygons {
+                    Some(p) => {
+                        polygons
+                            .indices
+                            .extend(p.indices.iter().map(|idx| idx + offset));
+                        polygons.arities.extend_from_slice(&p.arities);
+                    }
+                    None => {
+                        polygons
+                            .indices
+                            .extend(mesh.triangles.iter().map(|idx| idx + offset));
+                        polygons
+                            .arities
+                            .extend(std::iter::repeat_n(3, mesh.triangles.len() / 3));
+                    }
+                }
+            }
+            for (name, values) in merged.attributes.scalars.iter_mut() {
+                let (_, v) = mesh
+                    .attributes
+                    .scalars
+                    .iter()
+                    .find(|(n, _)| n == name)
+                    .unwrap();
+                values.extend_from_slice(v);
+            }
+        }
+        merged.polygons = polygons;
+        merged
+    }
+}
+
+/// Why the meshes of a file could not be read.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReadError {
+    pub message: String,
+    /// line of a text file where the parsing stopped, starting at 1
+    pub line: Option<usize>,
+}
+
+impl ReadError {
+    pub fn at_line(line: usize, message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            line: Some(line),
+        }
+    }
+}
+
+impl From<String> for ReadError {
+    fn from(message: String) -> Self {
+        Self {
+            message,
+            line: None,
+        }
+    }
+}
+
+impl From<&str> for ReadError {
+    fn from(message: &str) -> Self {
+        message.to_owned().into()
+    }
+}
+
+impl std::fmt::Display for ReadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{} at line {}", self.message, line),
+            None => f.write_str(&self.message),
+        }
+    }
+}
+
+/// Reports how much of a file was read, as a fraction in `[0, 1]`. The callback may be
+/// costly, such as posting a message from a worker, so it is only called every percent.
+#[derive(Default)]
+pub struct Progress<'a> {
+    report: Option<&'a dyn Fn(f64)>,
+    last: Cell<f64>,
+}
+
+impl<'a> Progress<'a> {
+    pub fn new(report: &'a dyn Fn(f64)) -> Self {
+        Self {
+            report: Some(report),
+            last: Cell::new(0.0),
+        }
+    }
+
+    /// Reports that `done` out of `total` units of work are done.
+    pub fn update(&self, done: usize, total: usize) {
+        let Some(report) = self.report else {
+            return;
+        };
+        let fraction = if total == 0 {
+            1.0
+        } else {
+            (done as f64 / total as f64).min(1.0)
+        };
+        let last = self.last.get();
+        if fraction >= last + 0.01 || (fraction == 1.0 && last < 1.0) {
+            self.last.set(fraction);
+            report(fraction);
+        }
+    }
+}
+
+/// Mesh file formats that can be imported.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Obj,
+    Stl,
+    Ply,
+    Gltf,
+}
+
+impl Format {
+    /// Formats the models can be exported to.
+    pub const EXPORTS: [Self; 4] = [Self::Obj, Self::Stl, Self::Ply, Self::Gltf];
+
+    /// Format given by the extension of the file name.
+    pub fn from_name(name: &str) -> Option<Self> {
+        let (_, ext) = name.rsplit_once('.')?;
+        Self::from_extension(ext)
+    }
+
+    pub fn from_extension(ext: &str) -> Option<Self> {
+        match ext.to_ascii_lowercase().as_str() {
+            "obj" => Some(Self::Obj),
+            "stl" => Some(Self::Stl),
+            "ply" => Some(Self::Ply),
+            "gltf" | "glb" => Some(Self::Gltf),
+            _ => None,
+        }
+    }
+
+    /// Format guessed from the content of the file.
+    pub fn detect(bytes: &[u8]) -> Option<Self> {
+        if gltf::is_binary(bytes) {
+            return Some(Self::Gltf);
+        }
+        if stl::is_binary(bytes) {
+            return Some(Self::Stl);
+        }
+        let head = String::from_utf8_lossy(&bytes[..bytes.len().min(1024)]);
+        let head = head.trim_start();
+        if head.starts_with("ply") {
+            return Some(Self::Ply);
+        }
+        if head.starts_with("solid") && head.contains("facet") {
+            return Some(Self::Stl);
+        }
+        if head.starts_with('{') && head.contains("\"asset\"") {
+            return Some(Self::Gltf);
+        }
+        let is_obj_line = |line: &str| {
+            let keyword = line.split_whitespace().next().unwrap_or("");
+            matches!(
+                keyword,
+                "v" | "vn" | "vt" | "f" | "o" | "g" | "mtllib" | "usemtl"
+            )
+        };
+        if head.lines().any(is_obj_line) {
+            return Some(Self::Obj);
+        }
+        None
+    }
+
+    /// Reads the meshes of this format from the content of a file. `resources` holds the
+    /// other files picked along with it, such as material libraries or glTF buffers, by file
+    /// name.
+    pub fn read(
+        self,
+        bytes: &[u8],
+        resources: &HashMap<String, Vec<u8>>,
+        progress: &Progress,
+    ) -> Result<Vec<ImportedMesh>, ReadError> {
+        match self {
+            Self::Obj => obj::read_obj(bytes, resources, progress),
+            Self::Stl => stl::read_stl(bytes, progress).map(|mesh| vec![mesh.into()]),
+            Self::Ply => Ok(vec![ply::read_ply(bytes, progress)?]),
+            Self::Gltf => gltf::read_gltf(bytes, resources, progress),
+        }
+    }
+
+    /// Extension of the files written in this format.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Obj => "obj",
+            Self::Stl => "stl",
+            Self::Ply => "ply",
+            Self::Gltf => "glb",
+        }
+    }
+
+    pub fn mime_type(self) -> &'static str {
+        match self {
+            Self::Obj => "model/obj",
+            Self::Stl => "model/stl",
+            Self::Ply => "application/octet-stream",
+            Self::Gltf => "model/gltf-binary",
+        }
+    }
+
+    /// Writes `meshes` into a single file of this format named after `stem`. OBJ files have
+    /// one `o` block per mesh and, with `bundle_mtl`, keep the colors in an MTL file stored
+    /// with them in a zip archive. glTF files are GLB ones with a node per mesh. STL and PLY
+    /// files are binary, holding all the meshes as one.
+    pub fn write(self, stem: &str, meshes: &[ExportedMesh], bundle_mtl: bool) -> ExportedFile {
+        let file = |format: Self, bytes| ExportedFile {
+            name: format!("{}.{}", stem, format.extension()),
+            mime_type: format.mime_type(),
+            bytes,
+        };
+        match self {
+            Self::Obj if !bundle_mtl => file(self, obj::write_obj(meshes, None).into_bytes()),
+            Self::Obj => {
+                let obj_name = format!("{}.{}", stem, self.extension());
+                let mtl_name = format!("{}.mtl", stem);
+                let obj = obj::write_obj(meshes, Some(&mtl_name));
+                let mtl = obj::write_mtl(meshes);
+                let files = [(&*obj_name, obj.as_bytes()), (&*mtl_name, mtl.as_bytes())];
+                ExportedFile {
+                    name: format!("{}.zip", stem),
+                    mime_type: "application/zip",
+                    bytes: zip::write_zip(&files),
+                }
+            }
+            Self::Stl => file(self, stl::write_stl(meshes)),
+            Self::Ply => file(self, ply::write_ply(meshes)),
+            Self::Gltf => file(self, gltf::write_glb(meshes)),
+        }
+    }
+}
+
+/// Reads the meshes of the file called `name`, guessing its format from the content when
+/// the extension is unknown.
+pub fn read_file(
+    name: &str,
+    bytes: &[u8],
+    resources: &HashMap<String, Vec<u8>>,
+    progress: &Progress,
+) -> Result<Vec<ImportedMesh>, ReadError> {
+    let format = Format::from_name(name)
+        .or_else(|| Format::detect(bytes))
+        .ok_or_else(|| format!("unsupported file {}", name))?;
+    format.read(bytes, resources, progress)
+}
+
+/// Converts a color with components in `[0, 1]` to bytes.
+fn to_rgba8(color: [f32; 3], alpha: f32) -> [u8; 4] {
+    let [r, g, b] = color;
+    [r, g, b, alpha].map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8)
+}
+
+/// Strips the extension from a file name.
+pub fn file_stem(name: &str) -> &str {
+    name.rsplit_once('.').map_or(name, |(stem, _)| stem)
+}
+
+/// Strips the directories from a path referenced inside a file.
+pub fn file_name(path: &str) -> &str {
+    path.rsplit(['/', '\\']).next().unwrap_or(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SQUARE: [f64; 12] = [0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 1.0, 0.0, 0.0, 1.0, 0.0];
+    const TRIANGLE: [f64; 9] = [0.0, 0.0, 1.0, 2.0, 0.0, 1.0, 0.0, 0.5, 1.0];
+
+    /// A square in two triangles, translucent orange, and an opaque blue triangle.
+    fn meshes() -> [ExportedMesh<'static>; 2] {
+        [
+            ExportedMesh {
+                name: "square",
+                points: &SQUARE,
+                triangles: &[0, 1, 2, 0, 2, 3],
+                polygons: None,
+                color: [1.0, 0.5, 0.0],
+                alpha: 0.5,
+            },
+            ExportedMesh {
+                name: "triangle",
+                points: &TRIANGLE,
+                triangles: &[0, 1, 2],
+                polygons: None,
+                color: [0.0, 0.25, 1.0],
+                alpha: 1.0,
+            },
+        ]
+    }
+
+    /// The corners of every triangle, so that meshes can be compared whatever the order of
+    /// their points.
+    fn corners(points: &[f64], triangles: &[usize]) -> Vec<[f64; 9]> {
+        Vec::from_iter(triangles.chunks(3).map(|tri| {
+            let mut corners = [0.0; 9];
+            for (k, &idx) in tri.iter().enumerate() {
+                corners[k * 3..k * 3 + 3].copy_from_slice(&points[idx * 3..idx * 3 + 3]);
+            }
+            corners
+        }))
+    }
+
+    fn all_corners(meshes: &[ExportedMesh]) -> Vec<[f64; 9]> {
+        meshes
+            .iter()
+            .flat_map(|m| corners(m.points, m.triangles))
+            .collect()
+    }
+
+    fn to_rgb(color: [f32; 3]) -> [f32; 3] {
+        let [r, g, b, _] = to_rgba8(color, 1.0);
+        [r, g, b].map(|c| c as f32 / 255.0)
+    }
+
+    #[test]
+    fn readers_report_their_progress() {
+        let meshes = meshes();
+        for (name, bytes) in [
+            ("scene.stl", stl::write_stl(&meshes)),
+            ("scene.ply", ply::write_ply(&meshes)),
+            ("scene.glb", gltf::write_glb(&meshes)),
+            ("scene.obj", obj::write_obj(&meshes, Some("scene.mtl")).into_bytes()),
+        ] {
+            let reports = std::cell::RefCell::new(vec![]);
+            let report = |fraction| reports.borrow_mut().push(fraction);
+            read_file(name, &bytes, &HashMap::new(), &Progress::new(&report)).unwrap();
+            let reports = reports.into_inner();
+            assert!(reports.windows(2).all(|w| w[0] < w[1]), "{}", name);
+            assert_eq!(reports.last(), Some(&1.0), "{}", name);
+        }
+    }
+
+    #[test]
+    fn stl_round_trip() {
+        let meshes = meshes();
+        let bytes = stl::write_stl(&meshes);
+        let (points, triangles) = stl::read_stl(&bytes, &Progress::default()).unwrap();
+        assert_eq!(corners(&points, &triangles), all_corners(&meshes));
+
+        assert_eq!(&bytes[..6], b"COLOR=");
+        assert_eq!(bytes[6..10], to_rgba8(meshes[0].color, meshes[0].alpha));
+        let facet_colors = Vec::from_iter(bytes[84..].chunks(50).map(|facet| {
+            let attribute = u16::from_le_bytes([facet[48], facet[49]]);
+            [0, 5, 10].map(|shift| ((attribute >> shift) & 31) as u8)
+        }));
+        let expected = Vec::from_iter(meshes.iter().flat_map(|m| {
+            let [r, g, b, _] = to_rgba8(m.color, m.alpha);
+            std::iter::repeat_n([r >> 3, g >> 3, b >> 3], m.triangles.len() / 3)
+        }));
+        assert_eq!(facet_colors, expected);
+    }
+
+    #[test]
+    fn ply_round_trip() {
+        let meshes = meshes();
+        let bytes = ply::write_ply(&meshes);
+        let mesh = ply::read_ply(&bytes, &Progress::default()).unwrap();
+        assert_eq!(corners(&mesh.points, &mesh.triangles), all_corners(&meshes));
+
+        let expected = Vec::from_iter(
+            meshes
+                .iter()
+                .flat_map(|m| [to_rgb(m.color); 4][..m.points.len() / 3].concat()),
+        );
+        assert_eq!(mesh.attributes.colors, Some(expected));
+        // the alpha ends the 16 bytes of every vertex
+        let body = bytes.windows(11).position(|w| w == b"end_header\n").unwrap() + 11;
+        let alphas = Vec::from_iter(bytes[body..body + 7 * 16].chunks(16).map(|v| v[15]));
+        assert_eq!(alphas, [128, 128, 128, 128, 255, 255, 255]);
+    }
+
+    #[test]
+    fn glb_round_trip() {
+        let meshes = meshes();
+        let bytes = gltf::write_glb(&meshes);
+        let read = gltf::read_gltf(&bytes, &HashMap::new(), &Progress::default()).unwrap();
+        assert_eq!(read.len(), meshes.len());
+        for (read, mesh) in read.iter().zip(&meshes) {
+            assert_eq!(read.name.as_deref(), Some(mesh.name));
+            assert_eq!(read.parent, None);
+            assert_eq!(
+                corners(&read.points, &read.triangles),
+                corners(mesh.points, mesh.triangles)
+            );
+            let material = read.material.as_ref().unwrap();
+            assert_eq!(material.diffuse, mesh.color);
+            assert_eq!(material.alpha, mesh.alpha);
+        }
+    }
+
+    #[test]
+    fn obj_is_zipped_with_its_mtl_only_when_bundled() {
+        let meshes = meshes();
+        let single = Format::Obj.write("model", &meshes, false);
+        assert_eq!(single.name, "model.obj");
+        assert_eq!(single.mime_type, "model/obj");
+        let text = std::str::from_utf8(&single.bytes).unwrap();
+        assert!(!text.contains("mtllib") && !text.contains("usemtl"));
+        let read = read_file(&single.name, &single.bytes, &HashMap::new(), &Progress::default());
+        assert_eq!(read.unwrap().len(), meshes.len());
+
+        let scene = Format::Obj.write("scene", &meshes, true);
+        assert_eq!(scene.name, "scene.zip");
+        assert_eq!(scene.mime_type, "application/zip");
+        assert!(scene.bytes.starts_with(b"PK\x03\x04"));
+        let contains = |needle: &[u8]| scene.bytes.windows(needle.len()).any(|w| w == needle);
+        assert!(contains(b"scene.obj") && contains(b"scene.mtl"));
+        assert!(contains(b"mtllib scene.mtl"));
+    }
+
+    #[test]
+    fn obj_round_trip() {
+        let meshes = meshes();
+        let obj = obj::write_obj(&meshes, Some("scene.mtl"));
+        let resources = HashMap::from([("scene.mtl".to_owned(), obj::write_mtl(&meshes).into())]);
+        let read = obj::read_obj(obj.as_bytes(), &resources, &Progress::default()).unwrap();
+        assert_eq!(read.len(), meshes.len());
+        for (read, mesh) in read.iter().zip(&meshes) {
+            assert_eq!(read.name.as_deref(), Some(mesh.name));
+            assert_eq!(
+                corners(&read.points, &read.triangles),
+                corners(mesh.points, mesh.triangles)
+            );
+            let material = read.material.as_ref().unwrap();
+            assert_eq!(material.diffuse, mesh.color);
+            assert_eq!(material.alpha, mesh.alpha);
+        }
+    }
+}
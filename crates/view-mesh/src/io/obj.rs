@@ -0,0 +1,281 @@
+use std::{
+    collections::HashMap,
+    io::{BufRead, BufReader, Read},
+};
+
+use super::{ExportedMesh, ImportedMesh, Progress, ReadError, SurfaceMaterial, file_name};
+use crate::mesh::{Attributes, Polygons, triangulate::triangulate_polygons};
+
+/// Reads every object and group of an OBJ file as its own mesh, triangulating the polygonal
+/// faces. Material libraries are looked up by file name in `resources`, and the authored
+/// normals and texture coordinates are kept as vertex attributes.
+pub fn read_obj(
+    bytes: &[u8],
+    resources: &HashMap<String, Vec<u8>>,
+    progress: &Progress,
+) -> Result<Vec<ImportedMesh>, ReadError> {
+    let mut reader = ProgressReader {
+        bytes,
+        pos: 0,
+        progress,
+    };
+    let options = tobj::LoadOptions {
+        // positions, normals and texture coordinates are indexed separately, so that the
+        // points along the seams are not duplicated
+        single_index: false,
+        ignore_points: true,
+        ignore_lines: true,
+        ..Default::default()
+    };
+    let loaded = tobj::load_obj_buf(&mut reader, &options, |path| {
+        let name = file_name(&path.to_string_lossy()).to_owned();
+        match resources.get(&name) {
+            Some(mtl) => tobj::load_mtl_buf(&mut BufReader::new(mtl.as_slice())),
+            None => Ok((vec![], HashMap::new())),
+        }
+    });
+    let (models, materials) = loaded.map_err(|e| {
+        use tobj::LoadError as E;
+        let message = format!("invalid obj file: {}", e);
+        match e {
+            // tobj stops right after the line these errors are on, the other ones are only
+            // found once a whole object is read
+            E::PositionParseError
+            | E::TexcoordParseError
+            | E::NormalParseError
+            | E::FaceParseError
+            | E::MaterialParseError
+            | E::InvalidObjectName
+            | E::ReadError => ReadError::at_line(reader.line(), message),
+            _ => ReadError::from(message),
+        }
+    })?;
+    let materials = materials.unwrap_or_default();
+
+    let meshes = Vec::from_iter(
+        models
+            .into_iter()
+            .filter(|model| !model.mesh.indices.is_empty())
+            .map(|model| {
+                let mesh = model.mesh;
+                let indices = Vec::from_iter(mesh.indices.iter().map(|idx| *idx as usize));
+                // tobj leaves the arities empty when every face is a triangle
+                let (triangles, polygons) = if mesh.face_arities.iter().all(|&n| n == 3) {
+                    (indices, None)
+                } else {
+                    let polygons = Polygons {
+                        indices,
+                        arities: Vec::from_iter(mesh.face_arities.iter().map(|&n| n as usize)),
+                    };
+                    let triangles = triangulate_polygons(&mesh.positions, &polygons);
+                    (triangles, Some(polygons))
+                };
+                // the face corner each triangle corner comes from
+                let face_corners = match &polygons {
+                    None => Vec::from_iter(0..triangles.len()),
+                    Some(polygons) => face_corners(&triangles, polygons),
+                };
+                let per_corner = |values: &[f64], indices: &[u32], dim: usize| {
+                    (!indices.is_empty() && indices.len() == mesh.indices.len()).then(|| {
+                        Vec::from_iter(face_corners.iter().flat_map(|&corner| {
+                            let start = indices[corner] as usize * dim;
+                            values[start..start + dim].iter().map(|&v| v as f32)
+                        }))
+                    })
+                };
+                let normals = per_corner(&mesh.normals, &mesh.normal_indices, 3);
+                let uvs = per_corner(&mesh.texcoords, &mesh.texcoord_indices, 2);
+                let material = mesh
+                    .material_id
+                    .and_then(|id| materials.get(id))
+                    .map(surface_material);
+                ImportedMesh {
+                    name: Some(model.name),
+                    points: mesh.positions,
+                    triangles,
+                    polygons,
+                    attributes: Attributes {
+                        normals,
+                        uvs,
+                        ..Default::default()
+                    },
+                    material,
+                    parent: None,
+                }
+            }),
+    );
+    if meshes.is_empty() {
+        return Err("obj file contains no face".into());
+    }
+    Ok(meshes)
+}
+
+/// Reader over the content of a file reporting how far tobj went through it.
+struct ProgressReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    progress: &'a Progress<'a>,
+}
+
+impl ProgressReader<'_> {
+    /// Line of the last byte read, starting at 1.
+    fn line(&self) -> usize {
+        let read = &self.bytes[..self.pos];
+        let read = read.strip_suffix(b"\n").unwrap_or(read);
+        read.iter().filter(|&&b| b == b'\n').count() + 1
+    }
+}
+
+impl Read for ProgressReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = (&self.bytes[self.pos..]).read(buf)?;
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl BufRead for ProgressReader<'_> {
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        Ok(&self.bytes[self.pos..])
+    }
+
+    fn consume(&mut self, amount: usize) {
+        self.pos = (self.pos + amount).min(self.bytes.len());
+        self.progress.update(self.pos, self.bytes.len());
+    }
+}
+
+/// Index of the polygon corner every corner of `triangles` comes from, the triangles of
+/// each polygon being consecutive.
+fn face_corners(triangles: &[usize], polygons: &Polygons) -> Vec<usize> {
+    let mut corners = Vec::with_capacity(triangles.len());
+    let mut start = 0;
+    let mut tri_start = 0;
+    for &arity in &polygons.arities {
+        let polygon = &polygons.indices[start..start + arity];
+        let tri_end = tri_start + arity.saturating_sub(2) * 3;
+        for &idx in &triangles[tri_start..tri_end] {
+            let k = polygon.iter().position(|&p| p == idx).unwrap_or(0);
+            corners.push(start + k);
+        }
+        start += arity;
+        tri_start = tri_end;
+    }
+    corners
+}
+
+fn surface_material(material: &tobj::Material) -> SurfaceMaterial {
+    let rgb = |c: [f64; 3]| c.map(|v| v as f32);
+    SurfaceMaterial {
+        ambient: material.ambient.map(rgb),
+        diffuse: material.diffuse.map_or([0.8; 3], rgb),
+        specular: material.specular.map(rgb),
+        alpha: material.dissolve.map_or(1.0, |d| d as f32),
+        diffuse_texture: material
+            .diffuse_texture
+            .as_deref()
+            .map(|path| file_name(path).to_owned()),
+    }
+}
+
+/// Writes `meshes` as OBJ text, one `o` block per mesh using the material of the same
+/// index in the `mtl_name` library written by [`write_mtl`], or no material without one.
+/// Polygons are written when the meshes have some.
+pub fn write_obj(meshes: &[ExportedMesh], mtl_name: Option<&str>) -> String {
+    let mut txt = mtl_name.map_or_else(String::new, |name| format!("mtllib {}\n", name));
+    let mut offset = 1;
+    for (k, mesh) in meshes.iter().enumerate() {
+        txt.push_str(&format!("o {}\n", obj_name(mesh.name)));
+        for p in mesh.points.chunks(3) {
+            txt.push_str(&format!("v {} {} {}\n", p[0], p[1], p[2]));
+        }
+        if mtl_name.is_some() {
+            txt.push_str(&format!("usemtl {}\n", material_name(k)));
+        }
+        for face in mesh.faces() {
+            txt.push('f');
+            for idx in face {
+                txt.push_str(&format!(" {}", idx + offset));
+            }
+            txt.push('\n');
+        }
+        offset += mesh.points.len() / 3;
+    }
+    txt
+}
+
+/// Writes the material library of the OBJ file written by [`write_obj`].
+pub fn write_mtl(meshes: &[ExportedMesh]) -> String {
+    let mut txt = "".to_owned();
+    for (k, mesh) in meshes.iter().enumerate() {
+        let [r, g, b] = mesh.color;
+        txt.push_str(&format!(
+            "newmtl {}\nKd {} {} {}\nd {}\n\n",
+            material_name(k),
+            r,
+            g,
+            b,
+            mesh.alpha
+        ));
+    }
+    txt
+}
+
+fn material_name(idx: usize) -> String {
+    format!("material{}", idx)
+}
+
+/// OBJ names end at the first whitespace.
+fn obj_name(name: &str) -> String {
+    let name = name.split_whitespace().collect::<Vec<_>>().join("_");
+    if name.is_empty() {
+        "object".to_owned()
+    } else {
+        name
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_the_line_of_invalid_statements() {
+        let read = |obj: &str| read_obj(obj.as_bytes(), &HashMap::new(), &Progress::default());
+        let error = read("v 0 0 0\nv 1 0 0\nv 0 x 0\nf 1 2 3\n").unwrap_err();
+        assert_eq!(error.line, Some(3));
+        let error = read("v 0 0 0\nv 1 0 0\nv 0 1 0\n\nf 1 2 a").unwrap_err();
+        assert_eq!(error.line, Some(5));
+        // out of range indices are only checked at the end of the object
+        let error = read("v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 4\n").unwrap_err();
+        assert_eq!(error.line, None);
+    }
+
+    #[test]
+    fn keeps_points_shared_by_corners_with_different_normals() {
+        // two faces of a box meeting at a sharp edge, each with its own normal
+        let obj = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nv 0 1 1\n\
+                   vn 0 0 1\nvn 0 1 0\nvt 0 0\nvt 1 0\nvt 1 1\n\
+                   f 1/1/1 2/2/1 3/3/1 4/1/1\nf 4/1/2 3/2/2 5/3/2\n";
+        let meshes = read_obj(obj.as_bytes(), &HashMap::new(), &Progress::default()).unwrap();
+        let mesh = &meshes[0];
+        assert_eq!(mesh.points.len(), 5 * 3);
+        assert_eq!(mesh.triangles.len(), 3 * 3);
+
+        let normals = mesh.attributes.normals.as_ref().unwrap();
+        let uvs = mesh.attributes.uvs.as_ref().unwrap();
+        assert_eq!(normals.len(), mesh.triangles.len() * 3);
+        assert_eq!(uvs.len(), mesh.triangles.len() * 2);
+        let uv_of_point = [[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 0.0]];
+        for (corner, &idx) in mesh.triangles.iter().enumerate() {
+            let normal = &normals[corner * 3..corner * 3 + 3];
+            let uv = &uvs[corner * 2..corner * 2 + 2];
+            if corner < 6 {
+                assert_eq!(normal, [0.0, 0.0, 1.0]);
+                assert_eq!(uv, uv_of_point[idx]);
+            } else {
+                assert_eq!(normal, [0.0, 1.0, 0.0]);
+            }
+        }
+    }
+}
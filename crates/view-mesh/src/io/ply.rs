@@ -0,0 +1,531 @@
+use super::{ExportedMesh, ImportedMesh, Progress, ReadError, to_rgba8};
+use crate::mesh::{Attributes, Polygons, triangulate::triangulate_polygons};
+
+/// Names of the vertex properties holding the normals and the texture coordinates.
+const NORMAL_NAMES: [&str; 3] = ["nx", "ny", "nz"];
+const UV_NAMES: [&str; 6] = ["s", "t", "u", "v", "texture_u", "texture_v"];
+
+#[derive(Clone, Copy, PartialEq)]
+enum Encoding {
+    Ascii,
+    LittleEndian,
+    BigEndian,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Scalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl Scalar {
+    fn parse(name: &str) -> Result<Self, String> {
+        Ok(match name {
+            "char" | "int8" => Self::I8,
+            "uchar" | "uint8" => Self::U8,
+            "short" | "int16" => Self::I16,
+            "ushort" | "uint16" => Self::U16,
+            "int" | "int32" => Self::I32,
+            "uint" | "uint32" => Self::U32,
+            "float" | "float32" => Self::F32,
+            "double" | "float64" => Self::F64,
+            _ => return Err(format!("unknown ply property type {}", name)),
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Self::I8 | Self::U8 => 1,
+            Self::I16 | Self::U16 => 2,
+            Self::I32 | Self::U32 | Self::F32 => 4,
+            Self::F64 => 8,
+        }
+    }
+}
+
+enum Property {
+    Value(Scalar, String),
+    List(Scalar, Scalar, String),
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+/// Sequential reader over the body of a PLY file, whatever its encoding.
+struct Body<'a> {
+    encoding: Encoding,
+    bytes: &'a [u8],
+    pos: usize,
+    /// the body of an ASCII file, empty for a binary one
+    text: &'a str,
+    /// line of the file the body starts at
+    first_line: usize,
+    tokens: std::str::SplitAsciiWhitespace<'a>,
+}
+
+impl Body<'_> {
+    fn read(&mut self, ty: Scalar) -> Result<f64, ReadError> {
+        if self.encoding == Encoding::Ascii {
+            return match self.tokens.next() {
+                Some(token) => token.parse::<f64>().map_err(|_| {
+                    let offset = token.as_ptr() as usize - self.text.as_ptr() as usize;
+                    ReadError::at_line(self.line(offset), "invalid ply value")
+                }),
+                None => Err(ReadError::at_line(
+                    self.line(self.text.trim_end().len()),
+                    "unexpected end of ply data",
+                )),
+            };
+        }
+        let size = ty.size();
+        let raw = self
+            .bytes
+            .get(self.pos..self.pos + size)
+            .ok_or("unexpected end of ply data")?;
+        self.pos += size;
+        let mut buf = [0u8; 8];
+        buf[..size].copy_from_slice(raw);
+        if self.encoding == Encoding::BigEndian {
+            buf[..size].reverse();
+        }
+        Ok(match ty {
+            Scalar::I8 => buf[0] as i8 as f64,
+            Scalar::U8 => buf[0] as f64,
+            Scalar::I16 => i16::from_le_bytes([buf[0], buf[1]]) as f64,
+            Scalar::U16 => u16::from_le_bytes([buf[0], buf[1]]) as f64,
+            Scalar::I32 => i32::from_le_bytes(buf[..4].try_into().unwrap()) as f64,
+            Scalar::U32 => u32::from_le_bytes(buf[..4].try_into().unwrap()) as f64,
+            Scalar::F32 => f32::from_le_bytes(buf[..4].try_into().unwrap()) as f64,
+            Scalar::F64 => f64::from_le_bytes(buf),
+        })
+    }
+
+    /// Line of the file at `offset` in the text of the body.
+    fn line(&self, offset: usize) -> usize {
+        self.first_line + self.text[..offset].matches('\n').count()
+    }
+
+    fn read_list(&mut self, count_ty: Scalar, item_ty: Scalar) -> Result<Vec<f64>, ReadError> {
+        let n = self.read(count_ty)? as usize;
+        // the length is read from the file, nothing is reserved before the items are there
+        let fits = n
+            .checked_mul(item_ty.size())
+            .is_some_and(|size| size <= self.bytes.len() - self.pos);
+        if self.encoding != Encoding::Ascii && !fits {
+            return Err("unexpected end of ply data".into());
+        }
+        let mut list = vec![];
+        for _ in 0..n {
+            list.push(self.read(item_ty)?);
+        }
+        Ok(list)
+    }
+
+    fn skip(&mut self, property: &Property) -> Result<(), ReadError> {
+        match property {
+            Property::Value(ty, _) => self.read(*ty).map(|_| ()),
+            Property::List(count_ty, item_ty, _) => self.read_list(*count_ty, *item_ty).map(|_| ()),
+        }
+    }
+}
+
+/// Reads an ASCII or binary PLY file. Besides the positions and faces, the `red`, `green`
+/// and `blue` vertex properties become the vertex colors, `nx`, `ny` and `nz` the normals,
+/// `s` and `t` (or `u` and `v`) the texture coordinates, and every other numeric vertex
+/// property becomes a scalar field.
+pub fn read_ply(bytes: &[u8], progress: &Progress) -> Result<ImportedMesh, ReadError> {
+    const END_HEADER: &[u8] = b"end_header";
+    let header_end = bytes
+        .windows(END_HEADER.len())
+        .position(|w| w == END_HEADER)
+        .ok_or("missing ply header")?;
+    let body_start = bytes[header_end..]
+        .iter()
+        .position(|&b| b == b'\n')
+        .map_or(bytes.len(), |p| header_end + p + 1);
+    let header = std::str::from_utf8(&bytes[..header_end]).map_err(|_| "invalid ply header")?;
+
+    let mut lines = header.lines();
+    if lines.next().map(str::trim) != Some("ply") {
+        return Err("not a ply file".into());
+    }
+    let mut encoding = None;
+    let mut elements: Vec<Element> = vec![];
+    // after the `ply` line
+    for (line_no, line) in (2..).zip(lines) {
+        let at_line = |message| ReadError::at_line(line_no, message);
+        let scalar = |name| Scalar::parse(name).map_err(at_line);
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        match tokens.as_slice() {
+            ["format", format, ..] => {
+                encoding = Some(match *format {
+                    "ascii" => Encoding::Ascii,
+                    "binary_little_endian" => Encoding::LittleEndian,
+                    "binary_big_endian" => Encoding::BigEndian,
+                    _ => return Err(at_line(format!("unknown ply format {}", format))),
+                });
+            }
+            ["element", name, count] => elements.push(Element {
+                name: name.to_string(),
+                count: count
+                    .parse()
+                    .map_err(|_| at_line("invalid ply element count".to_owned()))?,
+                properties: vec![],
+            }),
+            ["property", "list", count_ty, item_ty, name] => elements
+                .last_mut()
+                .ok_or_else(|| at_line("ply property outside of an element".to_owned()))?
+                .properties
+                .push(Property::List(
+                    scalar(count_ty)?,
+                    scalar(item_ty)?,
+                    name.to_string(),
+                )),
+            ["property", ty, name] => elements
+                .last_mut()
+                .ok_or_else(|| at_line("ply property outside of an element".to_owned()))?
+                .properties
+                .push(Property::Value(scalar(ty)?, name.to_string())),
+            _ => {}
+        }
+    }
+    let encoding = encoding.ok_or("missing ply format")?;
+
+    // an element takes at least a byte per property in ASCII and the size of its values and
+    // list lengths in binary, which bounds the counts of the header by the size of the body
+    let mut remaining = bytes.len() - body_start;
+    for element in &elements {
+        let min_size: usize = element
+            .properties
+            .iter()
+            .map(|p| match (encoding, p) {
+                (Encoding::Ascii, _) => 1,
+                (_, Property::Value(ty, _)) => ty.size(),
+                (_, Property::List(count_ty, ..)) => count_ty.size(),
+            })
+            .sum();
+        let size = element
+            .count
+            .checked_mul(min_size.max(1))
+            .filter(|&size| size <= remaining)
+            .ok_or_else(|| {
+                format!("ply file too short for its {} {}", element.count, element.name)
+            })?;
+        remaining -= size;
+    }
+
+    let text = match encoding {
+        Encoding::Ascii => {
+            std::str::from_utf8(&bytes[body_start..]).map_err(|_| "invalid ply data")?
+        }
+        _ => "",
+    };
+    let mut body = Body {
+        encoding,
+        bytes: &bytes[body_start..],
+        pos: 0,
+        text,
+        first_line: 1 + bytes[..body_start].iter().filter(|&&b| b == b'\n').count(),
+        tokens: text.split_ascii_whitespace(),
+    };
+
+    // every vertex, face and other element read counts as much
+    let n_items: usize = elements.iter().map(|element| element.count).sum();
+    let mut items = 0;
+    let mut points = vec![];
+    let mut polygons = Polygons::default();
+    let mut colors: Option<Vec<f32>> = None;
+    // per vertex, spread over the corners once the faces are read
+    let mut normals: Option<Vec<f32>> = None;
+    let mut uvs: Option<Vec<f32>> = None;
+    let mut scalars: Vec<(String, Vec<f64>)> = vec![];
+    for element in &elements {
+        match element.name.as_str() {
+            "vertex" => {
+                let color_scale = element.properties.iter().find_map(|p| match p {
+                    Property::Value(ty, name) if name == "red" => {
+                        Some(if matches!(ty, Scalar::F32 | Scalar::F64) {
+                            1.0
+                        } else {
+                            255.0
+                        })
+                    }
+                    _ => None,
+                });
+                // the count is bounded by the size of the body by now
+                if color_scale.is_some() {
+                    colors = Some(Vec::with_capacity(element.count * 3));
+                }
+                let has = |names: &[&str]| {
+                    element.properties.iter().any(
+                        |p| matches!(p, Property::Value(_, name) if names.contains(&name.as_str())),
+                    )
+                };
+                if has(&NORMAL_NAMES) {
+                    normals = Some(Vec::with_capacity(element.count * 3));
+                }
+                if has(&UV_NAMES) {
+                    uvs = Some(Vec::with_capacity(element.count * 2));
+                }
+                for p in &element.properties {
+                    if let Property::Value(_, name) = p
+                        && !matches!(
+                            name.as_str(),
+                            "x" | "y" | "z" | "red" | "green" | "blue" | "alpha"
+                        )
+                        && !NORMAL_NAMES.contains(&name.as_str())
+                        && !UV_NAMES.contains(&name.as_str())
+                    {
+                        scalars.push((name.clone(), Vec::with_capacity(element.count)));
+                    }
+                }
+                for _ in 0..element.count {
+                    items += 1;
+                    progress.update(items, n_items);
+                    let mut position = [0.0; 3];
+                    let mut color = [0.0f32; 3];
+                    let mut normal = [0.0f32; 3];
+                    let mut uv = [0.0f32; 2];
+                    let mut scalar = 0;
+                    for p in &element.properties {
+                        match p {
+                            Property::Value(ty, name) => {
+                                let value = body.read(*ty)?;
+                                match name.as_str() {
+                                    "x" => position[0] = value,
+                                    "y" => position[1] = value,
+                                    "z" => position[2] = value,
+                                    "red" => color[0] = value as f32,
+                                    "green" => color[1] = value as f32,
+                                    "blue" => color[2] = value as f32,
+                                    "alpha" => {}
+                                    "nx" => normal[0] = value as f32,
+                                    "ny" => normal[1] = value as f32,
+                                    "nz" => normal[2] = value as f32,
+                                    "s" | "u" | "texture_u" => uv[0] = value as f32,
+                                    "t" | "v" | "texture_v" => uv[1] = value as f32,
+                                    _ => {
+                                        scalars[scalar].1.push(value);
+                                        scalar += 1;
+                                    }
+                                }
+                            }
+                            Property::List(..) => body.skip(p)?,
+                        }
+                    }
+                    points.extend_from_slice(&position);
+                    if let (Some(colors), Some(scale)) = (colors.as_mut(), color_scale) {
+                        colors.extend(color.map(|c| c / scale as f32));
+                    }
+                    if let Some(normals) = normals.as_mut() {
+                        normals.extend(normal);
+                    }
+                    if let Some(uvs) = uvs.as_mut() {
+                        uvs.extend(uv);
+                    }
+                }
+            }
+            "face" => {
+                for _ in 0..element.count {
+                    items += 1;
+                    progress.update(items, n_items);
+                    for p in &element.properties {
+                        match p {
+                            Property::List(count_ty, item_ty, name)
+                                if name == "vertex_indices" || name == "vertex_index" =>
+                            {
+                                let polygon = body.read_list(*count_ty, *item_ty)?;
+                                polygons.arities.push(polygon.len());
+                                polygons
+                                    .indices
+                                    .extend(polygon.into_iter().map(|idx| idx as usize));
+                            }
+                            _ => body.skip(p)?,
+                        }
+                    }
+                }
+            }
+            _ => {
+                for _ in 0..element.count {
+                    items += 1;
+                    progress.update(items, n_items);
+                    for p in &element.properties {
+                        body.skip(p)?;
+                    }
+                }
+            }
+        }
+    }
+
+    let n_points = points.len() / 3;
+    if polygons.indices.iter().any(|&idx| idx >= n_points) {
+        return Err("ply face refers to a missing vertex".into());
+    }
+    let triangles = triangulate_polygons(&points, &polygons);
+    let per_corner = |values: &[f32], n: usize| {
+        Vec::from_iter(
+            triangles
+                .iter()
+                .flat_map(|&idx| &values[idx * n..(idx + 1) * n])
+                .copied(),
+        )
+    };
+    let normals = normals.map(|normals| per_corner(&normals, 3));
+    let uvs = uvs.map(|uvs| per_corner(&uvs, 2));
+    let polygons = polygons.arities.iter().any(|&n| n != 3).then_some(polygons);
+    Ok(ImportedMesh {
+        name: None,
+        points,
+        triangles,
+        polygons,
+        attributes: Attributes {
+            colors,
+            normals,
+            uvs,
+            scalars,
+        },
+        material: None,
+        parent: None,
+    })
+}
+
+/// Writes `meshes` as a single binary little endian PLY file, with the color of each mesh
+/// on its vertices so that it reads back as vertex colors. Polygons are written when the
+/// meshes have some.
+pub fn write_ply(meshes: &[ExportedMesh]) -> Vec<u8> {
+    let n_points: usize = meshes.iter().map(|m| m.points.len() / 3).sum();
+    let faces = Vec::from_iter(meshes.iter().map(|mesh| {
+        let faces = mesh.faces();
+        // the face arity is written in a single byte
+        if faces.iter().all(|f| f.len() <= u8::MAX as usize) {
+            faces
+        } else {
+            Vec::from_iter(mesh.triangles.chunks_exact(3))
+        }
+    }));
+    let header = format!(
+        "ply\n\
+         format binary_little_endian 1.0\n\
+         element vertex {}\n\
+         property float x\n\
+         property float y\n\
+         property float z\n\
+         property uchar red\n\
+         property uchar green\n\
+         property uchar blue\n\
+         property uchar alpha\n\
+         element face {}\n\
+         property list uchar int vertex_indices\n\
+         end_header\n",
+        n_points,
+        faces.iter().map(Vec::len).sum::<usize>()
+    );
+    let mut bytes = header.into_bytes();
+    for mesh in meshes {
+        let color = to_rgba8(mesh.color, mesh.alpha);
+        for p in mesh.points.chunks_exact(3) {
+            for &v in p {
+                bytes.extend_from_slice(&(v as f32).to_le_bytes());
+            }
+            bytes.extend_from_slice(&color);
+        }
+    }
+    let mut offset = 0;
+    for (mesh, faces) in meshes.iter().zip(faces) {
+        for face in faces {
+            bytes.push(face.len() as u8);
+            for &idx in face {
+                bytes.extend_from_slice(&((idx + offset) as i32).to_le_bytes());
+            }
+        }
+        offset += mesh.points.len() / 3;
+    }
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_counts_larger_than_the_file() {
+        let header = "ply\nformat binary_little_endian 1.0\nelement vertex 4611686018427387904\n\
+                      property float x\nproperty float y\nproperty float z\nend_header\n";
+        assert!(read_ply(header.as_bytes(), &Progress::default()).is_err());
+        let header = "ply\nformat ascii 1.0\nelement vertex 1000\nproperty float x\n\
+                      property float y\nproperty float z\nend_header\n0 0 0\n";
+        assert!(read_ply(header.as_bytes(), &Progress::default()).is_err());
+    }
+
+    const QUAD: &str = "ply
+format ascii 1.0
+element vertex 4
+property float x
+property float y
+property float z
+property float nx
+property float ny
+property float nz
+property float s
+property float t
+property float quality
+element face 1
+property list uchar int vertex_indices
+end_header
+0 0 0 0 0 1 0 0 0.5
+1 0 0 0 0 1 1 0 0.5
+1 1 0 0 1 0 1 1 0.5
+0 1 0 0 1 0 0 1 0.5
+4 0 1 2 3
+";
+
+    #[test]
+    fn reads_normals_and_texture_coordinates_per_corner() {
+        let mesh = read_ply(QUAD.as_bytes(), &Progress::default()).unwrap();
+        let corners = mesh.triangles.len();
+        assert_eq!(corners, 6);
+        let normals = mesh.attributes.normals.unwrap();
+        let uvs = mesh.attributes.uvs.unwrap();
+        assert_eq!((normals.len(), uvs.len()), (corners * 3, corners * 2));
+        for (corner, &idx) in mesh.triangles.iter().enumerate() {
+            let normal = if idx < 2 { [0.0, 0.0, 1.0] } else { [0.0, 1.0, 0.0] };
+            assert_eq!(normals[corner * 3..corner * 3 + 3], normal);
+            let uv = [0, 1].map(|i| mesh.points[idx * 3 + i] as f32);
+            assert_eq!(uvs[corner * 2..corner * 2 + 2], uv);
+        }
+        // only the other properties are scalar fields
+        let names = Vec::from_iter(mesh.attributes.scalars.iter().map(|(name, _)| name.as_str()));
+        assert_eq!(names, ["quality"]);
+    }
+
+    #[test]
+    fn reports_the_line_of_ascii_errors() {
+        let bytes = QUAD.replace("1 1 0 0 1 0 1 1 0.5", "1 1 0 0 1 0 1 x 0.5");
+        let error = read_ply(bytes.as_bytes(), &Progress::default()).unwrap_err();
+        assert_eq!(error.line, Some(18));
+        let bytes = QUAD.replace("4 0 1 2 3", "4 0 1 2");
+        let error = read_ply(bytes.as_bytes(), &Progress::default()).unwrap_err();
+        assert_eq!(error.line, Some(20));
+        let bytes = QUAD.replace("property float quality", "property flaot quality");
+        let error = read_ply(bytes.as_bytes(), &Progress::default()).unwrap_err();
+        assert_eq!(error.line, Some(12));
+    }
+
+    #[test]
+    fn rejects_lists_longer_than_the_file() {
+        let mut bytes = b"ply\nformat binary_little_endian 1.0\nelement face 1\n\
+                          property list uint int vertex_indices\nend_header\n"
+            .to_vec();
+        bytes.extend_from_slice(&u32::MAX.to_le_bytes());
+        assert!(read_ply(&bytes, &Progress::default()).is_err());
+    }
+}
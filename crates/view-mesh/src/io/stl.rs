@@ -0,0 +1,184 @@
+use super::{ExportedMesh, Progress, ReadError, to_rgba8};
+use crate::mesh::{face_normal, weld};
+
+const HEADER_LEN: usize = 80;
+const FACET_LEN: usize = 50;
+
+/// Whether `bytes` is a binary STL file, i.e. its size matches the triangle count of the header.
+///
+/// Some exporters start binary files with `solid` too, so the size is the reliable criterion.
+/// Others write bytes after the facets, which is accepted when the header does not start
+/// with `solid`.
+pub fn is_binary(bytes: &[u8]) -> bool {
+    let Some(expected) = binary_len(bytes) else {
+        return false;
+    };
+    bytes.len() == expected || (bytes.len() > expected && !bytes.starts_with(b"solid"))
+}
+
+/// Size of the binary STL file whose header starts `bytes`, `None` if it has no header.
+fn binary_len(bytes: &[u8]) -> Option<usize> {
+    let count = bytes.get(HEADER_LEN..HEADER_LEN + 4)?;
+    let count = u32::from_le_bytes(count.try_into().unwrap()) as usize;
+    count.checked_mul(FACET_LEN)?.checked_add(HEADER_LEN + 4)
+}
+
+/// Reads a binary or ASCII STL file and welds the vertices shared by its triangles.
+pub fn read_stl(
+    bytes: &[u8],
+    progress: &Progress,
+) -> Result<(Vec<f64>, Vec<usize>), ReadError> {
+    let points = if is_binary(bytes) {
+        read_binary(bytes, progress)
+    } else {
+        read_ascii(bytes, progress)?
+    };
+    if points.is_empty() {
+        return Err("stl file contains no triangle".into());
+    }
+    let triangles = Vec::from_iter(0..points.len() / 3);
+    Ok(weld(&points, &triangles, 0.0))
+}
+
+fn read_binary(bytes: &[u8], progress: &Progress) -> Vec<f64> {
+    let mut points = Vec::new();
+    // the trailing bytes are not facets
+    let end = binary_len(bytes).unwrap();
+    let facets = bytes[HEADER_LEN + 4..end].chunks_exact(FACET_LEN);
+    let n_facets = facets.len();
+    for (i, facet) in facets.enumerate() {
+        progress.update(i + 1, n_facets);
+        // skip the normal, then three vertices, then the attribute byte count
+        for v in facet[12..48].chunks_exact(4) {
+            points.push(f32::from_le_bytes(v.try_into().unwrap()) as f64);
+        }
+    }
+    points
+}
+
+fn read_ascii(bytes: &[u8], progress: &Progress) -> Result<Vec<f64>, ReadError> {
+    let text = std::str::from_utf8(bytes).map_err(|_| "stl file is neither binary nor ascii")?;
+    let mut points = Vec::new();
+    let mut pos = 0;
+    for (line_no, line) in text.lines().enumerate() {
+        pos += line.len() + 1;
+        progress.update(pos, text.len());
+        let mut tokens = line.split_whitespace();
+        if tokens.next() != Some("vertex") {
+            continue;
+        }
+        for _ in 0..3 {
+            let value = tokens
+                .next()
+                .and_then(|t| t.parse::<f64>().ok())
+                .ok_or_else(|| ReadError::at_line(line_no + 1, "invalid stl vertex"))?;
+            points.push(value);
+        }
+    }
+    if points.len() % 9 != 0 {
+        return Err("stl facets must have three vertices".into());
+    }
+    Ok(points)
+}
+
+/// Writes `meshes` as a single binary STL file. The colors are stored the way Materialise
+/// tools expect them: the one of the first mesh in the header as `COLOR=` followed by the
+/// RGBA bytes, and the one of each facet in its attribute, 5 bits per channel.
+pub fn write_stl(meshes: &[ExportedMesh]) -> Vec<u8> {
+    let n_triangles: usize = meshes.iter().map(|m| m.triangles.len() / 3).sum();
+    let mut bytes = Vec::with_capacity(HEADER_LEN + 4 + n_triangles * FACET_LEN);
+    if let Some(first) = meshes.first() {
+        bytes.extend_from_slice(b"COLOR=");
+        bytes.extend(to_rgba8(first.color, first.alpha));
+    }
+    bytes.resize(HEADER_LEN, b' ');
+    bytes.extend_from_slice(&(n_triangles as u32).to_le_bytes());
+    for mesh in meshes {
+        let [r, g, b, _] = to_rgba8(mesh.color, mesh.alpha).map(|c| (c >> 3) as u16);
+        // the high bit is cleared for the facet color to be used
+        let attribute = r | (g << 5) | (b << 10);
+        for tri in mesh.triangles.chunks_exact(3) {
+            let normal = face_normal(mesh.points, tri);
+            let coords = normal.iter().chain(
+                tri.iter()
+                    .flat_map(|&idx| &mesh.points[idx * 3..idx * 3 + 3]),
+            );
+            for &v in coords {
+                bytes.extend_from_slice(&(v as f32).to_le_bytes());
+            }
+            bytes.extend_from_slice(&attribute.to_le_bytes());
+        }
+    }
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A binary file with `header` holding the facets of `triangles`, followed by `trailing`.
+    fn binary(header: &[u8], triangles: &[[[f32; 3]; 3]], trailing: &[u8]) -> Vec<u8> {
+        let mut bytes = header.to_vec();
+        bytes.resize(HEADER_LEN, b' ');
+        bytes.extend((triangles.len() as u32).to_le_bytes());
+        for tri in triangles {
+            bytes.extend([0u8; 12]);
+            bytes.extend(tri.iter().flatten().flat_map(|v| v.to_le_bytes()));
+            bytes.extend([0u8; 2]);
+        }
+        bytes.extend(trailing);
+        bytes
+    }
+
+    const TRIANGLES: [[[f32; 3]; 3]; 2] = [
+        [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]],
+        [[1.0, 0.0, 0.0], [1.0, 1.0, 0.0], [0.0, 1.0, 0.0]],
+    ];
+
+    #[test]
+    fn reads_an_ascii_file() {
+        let text = "solid square
+facet normal 0 0 1
+ outer loop
+  vertex 0 0 0
+  vertex 1 0 0
+  vertex 0 1 0
+ endloop
+endfacet
+facet normal 0 0 1
+ outer loop
+  vertex 1 0 0
+  vertex 1 1 0
+  vertex 0 1 0
+ endloop
+endfacet
+endsolid square
+";
+        let (points, triangles) = read_stl(text.as_bytes(), &Progress::default()).unwrap();
+        assert_eq!(points.len(), 4 * 3);
+        assert_eq!(triangles.len(), 2 * 3);
+
+        let text = text.replace("vertex 1 1 0", "vertex 1 x 0");
+        let error = read_stl(text.as_bytes(), &Progress::default()).unwrap_err();
+        assert_eq!(error.line, Some(12));
+    }
+
+    #[test]
+    fn reads_a_binary_file_whose_header_starts_with_solid() {
+        let bytes = binary(b"solid exported", &TRIANGLES, &[]);
+        assert!(is_binary(&bytes));
+        let (points, triangles) = read_stl(&bytes, &Progress::default()).unwrap();
+        assert_eq!(points.len(), 4 * 3);
+        assert_eq!(triangles.len(), 2 * 3);
+    }
+
+    #[test]
+    fn ignores_the_bytes_after_the_binary_facets() {
+        let bytes = binary(b"exported", &TRIANGLES, &[0; FACET_LEN + 3]);
+        assert!(is_binary(&bytes));
+        let (_, triangles) = read_stl(&bytes, &Progress::default()).unwrap();
+        assert_eq!(triangles.len(), 2 * 3);
+        // an ascii file can be as long, its header is the criterion then
+        assert!(!is_binary(&binary(b"solid exported", &TRIANGLES, &[0; 3])));
+    }
+}
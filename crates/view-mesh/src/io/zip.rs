@@ -0,0 +1,130 @@
+const LOCAL_HEADER: u32 = 0x04034B50;
+const CENTRAL_HEADER: u32 = 0x02014B50;
+const END_OF_CENTRAL_DIRECTORY: u32 = 0x06054B50;
+
+/// Version 1.0 of the format is enough for stored files.
+const VERSION: u16 = 10;
+/// The file names are UTF-8.
+const FLAG_UTF8: u16 = 1 << 11;
+/// 1980-01-01, the earliest date the format can hold, in MS-DOS format.
+const DOS_DATE: u16 = (1 << 5) | 1;
+
+/// Writes `files`, given as names and contents, into a zip archive without compression.
+///
+/// Sizes and offsets are written on 32 bits, which holds anything the 32 bit wasm memory
+/// can.
+pub fn write_zip(files: &[(&str, &[u8])]) -> Vec<u8> {
+    let mut bytes = vec![];
+    let mut central = vec![];
+    for &(name, data) in files {
+        let offset = bytes.len() as u32;
+        // the part of the header shared by the local and the central headers
+        let mut common = vec![];
+        for v in [VERSION, FLAG_UTF8, 0, 0, DOS_DATE] {
+            common.extend_from_slice(&v.to_le_bytes());
+        }
+        for v in [crc32(data), data.len() as u32, data.len() as u32] {
+            common.extend_from_slice(&v.to_le_bytes());
+        }
+        common.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        // no extra field
+        common.extend_from_slice(&0u16.to_le_bytes());
+
+        bytes.extend_from_slice(&LOCAL_HEADER.to_le_bytes());
+        bytes.extend_from_slice(&common);
+        bytes.extend_from_slice(name.as_bytes());
+        bytes.extend_from_slice(data);
+
+        central.extend_from_slice(&CENTRAL_HEADER.to_le_bytes());
+        // made by the same version
+        central.extend_from_slice(&VERSION.to_le_bytes());
+        central.extend_from_slice(&common);
+        // no comment, first disk, no attributes
+        central.extend_from_slice(&[0; 10]);
+        central.extend_from_slice(&offset.to_le_bytes());
+        central.extend_from_slice(name.as_bytes());
+    }
+    let central_offset = bytes.len() as u32;
+    bytes.extend_from_slice(&central);
+    bytes.extend_from_slice(&END_OF_CENTRAL_DIRECTORY.to_le_bytes());
+    // a single disk holding every entry
+    for v in [0, 0, files.len() as u16, files.len() as u16] {
+        bytes.extend_from_slice(&v.to_le_bytes());
+    }
+    bytes.extend_from_slice(&(central.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(&central_offset.to_le_bytes());
+    // no comment
+    bytes.extend_from_slice(&0u16.to_le_bytes());
+    bytes
+}
+
+/// CRC-32 of `data`, the checksum of the zip and PNG formats.
+fn crc32(data: &[u8]) -> u32 {
+    const TABLE: [u32; 256] = {
+        let mut table = [0; 256];
+        let mut n = 0;
+        while n < 256 {
+            let mut c = n as u32;
+            let mut k = 0;
+            while k < 8 {
+                c = if c & 1 != 0 {
+                    0xEDB88320 ^ (c >> 1)
+                } else {
+                    c >> 1
+                };
+                k += 1;
+            }
+            table[n] = c;
+            n += 1;
+        }
+        table
+    };
+    !data.iter().fold(!0, |crc, &b| {
+        TABLE[((crc ^ b as u32) & 0xFF) as usize] ^ (crc >> 8)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn u16_at(bytes: &[u8], pos: usize) -> u16 {
+        u16::from_le_bytes([bytes[pos], bytes[pos + 1]])
+    }
+
+    fn u32_at(bytes: &[u8], pos: usize) -> u32 {
+        u32::from_le_bytes(bytes[pos..pos + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn computes_the_standard_crc32() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xCBF43926);
+    }
+
+    #[test]
+    fn lists_the_stored_files_in_the_central_directory() {
+        let files: [(&str, &[u8]); 2] = [("scene.obj", b"o cube\n"), ("scene.mtl", b"")];
+        let bytes = write_zip(&files);
+
+        let end = bytes.len() - 22;
+        assert_eq!(u32_at(&bytes, end), END_OF_CENTRAL_DIRECTORY);
+        assert_eq!(u16_at(&bytes, end + 10), 2);
+        let mut pos = u32_at(&bytes, end + 16) as usize;
+        for (name, data) in files {
+            assert_eq!(u32_at(&bytes, pos), CENTRAL_HEADER);
+            assert_eq!(u16_at(&bytes, pos + 10), 0, "stored without compression");
+            assert_eq!(u32_at(&bytes, pos + 16), crc32(data));
+            assert_eq!(u32_at(&bytes, pos + 24), data.len() as u32);
+            let name_len = u16_at(&bytes, pos + 28) as usize;
+            assert_eq!(&bytes[pos + 46..pos + 46 + name_len], name.as_bytes());
+
+            let local = u32_at(&bytes, pos + 42) as usize;
+            assert_eq!(u32_at(&bytes, local), LOCAL_HEADER);
+            let start = local + 30 + name_len;
+            assert_eq!(&bytes[start..start + data.len()], data);
+            pos += 46 + name_len;
+        }
+        assert_eq!(pos, end);
+    }
+}
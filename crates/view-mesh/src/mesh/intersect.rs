@@ -0,0 +1,841 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use super::{Point, cross, dot, norm, point, sub, tri_normal};
+
+/// Points, triangles and the shell of every triangle.
+type Shells = (Vec<f64>, Vec<usize>, Vec<usize>);
+
+/// Resolves the intersections between triangles of different shells.
+///
+/// Every intersecting pair of triangles gets its intersection segment inserted as an edge of
+/// both, and coplanar pairs get the edges of each other inserted where they overlap. Points
+/// are shared between all the triangles they lie on, and a point on an edge is inserted in
+/// every triangle around that edge, so that the split mesh stays watertight. The split
+/// triangles are then grouped into patches bounded by the inserted edges, and the patches
+/// lying inside another shell are discarded, as well as the patches two shells share with
+/// opposite orientations and one copy of those they share with the same orientation, so that
+/// what remains is the outer surface of the union of the shells. Intersections inside a
+/// single shell are left untouched.
+pub(super) fn resolve(
+    points: Vec<f64>,
+    triangles: Vec<usize>,
+    shells: Vec<usize>,
+    eps: f64,
+) -> Result<Shells, String> {
+    let mut verts = Vertices::new(&points, eps);
+    let tris: Vec<[usize; 3]> = triangles.chunks(3).map(|t| [t[0], t[1], t[2]]).collect();
+    let normals: Vec<Point> = tris
+        .iter()
+        .map(|t| {
+            let n = tri_normal(&verts.pts[t[0]], &verts.pts[t[1]], &verts.pts[t[2]]);
+            let len = norm(&n);
+            n.map(|c| c / len)
+        })
+        .collect();
+
+    let mut splits = Splits::default();
+    for (t, u) in candidate_pairs(&verts.pts, &tris, &shells, eps) {
+        intersect_pair(&mut verts, &tris, &normals, t, u, eps, &mut splits);
+    }
+
+    let mut edge_tris: HashMap<(usize, usize), Vec<usize>> = HashMap::new();
+    for (i, t) in tris.iter().enumerate() {
+        for (a, b) in edges(t) {
+            edge_tris.entry(key(a, b)).or_default().push(i);
+        }
+    }
+
+    // sort the points out between the edges and the insides of the triangles, handing the
+    // points on an edge to the other triangles around it and the points of a triangle to the
+    // coplanar triangles they lie on, until every triangle knows all its points
+    let mut queue: Vec<(usize, usize)> = splits
+        .points
+        .iter()
+        .flat_map(|(&t, on)| on.iter().map(move |&p| (t, p)))
+        .collect();
+    queue.sort_unstable();
+    let mut known: HashSet<(usize, usize)> = queue.iter().copied().collect();
+    let mut edge_points: HashMap<(usize, usize), Vec<usize>> = HashMap::new();
+    let mut interior: HashMap<usize, Vec<usize>> = HashMap::new();
+    while let Some((t, p)) = queue.pop() {
+        let mut targets = vec![];
+        match locate(&verts.pts, &tris[t], p, eps) {
+            Location::Corner => continue,
+            Location::Edge(a, b) => {
+                let on_edge = edge_points.entry(key(a, b)).or_default();
+                if !on_edge.contains(&p) {
+                    on_edge.push(p);
+                }
+                targets.extend_from_slice(&edge_tris[&key(a, b)]);
+            }
+            Location::Interior => interior.entry(t).or_default().push(p),
+        }
+        for &u in splits.coplanar.get(&t).into_iter().flatten() {
+            if contains(&verts.pts, &tris[u], &normals[u], &verts.pts[p], eps) {
+                targets.push(u);
+            }
+        }
+        for u in targets {
+            if known.insert((u, p)) {
+                queue.push((u, p));
+            }
+        }
+    }
+
+    let mut faces = Vec::with_capacity(tris.len());
+    let mut face_origins = Vec::with_capacity(tris.len());
+    let mut constraints: HashSet<(usize, usize)> = HashSet::new();
+    for (i, t) in tris.iter().enumerate() {
+        let on_edges = edges(t).map(|(a, b)| edge_points.get(&key(a, b)).map(Vec::as_slice));
+        let inside = interior.get(&i).map(Vec::as_slice);
+        let segments = splits.segments.get(&i).map(Vec::as_slice);
+        if on_edges.iter().all(Option::is_none) && inside.is_none() && segments.is_none() {
+            faces.push(*t);
+            face_origins.push(i);
+            continue;
+        }
+        let mut cdt = Triangulation::new(&verts.pts, t, &normals[i]);
+        for (k, on_edge) in on_edges.iter().enumerate() {
+            cdt.split_side(&verts.pts, k, on_edge.unwrap_or(&[]));
+        }
+        for &p in inside.unwrap_or(&[]) {
+            cdt.insert(&verts.pts, p)?;
+        }
+        for &(a, b) in segments.unwrap_or(&[]) {
+            cdt.insert_segment(&mut verts, a, b, eps)?;
+        }
+        for f in &cdt.faces {
+            faces.push(f.map(|v| cdt.ids[v]));
+            face_origins.push(i);
+        }
+        constraints.extend(cdt.constrained.iter().map(|&(a, b)| key(cdt.ids[a], cdt.ids[b])));
+    }
+
+    let surfaces = Surfaces {
+        tris: &tris,
+        normals: &normals,
+        shells: &shells,
+        coplanar: &splits.coplanar,
+    };
+    let keep = classify(&verts.pts, &faces, &face_origins, &constraints, &surfaces, eps);
+    let mut triangles = Vec::with_capacity(faces.len() * 3);
+    let mut kept_shells = Vec::with_capacity(faces.len());
+    for ((f, origin), keep) in faces.iter().zip(face_origins).zip(keep) {
+        if keep {
+            triangles.extend_from_slice(f);
+            kept_shells.push(shells[origin]);
+        }
+    }
+    let points = verts.pts.into_iter().flatten().collect();
+    Ok((points, triangles, kept_shells))
+}
+
+#[inline]
+fn edges(t: &[usize; 3]) -> [(usize, usize); 3] {
+    [(t[0], t[1]), (t[1], t[2]), (t[2], t[0])]
+}
+
+/// Undirected edge between `a` and `b`.
+#[inline]
+fn key(a: usize, b: usize) -> (usize, usize) {
+    (a.min(b), a.max(b))
+}
+
+/// Point at `s` along the segment between the points `a` and `b`, always measured from the
+/// one of lowest index so that the same point comes out whatever the direction asked for.
+fn lerp(pts: &[Point], a: usize, b: usize, s: f64) -> Point {
+    let (from, to, s) = if a < b { (a, b, s) } else { (b, a, 1.0 - s) };
+    let (p, q) = (pts[from], pts[to]);
+    [0, 1, 2].map(|i| p[i] + (q[i] - p[i]) * s)
+}
+
+/// Points of the split mesh, a point closer than `eps` to an existing one being merged into
+/// it so that the triangles computing the same intersection agree on its index.
+struct Vertices {
+    pts: Vec<Point>,
+    grid: HashMap<[i64; 3], Vec<usize>>,
+    eps: f64,
+}
+
+impl Vertices {
+    fn new(points: &[f64], eps: f64) -> Self {
+        let mut verts = Self {
+            pts: Vec::with_capacity(points.len() / 3),
+            grid: HashMap::new(),
+            eps: eps.max(f64::MIN_POSITIVE),
+        };
+        for i in 0..points.len() / 3 {
+            let p = point(points, i);
+            let cell = verts.cell(&p);
+            verts.grid.entry(cell).or_default().push(i);
+            verts.pts.push(p);
+        }
+        verts
+    }
+
+    fn cell(&self, p: &Point) -> [i64; 3] {
+        p.map(|c| (c / self.eps).floor() as i64)
+    }
+
+    /// Index of the point `p`, appending it unless an existing point is close enough.
+    fn add(&mut self, p: Point) -> usize {
+        let cell = self.cell(&p);
+        for dx in -1..=1 {
+            for dy in -1..=1 {
+                for dz in -1..=1 {
+                    let near = [cell[0] + dx, cell[1] + dy, cell[2] + dz];
+                    for &idx in self.grid.get(&near).into_iter().flatten() {
+                        let d = sub(&p, &self.pts[idx]);
+                        if dot(&d, &d) <= self.eps * self.eps {
+                            return idx;
+                        }
+                    }
+                }
+            }
+        }
+        self.grid.entry(cell).or_default().push(self.pts.len());
+        self.pts.push(p);
+        self.pts.len() - 1
+    }
+}
+
+/// What has to be inserted in the triangles to resolve the intersections.
+#[derive(Default)]
+struct Splits {
+    /// points lying on each triangle, corners and edges included
+    points: HashMap<usize, HashSet<usize>>,
+    /// segments to insert as edges of each triangle
+    segments: HashMap<usize, Vec<(usize, usize)>>,
+    /// triangles of other shells lying in the plane of each triangle
+    coplanar: HashMap<usize, Vec<usize>>,
+}
+
+impl Splits {
+    fn add_segment(&mut self, tri: usize, a: usize, b: usize) {
+        self.segments.entry(tri).or_default().push((a, b));
+        self.add_points(tri, [a, b]);
+    }
+
+    fn add_points(&mut self, tri: usize, points: [usize; 2]) {
+        self.points.entry(tri).or_default().extend(points);
+    }
+}
+
+/// Pairs of triangles from different shells whose bounding boxes overlap, found by sweeping
+/// the boxes along the x axis.
+fn candidate_pairs(
+    pts: &[Point],
+    tris: &[[usize; 3]],
+    shells: &[usize],
+    eps: f64,
+) -> Vec<(usize, usize)> {
+    let boxes: Vec<(Point, Point)> = tris
+        .iter()
+        .map(|t| {
+            let mut min = [f64::MAX; 3];
+            let mut max = [f64::MIN; 3];
+            for &v in t {
+                for i in 0..3 {
+                    min[i] = min[i].min(pts[v][i] - eps);
+                    max[i] = max[i].max(pts[v][i] + eps);
+                }
+            }
+            (min, max)
+        })
+        .collect();
+    let mut order: Vec<usize> = (0..tris.len()).collect();
+    order.sort_unstable_by(|&a, &b| boxes[a].0[0].total_cmp(&boxes[b].0[0]));
+
+    let mut pairs = vec![];
+    let mut active: Vec<usize> = vec![];
+    for &t in &order {
+        let (min, max) = &boxes[t];
+        active.retain(|&u| boxes[u].1[0] >= min[0]);
+        for &u in &active {
+            let (umin, umax) = &boxes[u];
+            if shells[u] != shells[t]
+                && umin[1] <= max[1]
+                && min[1] <= umax[1]
+                && umin[2] <= max[2]
+                && min[2] <= umax[2]
+            {
+                pairs.push((u, t));
+            }
+        }
+        active.push(t);
+    }
+    pairs
+}
+
+/// Records what the triangles `t` and `u` have to be split with where they meet.
+fn intersect_pair(
+    verts: &mut Vertices,
+    tris: &[[usize; 3]],
+    normals: &[Point],
+    t: usize,
+    u: usize,
+    eps: f64,
+    splits: &mut Splits,
+) {
+    // signed distances of the vertices of `of` to the plane of `on`, snapped to zero
+    let distances = |on: usize, of: usize| {
+        let origin = verts.pts[tris[on][0]];
+        tris[of].map(|v| {
+            let d = dot(&normals[on], &sub(&verts.pts[v], &origin));
+            if d.abs() <= eps { 0.0 } else { d }
+        })
+    };
+    let separated = |d: &[f64; 3]| d.iter().all(|&d| d > 0.0) || d.iter().all(|&d| d < 0.0);
+    let (du, dt) = (distances(t, u), distances(u, t));
+    if separated(&du) || separated(&dt) {
+        return;
+    }
+    if du.iter().all(|&d| d == 0.0) || dt.iter().all(|&d| d == 0.0) {
+        intersect_coplanar(verts, tris, normals, t, u, eps, splits);
+        return;
+    }
+
+    let line = cross(&normals[t], &normals[u]);
+    let len = norm(&line);
+    if len <= f64::EPSILON {
+        return;
+    }
+    let line = line.map(|c| c / len);
+    let (Some(su), Some(st)) = (
+        section(&verts.pts, &tris[u], &du, &line),
+        section(&verts.pts, &tris[t], &dt, &line),
+    ) else {
+        return;
+    };
+    // both sections lie on the line where the planes meet, the triangles share their overlap
+    let lo = if su.0.0 >= st.0.0 { su.0 } else { st.0 };
+    let hi = if su.1.0 <= st.1.0 { su.1 } else { st.1 };
+    if hi.0 - lo.0 <= eps {
+        return;
+    }
+    let (a, b) = (verts.add(lo.1), verts.add(hi.1));
+    if a != b {
+        splits.add_segment(t, a, b);
+        splits.add_segment(u, a, b);
+    }
+}
+
+/// Part of the triangle `tri` lying in a plane, `d` being the signed distances of its
+/// vertices to the plane, given by its two extreme points along `line` with their coordinate
+/// along it.
+#[allow(clippy::type_complexity)]
+fn section(
+    pts: &[Point],
+    tri: &[usize; 3],
+    d: &[f64; 3],
+    line: &Point,
+) -> Option<((f64, Point), (f64, Point))> {
+    let mut found = vec![];
+    for k in 0..3 {
+        let (a, b) = (tri[k], tri[(k + 1) % 3]);
+        let (da, db) = (d[k], d[(k + 1) % 3]);
+        if da == 0.0 {
+            found.push(pts[a]);
+        }
+        if da * db < 0.0 {
+            found.push(lerp(pts, a, b, da / (da - db)));
+        }
+    }
+    let along = |p: &Point| (dot(p, line), *p);
+    let lo = found.iter().map(along).min_by(|p, q| p.0.total_cmp(&q.0))?;
+    let hi = found.iter().map(along).max_by(|p, q| p.0.total_cmp(&q.0))?;
+    Some((lo, hi))
+}
+
+/// Records the edges of each of the coplanar triangles `t` and `u` that cross the other one.
+fn intersect_coplanar(
+    verts: &mut Vertices,
+    tris: &[[usize; 3]],
+    normals: &[Point],
+    t: usize,
+    u: usize,
+    eps: f64,
+    splits: &mut Splits,
+) {
+    let (i, j) = projection_axes(&normals[t]);
+    let to_2d = |p: &Point| [p[i], p[j]];
+    for (on, other) in [(t, u), (u, t)] {
+        let corners = tris[on].map(|v| to_2d(&verts.pts[v]));
+        for (a, b) in edges(&tris[other]) {
+            let (pa, pb) = (verts.pts[a], verts.pts[b]);
+            let Some((s0, s1)) = clip(&corners, &to_2d(&pa), &to_2d(&pb), eps) else {
+                continue;
+            };
+            if (s1 - s0) * norm(&sub(&pb, &pa)) <= eps {
+                continue;
+            }
+            let mut at = |s: f64| {
+                if s <= 0.0 {
+                    a
+                } else if s >= 1.0 {
+                    b
+                } else {
+                    verts.add(lerp(&verts.pts, a, b, s))
+                }
+            };
+            let (p, q) = (at(s0), at(s1));
+            if p != q {
+                splits.add_segment(on, p, q);
+                splits.add_points(other, [p, q]);
+            }
+        }
+    }
+    splits.coplanar.entry(t).or_default().push(u);
+    splits.coplanar.entry(u).or_default().push(t);
+}
+
+/// Parameters along `p -> q` of the part of that segment inside the triangle `corners`, the
+/// ends closer than `eps` to a side counting as being on it.
+fn clip(corners: &[Point2; 3], p: &Point2, q: &Point2, eps: f64) -> Option<(f64, f64)> {
+    let sign = orient2d(&corners[0], &corners[1], &corners[2]).signum();
+    let (mut lo, mut hi) = (0.0f64, 1.0f64);
+    for k in 0..3 {
+        let (a, b) = (&corners[k], &corners[(k + 1) % 3]);
+        let tol = eps * ((b[0] - a[0]).powi(2) + (b[1] - a[1]).powi(2)).sqrt();
+        let fp = sign * orient2d(a, b, p);
+        let fq = sign * orient2d(a, b, q);
+        match (fp < -tol, fq < -tol) {
+            (true, true) => return None,
+            (true, false) => lo = lo.max(fp / (fp - fq)),
+            (false, true) => hi = hi.min(fp / (fp - fq)),
+            (false, false) => {}
+        }
+    }
+    (lo < hi).then_some((lo, hi))
+}
+
+/// Where a point lies on a triangle.
+enum Location {
+    Corner,
+    /// on the edge between these two corners
+    Edge(usize, usize),
+    Interior,
+}
+
+fn locate(pts: &[Point], tri: &[usize; 3], p: usize, eps: f64) -> Location {
+    if tri.contains(&p) {
+        return Location::Corner;
+    }
+    for (a, b) in edges(tri) {
+        let ab = sub(&pts[b], &pts[a]);
+        let ap = sub(&pts[p], &pts[a]);
+        let s = dot(&ap, &ab) / dot(&ab, &ab);
+        if s > 0.0 && s < 1.0 && norm(&cross(&ab, &ap)) <= eps * norm(&ab) {
+            return Location::Edge(a, b);
+        }
+    }
+    Location::Interior
+}
+
+/// Whether `p`, lying in the plane of the triangle `tri` of normal `n`, is inside it or
+/// closer than `eps` to it.
+fn contains(pts: &[Point], tri: &[usize; 3], n: &Point, p: &Point, eps: f64) -> bool {
+    edges(tri).iter().all(|&(a, b)| {
+        let ab = sub(&pts[b], &pts[a]);
+        dot(n, &cross(&ab, &sub(p, &pts[a]))) >= -eps * norm(&ab)
+    })
+}
+
+type Point2 = [f64; 2];
+
+#[inline]
+fn orient2d(a: &Point2, b: &Point2, c: &Point2) -> f64 {
+    (b[0] - a[0]) * (c[1] - a[1]) - (b[1] - a[1]) * (c[0] - a[0])
+}
+
+/// Coordinates to project onto the plane best aligned with a triangle of normal `n`, keeping
+/// it counter-clockwise.
+fn projection_axes(n: &Point) -> (usize, usize) {
+    let axis = (0..3)
+        .max_by(|&a, &b| n[a].abs().total_cmp(&n[b].abs()))
+        .unwrap();
+    let (i, j) = ((axis + 1) % 3, (axis + 2) % 3);
+    if n[axis] < 0.0 { (j, i) } else { (i, j) }
+}
+
+/// Constrained triangulation of a triangle of the mesh with points inserted, in the plane
+/// it is projected onto. Its faces refer to local vertex indices.
+struct Triangulation {
+    axes: (usize, usize),
+    /// index in the mesh of every vertex
+    ids: Vec<usize>,
+    local: Vec<Point2>,
+    faces: Vec<[usize; 3]>,
+    constrained: HashSet<(usize, usize)>,
+}
+
+impl Triangulation {
+    fn new(pts: &[Point], tri: &[usize; 3], n: &Point) -> Self {
+        let mut cdt = Self {
+            axes: projection_axes(n),
+            ids: vec![],
+            local: vec![],
+            faces: vec![[0, 1, 2]],
+            constrained: HashSet::new(),
+        };
+        for &v in tri {
+            cdt.push(pts, v);
+        }
+        cdt
+    }
+
+    fn push(&mut self, pts: &[Point], id: usize) -> usize {
+        let (i, j) = self.axes;
+        self.ids.push(id);
+        self.local.push([pts[id][i], pts[id][j]]);
+        self.ids.len() - 1
+    }
+
+    fn index_of(&self, id: usize) -> Option<usize> {
+        self.ids.iter().position(|&v| v == id)
+    }
+
+    /// Face holding the directed edge `p -> q`, with its vertex opposite to that edge.
+    fn face_with(&self, p: usize, q: usize) -> Option<(usize, usize)> {
+        self.faces.iter().enumerate().find_map(|(f, face)| {
+            (0..3)
+                .find(|&k| face[k] == p && face[(k + 1) % 3] == q)
+                .map(|k| (f, face[(k + 2) % 3]))
+        })
+    }
+
+    /// Splits the faces on both sides of the edge `p - q` at its point `v`.
+    fn split_edge(&mut self, p: usize, q: usize, v: usize) {
+        for (s, e) in [(p, q), (q, p)] {
+            if let Some((f, o)) = self.face_with(s, e) {
+                self.faces[f] = [s, v, o];
+                self.faces.push([v, e, o]);
+            }
+        }
+        if self.constrained.remove(&key(p, q)) {
+            self.constrained.insert(key(p, v));
+            self.constrained.insert(key(v, q));
+        }
+    }
+
+    /// Inserts the points `on_side` of the side starting at the `k`th corner, in any order.
+    fn split_side(&mut self, pts: &[Point], k: usize, on_side: &[usize]) {
+        let (a, b) = (self.ids[k], self.ids[(k + 1) % 3]);
+        let ab = sub(&pts[b], &pts[a]);
+        let mut sorted = Vec::from_iter(
+            on_side
+                .iter()
+                .map(|&p| (dot(&sub(&pts[p], &pts[a]), &ab), p)),
+        );
+        sorted.sort_unstable_by(|x, y| x.0.total_cmp(&y.0));
+        let mut prev = k;
+        for (_, p) in sorted {
+            if self.index_of(p).is_some() {
+                continue;
+            }
+            let v = self.push(pts, p);
+            self.split_edge(prev, (k + 1) % 3, v);
+            prev = v;
+        }
+    }
+
+    /// Inserts the point `id` inside the triangle, splitting the face it falls in or the
+    /// inner edge it lies on.
+    fn insert(&mut self, pts: &[Point], id: usize) -> Result<(), String> {
+        const TOLERANCE: f64 = 1e-12;
+        if self.index_of(id).is_some() {
+            return Ok(());
+        }
+        let v = self.push(pts, id);
+        let p = self.local[v];
+        // the face where the point is the deepest inside
+        let (f, lambda) = self.faces[..]
+            .iter()
+            .enumerate()
+            .map(|(f, face)| {
+                let [a, b, c] = face.map(|k| self.local[k]);
+                let area = orient2d(&a, &b, &c);
+                let lambda = [
+                    orient2d(&b, &c, &p) / area,
+                    orient2d(&c, &a, &p) / area,
+                    orient2d(&a, &b, &p) / area,
+                ];
+                (f, lambda)
+            })
+            .max_by(|x, y| min3(&x.1).total_cmp(&min3(&y.1)))
+            .ok_or("empty triangulation")?;
+        if min3(&lambda) < -1e-6 {
+            return Err("intersection point outside of its triangle".to_owned());
+        }
+        let face = self.faces[f];
+        let k = (0..3).min_by(|&x, &y| lambda[x].total_cmp(&lambda[y])).unwrap();
+        let (s, e) = (face[(k + 1) % 3], face[(k + 2) % 3]);
+        if lambda[k] < TOLERANCE && self.face_with(e, s).is_some() {
+            self.split_edge(s, e, v);
+        } else {
+            let [a, b, c] = face;
+            self.faces[f] = [a, b, v];
+            self.faces.push([b, c, v]);
+            self.faces.push([c, a, v]);
+        }
+        Ok(())
+    }
+
+    /// Makes the segment between the points `a` and `b` a chain of constrained edges. The
+    /// segment is split at the vertices lying on it and at the constrained edges it crosses,
+    /// and the other edges it crosses are flipped away.
+    fn insert_segment(
+        &mut self,
+        verts: &mut Vertices,
+        a: usize,
+        b: usize,
+        eps: f64,
+    ) -> Result<(), String> {
+        let (Some(a), Some(b)) = (self.index_of(a), self.index_of(b)) else {
+            return Err("intersection segment without its end points".to_owned());
+        };
+        let mut budget = 16 * (self.faces.len() + 16);
+        let mut stack = vec![(a, b)];
+        while let Some((a, b)) = stack.pop() {
+            budget = budget
+                .checked_sub(1)
+                .ok_or("failed to insert an intersection segment")?;
+            if a == b {
+                continue;
+            }
+            if self.face_with(a, b).is_some() || self.face_with(b, a).is_some() {
+                self.constrained.insert(key(a, b));
+                continue;
+            }
+            if let Some(v) = self.vertex_on(a, b, eps) {
+                stack.push((a, v));
+                stack.push((v, b));
+                continue;
+            }
+            let crossing = self.crossing_edges(a, b);
+            if let Some(&(p, q)) = crossing
+                .iter()
+                .find(|&&(p, q)| self.constrained.contains(&key(p, q)))
+            {
+                // two intersection curves cross, e.g. where three shells meet
+                let (la, lb) = (self.local[a], self.local[b]);
+                let (sp, sq) = (
+                    orient2d(&la, &lb, &self.local[p]),
+                    orient2d(&la, &lb, &self.local[q]),
+                );
+                let at = lerp(&verts.pts, self.ids[p], self.ids[q], sp / (sp - sq));
+                let id = verts.add(at);
+                let v = match self.index_of(id) {
+                    Some(v) => v,
+                    None => {
+                        let v = self.push(&verts.pts, id);
+                        self.split_edge(p, q, v);
+                        v
+                    }
+                };
+                stack.push((a, v));
+                stack.push((v, b));
+                continue;
+            }
+            self.flip_away(a, b, crossing, eps)?;
+            self.constrained.insert(key(a, b));
+        }
+        Ok(())
+    }
+
+    /// Vertex lying on the segment `a - b` other than its ends, the closest to `a`.
+    fn vertex_on(&self, a: usize, b: usize, eps: f64) -> Option<usize> {
+        let (la, lb) = (self.local[a], self.local[b]);
+        let ab = [lb[0] - la[0], lb[1] - la[1]];
+        let len2 = ab[0] * ab[0] + ab[1] * ab[1];
+        (0..self.local.len())
+            .filter(|&v| v != a && v != b)
+            .filter_map(|v| {
+                let p = self.local[v];
+                let s = ((p[0] - la[0]) * ab[0] + (p[1] - la[1]) * ab[1]) / len2;
+                let off = orient2d(&la, &lb, &p).abs() / len2.sqrt();
+                (s > 0.0 && s < 1.0 && off <= eps).then_some((s, v))
+            })
+            .min_by(|x, y| x.0.total_cmp(&y.0))
+            .map(|(_, v)| v)
+    }
+
+    /// Inner edges crossing the segment `a - b` in their interiors, the segment lying inside
+    /// the triangle.
+    fn crossing_edges(&self, a: usize, b: usize) -> Vec<(usize, usize)> {
+        let mut found = vec![];
+        for face in &self.faces {
+            for (p, q) in edges(face) {
+                if p < q && self.crosses(a, b, p, q) {
+                    found.push((p, q));
+                }
+            }
+        }
+        found
+    }
+
+    fn crosses(&self, a: usize, b: usize, p: usize, q: usize) -> bool {
+        if p == a || p == b || q == a || q == b {
+            return false;
+        }
+        let [la, lb, lp, lq] = [a, b, p, q].map(|v| self.local[v]);
+        orient2d(&la, &lb, &lp) * orient2d(&la, &lb, &lq) < 0.0
+            && orient2d(&lp, &lq, &la) * orient2d(&lp, &lq, &lb) < 0.0
+    }
+
+    /// Flips the unconstrained edges `crossing` the segment `a - b` until none is left
+    /// (Sloan's algorithm).
+    fn flip_away(
+        &mut self,
+        a: usize,
+        b: usize,
+        crossing: Vec<(usize, usize)>,
+        eps: f64,
+    ) -> Result<(), String> {
+        let mut queue = VecDeque::from(crossing);
+        let mut stalled = 0;
+        while let Some((p, q)) = queue.pop_front() {
+            let (Some((f, r)), Some((g, s))) = (self.face_with(p, q), self.face_with(q, p)) else {
+                return Err("intersection segment leaving its triangle".to_owned());
+            };
+            let [lp, lq, lr, ls] = [p, q, r, s].map(|v| self.local[v]);
+            // the quad p, s, q, r is convex only if r - s crosses p - q, far enough from p and
+            // q for the flipped faces not to be flat
+            let tol = eps * ((ls[0] - lr[0]).powi(2) + (ls[1] - lr[1]).powi(2)).sqrt();
+            let (sp, sq) = (orient2d(&lr, &ls, &lp), orient2d(&lr, &ls, &lq));
+            if sp.min(sq) >= -tol || sp.max(sq) <= tol {
+                stalled += 1;
+                if stalled > queue.len() {
+                    return Err("failed to insert an intersection segment".to_owned());
+                }
+                queue.push_back((p, q));
+                continue;
+            }
+            stalled = 0;
+            self.faces[f] = [p, s, r];
+            self.faces[g] = [s, q, r];
+            if self.crosses(a, b, r, s) {
+                queue.push_back((r, s));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[inline]
+fn min3(v: &[f64; 3]) -> f64 {
+    v[0].min(v[1]).min(v[2])
+}
+
+/// The triangles of the shells before splitting, to tell on which side of them a point is.
+struct Surfaces<'a> {
+    tris: &'a [[usize; 3]],
+    normals: &'a [Point],
+    shells: &'a [usize],
+    coplanar: &'a HashMap<usize, Vec<usize>>,
+}
+
+/// Marks the faces to keep: faces are grouped into patches that do not cross the
+/// constrained edges, and every patch is classified against the other shells from one of
+/// its faces, `origins` giving the triangle each face was split from.
+fn classify(
+    pts: &[Point],
+    faces: &[[usize; 3]],
+    origins: &[usize],
+    constraints: &HashSet<(usize, usize)>,
+    surfaces: &Surfaces,
+    eps: f64,
+) -> Vec<bool> {
+    let shell_of = |f: usize| surfaces.shells[origins[f]];
+    let mut parent: Vec<usize> = (0..faces.len()).collect();
+    fn find(parent: &mut [usize], mut x: usize) -> usize {
+        while parent[x] != x {
+            parent[x] = parent[parent[x]];
+            x = parent[x];
+        }
+        x
+    }
+
+    let mut edge_faces: HashMap<(usize, usize), Vec<usize>> = HashMap::new();
+    for (i, f) in faces.iter().enumerate() {
+        for (a, b) in edges(f) {
+            edge_faces.entry(key(a, b)).or_default().push(i);
+        }
+    }
+    for (edge, around) in &edge_faces {
+        if constraints.contains(edge) {
+            continue;
+        }
+        for (k, &f) in around.iter().enumerate() {
+            for &g in &around[k + 1..] {
+                if shell_of(g) == shell_of(f) {
+                    let (x, y) = (find(&mut parent, f), find(&mut parent, g));
+                    parent[x] = y;
+                }
+            }
+        }
+    }
+
+    let area = |f: &[usize; 3]| norm(&tri_normal(&pts[f[0]], &pts[f[1]], &pts[f[2]]));
+    let mut representative: HashMap<usize, usize> = HashMap::new();
+    for i in 0..faces.len() {
+        let root = find(&mut parent, i);
+        let best = representative.entry(root).or_insert(i);
+        if area(&faces[i]) > area(&faces[*best]) {
+            *best = i;
+        }
+    }
+
+    let mut shell_tris: HashMap<usize, Vec<usize>> = HashMap::new();
+    for (i, &shell) in surfaces.shells.iter().enumerate() {
+        shell_tris.entry(shell).or_default().push(i);
+    }
+
+    let mut keep_patch: HashMap<usize, bool> = HashMap::new();
+    for (&root, &face) in &representative {
+        let f = &faces[face];
+        let shell = shell_of(face);
+        let origin = origins[face];
+        let centroid = [0, 1, 2].map(|i| (pts[f[0]][i] + pts[f[1]][i] + pts[f[2]][i]) / 3.0);
+        // shells whose surface the patch lies on, and whether they face the same way
+        let on_surface = Vec::from_iter(
+            surfaces
+                .coplanar
+                .get(&origin)
+                .into_iter()
+                .flatten()
+                .filter(|&&u| {
+                    let (n, tri) = (&surfaces.normals[u], &surfaces.tris[u]);
+                    contains(pts, tri, n, &centroid, -eps)
+                })
+                .map(|&u| {
+                    let same = dot(&surfaces.normals[u], &surfaces.normals[origin]) > 0.0;
+                    (surfaces.shells[u], same)
+                }),
+        );
+        let inside = shell_tris
+            .iter()
+            .filter(|&(&other, _)| other != shell && on_surface.iter().all(|&(s, _)| s != other))
+            .any(|(_, tris)| winding_number(pts, surfaces.tris, tris, &centroid) > 0.5);
+        // of the copies of a surface shared with the same orientation, the first one is kept
+        let keep = !inside && on_surface.iter().all(|&(other, same)| same && shell < other);
+        keep_patch.insert(root, keep);
+    }
+    (0..faces.len())
+        .map(|i| keep_patch[&find(&mut parent, i)])
+        .collect()
+}
+
+/// Generalized winding number of the triangles `subset` of `tris` around `p`, computed from
+/// the solid angles of the triangles (Van Oosterom and Strackee).
+fn winding_number(pts: &[Point], tris: &[[usize; 3]], subset: &[usize], p: &Point) -> f64 {
+    let mut total = 0.0;
+    for &i in subset {
+        let [a, b, c] = tris[i].map(|v| sub(&pts[v], p));
+        let (la, lb, lc) = (norm(&a), norm(&b), norm(&c));
+        let numerator = dot(&a, &cross(&b, &c));
+        let denominator = la * lb * lc + dot(&a, &b) * lc + dot(&a, &c) * lb + dot(&b, &c) * la;
+        total += 2.0 * numerator.atan2(denominator);
+    }
+    total / (4.0 * std::f64::consts::PI)
+}
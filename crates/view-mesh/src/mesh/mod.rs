@@ -0,0 +1,166 @@
+use std::collections::HashMap;
+
+mod intersect;
+pub mod normals;
+pub mod repair;
+pub mod triangulate;
+
+/// Per-vertex and per-corner data carried along with the geometry of a model.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Attributes {
+    /// RGB colors in `[0, 1]`, three values per vertex
+    pub colors: Option<Vec<f32>>,
+    /// authored normals, three values per triangle corner so that the corners sharing a
+    /// vertex along a seam can have different ones
+    pub normals: Option<Vec<f32>>,
+    /// texture coordinates, two values per triangle corner
+    pub uvs: Option<Vec<f32>>,
+    /// named scalar fields, one value per vertex
+    pub scalars: Vec<(String, Vec<f64>)>,
+}
+
+/// Polygonal faces a triangle mesh was triangulated from.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Polygons {
+    /// vertex indices of all the polygons, one polygon after another
+    pub indices: Vec<usize>,
+    /// number of vertices of every polygon, whose `arity - 2` triangles are consecutive
+    pub arities: Vec<usize>,
+}
+
+/// Merges vertices that are closer than `eps` to each other and remaps `triangles`
+/// onto the merged vertices. With `eps <= 0.0` only bitwise identical points are merged.
+pub fn weld(points: &[f64], triangles: &[usize], eps: f64) -> (Vec<f64>, Vec<usize>) {
+    let mut merged: Vec<f64> = Vec::with_capacity(points.len());
+    let mut remap = Vec::with_capacity(points.len() / 3);
+    if eps > 0.0 {
+        let mut grid: HashMap<[i64; 3], Vec<usize>> = HashMap::new();
+        for p in points.chunks(3) {
+            let cell = [0, 1, 2].map(|i| (p[i] / eps).floor() as i64);
+            let mut found = None;
+            'search: for dx in -1..=1 {
+                for dy in -1..=1 {
+                    for dz in -1..=1 {
+                        let key = [cell[0] + dx, cell[1] + dy, cell[2] + dz];
+                        for &idx in grid.get(&key).into_iter().flatten() {
+                            let q = &merged[idx * 3..idx * 3 + 3];
+                            let d2 = (0..3).map(|i| (p[i] - q[i]).powi(2)).sum::<f64>();
+                            if d2 <= eps * eps {
+                                found = Some(idx);
+                                break 'search;
+                            }
+                        }
+                    }
+                }
+            }
+            let idx = found.unwrap_or_else(|| {
+                let idx = merged.len() / 3;
+                merged.extend_from_slice(p);
+                grid.entry(cell).or_default().push(idx);
+                idx
+            });
+            remap.push(idx);
+        }
+    } else {
+        let mut map: HashMap<[u64; 3], usize> = HashMap::new();
+        for p in points.chunks(3) {
+            // adding 0.0 folds -0.0 into 0.0 so both hash to the same key
+            let key = [0, 1, 2].map(|i| (p[i] + 0.0).to_bits());
+            let idx = *map.entry(key).or_insert_with(|| {
+                merged.extend_from_slice(p);
+                merged.len() / 3 - 1
+            });
+            remap.push(idx);
+        }
+    }
+    let triangles = triangles.iter().map(|&idx| remap[idx]).collect();
+    (merged, triangles)
+}
+
+/// Drops the vertices that are not referenced by any triangle.
+pub fn compact(points: &[f64], triangles: &[usize]) -> (Vec<f64>, Vec<usize>) {
+    let mut remap = vec![usize::MAX; points.len() / 3];
+    let mut compacted = Vec::new();
+    let triangles = triangles
+        .iter()
+        .map(|&idx| {
+            if remap[idx] == usize::MAX {
+                remap[idx] = compacted.len() / 3;
+                compacted.extend_from_slice(&points[idx * 3..idx * 3 + 3]);
+            }
+            remap[idx]
+        })
+        .collect();
+    (compacted, triangles)
+}
+
+/// Length of the diagonal of the axis aligned bounding box of `points`.
+pub fn diagonal(points: &[f64]) -> f64 {
+    if points.is_empty() {
+        return 0.0;
+    }
+    let mut min = [f64::MAX; 3];
+    let mut max = [f64::MIN; 3];
+    for p in points.chunks(3) {
+        for i in 0..3 {
+            min[i] = min[i].min(p[i]);
+            max[i] = max[i].max(p[i]);
+        }
+    }
+    (0..3)
+        .map(|i| (max[i] - min[i]).powi(2))
+        .sum::<f64>()
+        .sqrt()
+}
+
+/// Unit normal of the triangle `tri`, or zero if the triangle is degenerate.
+pub fn face_normal(points: &[f64], tri: &[usize]) -> [f64; 3] {
+    let n = tri_normal(
+        &point(points, tri[0]),
+        &point(points, tri[1]),
+        &point(points, tri[2]),
+    );
+    let len = norm(&n);
+    if len > 0.0 {
+        n.map(|v| v / len)
+    } else {
+        [0.0; 3]
+    }
+}
+
+type Point = [f64; 3];
+
+#[inline]
+fn point(points: &[f64], idx: usize) -> Point {
+    [points[idx * 3], points[idx * 3 + 1], points[idx * 3 + 2]]
+}
+
+#[inline]
+fn sub(a: &Point, b: &Point) -> Point {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+#[inline]
+fn dot(a: &Point, b: &Point) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+#[inline]
+fn cross(a: &Point, b: &Point) -> Point {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+#[inline]
+fn norm(a: &Point) -> f64 {
+    dot(a, a).sqrt()
+}
+
+/// Unnormalized normal of the triangle `(a, b, c)`, its length is twice the area.
+#[inline]
+fn tri_normal(a: &Point, b: &Point, c: &Point) -> Point {
+    cross(&sub(b, a), &sub(c, a))
+}
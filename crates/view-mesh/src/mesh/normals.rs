@@ -0,0 +1,208 @@
+use super::{Point, dot, norm, point, sub, tri_normal};
+
+/// How the normals of the triangles around a point are weighted in its vertex normal.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Weighting {
+    /// by the area of the triangles, cheap but skewed by long thin triangles
+    Area,
+    /// by the angle of the triangles at the point, independent of the tessellation
+    Angle,
+}
+
+/// Normal of every corner of `triangles`, three values per corner, averaging the normals of
+/// the triangles around each point. With a `crease_angle` (in radians), a corner only
+/// averages the triangles whose normal is within that angle of its own triangle normal, so
+/// that the sharp edges stay sharp.
+pub fn corner_normals(
+    points: &[f64],
+    triangles: &[usize],
+    weighting: Weighting,
+    crease_angle: Option<f64>,
+) -> Vec<f32> {
+    let n_points = points.len() / 3;
+    let n_triangles = triangles.len() / 3;
+
+    let mut unit_normals = Vec::with_capacity(n_triangles);
+    // weighted normal of each corner
+    let mut weighted = Vec::with_capacity(triangles.len());
+    for tri in triangles.chunks_exact(3) {
+        let [a, b, c] = [0, 1, 2].map(|k| point(points, tri[k]));
+        // twice the area of the triangle
+        let n = tri_normal(&a, &b, &c);
+        let len = norm(&n);
+        let unit = if len > 0.0 {
+            n.map(|v| v / len)
+        } else {
+            [0.0; 3]
+        };
+        unit_normals.push(unit);
+        match weighting {
+            Weighting::Area => weighted.extend([n; 3]),
+            Weighting::Angle => {
+                for (p, q, r) in [(&a, &b, &c), (&b, &c, &a), (&c, &a, &b)] {
+                    let angle = corner_angle(p, q, r);
+                    weighted.push(unit.map(|v| v * angle));
+                }
+            }
+        }
+    }
+
+    // corners around each point, as offsets into `corners`
+    let mut offsets = vec![0; n_points + 1];
+    for &idx in triangles {
+        offsets[idx + 1] += 1;
+    }
+    for i in 0..n_points {
+        offsets[i + 1] += offsets[i];
+    }
+    let mut corners = vec![0; triangles.len()];
+    let mut filled = offsets.clone();
+    for (corner, &idx) in triangles.iter().enumerate() {
+        corners[filled[idx]] = corner;
+        filled[idx] += 1;
+    }
+
+    let min_cos = crease_angle.map(f64::cos);
+    let mut normals = Vec::with_capacity(triangles.len() * 3);
+    for (corner, &idx) in triangles.iter().enumerate() {
+        let own = &unit_normals[corner / 3];
+        let mut sum = [0.0; 3];
+        for &other in &corners[offsets[idx]..offsets[idx + 1]] {
+            let smooth = min_cos.is_none_or(|min_cos| {
+                other / 3 == corner / 3 || dot(own, &unit_normals[other / 3]) >= min_cos
+            });
+            if smooth {
+                for i in 0..3 {
+                    sum[i] += weighted[other][i];
+                }
+            }
+        }
+        let len = norm(&sum);
+        let normal = if len > 0.0 {
+            sum.map(|v| v / len)
+        } else {
+            *own
+        };
+        normals.extend(normal.map(|v| v as f32));
+    }
+    normals
+}
+
+/// Angle at `p` of the triangle `(p, q, r)`.
+fn corner_angle(p: &Point, q: &Point, r: &Point) -> f64 {
+    let u = sub(q, p);
+    let v = sub(r, p);
+    let len = norm(&u) * norm(&v);
+    if len > 0.0 {
+        (dot(&u, &v) / len).clamp(-1.0, 1.0).acos()
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::PI;
+
+    /// Unit cube facing outwards, two triangles per face.
+    fn cube() -> (Vec<f64>, Vec<usize>) {
+        let points =
+            Vec::from_iter((0..8).flat_map(|i| [0, 1, 2].map(|axis| ((i >> axis) & 1) as f64)));
+        let quads = [
+            [0, 2, 3, 1],
+            [4, 5, 7, 6],
+            [0, 1, 5, 4],
+            [2, 6, 7, 3],
+            [0, 4, 6, 2],
+            [1, 3, 7, 5],
+        ];
+        let triangles = Vec::from_iter(quads.iter().flat_map(|&[a, b, c, d]| [a, b, c, a, c, d]));
+        (points, triangles)
+    }
+
+    fn corner(normals: &[f32], corner: usize) -> [f32; 3] {
+        [normals[corner * 3], normals[corner * 3 + 1], normals[corner * 3 + 2]]
+    }
+
+    fn assert_close(a: [f32; 3], b: [f32; 3]) {
+        assert!((0..3).all(|i| (a[i] - b[i]).abs() < 1e-6), "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn weights_an_uneven_fan_by_area_or_angle() {
+        // a large triangle facing +Z and a small one facing +X, both right angled at point 0
+        let points = [
+            [0.0, 0.0, 0.0],
+            [10.0, 0.0, 0.0],
+            [0.0, 10.0, 0.0],
+            [0.0, 1.0, 0.0],
+            [0.0, 0.0, 1.0],
+        ]
+        .concat();
+        let triangles = [0, 1, 2, 0, 3, 4];
+        let by_area = corner_normals(&points, &triangles, Weighting::Area, None);
+        let by_angle = corner_normals(&points, &triangles, Weighting::Angle, None);
+
+        let half = std::f32::consts::FRAC_1_SQRT_2;
+        assert_close(corner(&by_angle, 0), [half, 0.0, half]);
+        let [x, y, z] = corner(&by_area, 0);
+        assert!(z > 0.99 && x > 0.0 && y == 0.0, "{:?}", [x, y, z]);
+        // the points of a single triangle keep its normal either way
+        assert_close(corner(&by_area, 1), [0.0, 0.0, 1.0]);
+        assert_close(corner(&by_angle, 4), [1.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn crease_angles_of_0_and_180_degrees_give_flat_and_smooth_shading() {
+        let (points, triangles) = cube();
+        for weighting in [Weighting::Area, Weighting::Angle] {
+            let flat = corner_normals(&points, &triangles, weighting, Some(0.0));
+            for (t, tri) in triangles.chunks_exact(3).enumerate() {
+                let [a, b, c] = [0, 1, 2].map(|k| point(&points, tri[k]));
+                let n = tri_normal(&a, &b, &c);
+                let len = norm(&n);
+                let face = n.map(|v| (v / len) as f32);
+                for k in 0..3 {
+                    assert_close(corner(&flat, t * 3 + k), face);
+                }
+            }
+
+            let smooth = corner_normals(&points, &triangles, weighting, None);
+            assert_eq!(corner_normals(&points, &triangles, weighting, Some(PI)), smooth);
+        }
+        // smooth corners point away from the center, the same for all corners of a point
+        let smooth = corner_normals(&points, &triangles, Weighting::Angle, None);
+        let third = 1.0 / 3f32.sqrt();
+        for (c, &idx) in triangles.iter().enumerate() {
+            let expected =
+                [0, 1, 2].map(|axis| if (idx >> axis) & 1 == 1 { third } else { -third });
+            assert_close(corner(&smooth, c), expected);
+        }
+    }
+
+    #[test]
+    fn splits_the_normals_across_a_sharp_edge() {
+        // a strip bent at a right angle along the edge from point 0 to point 1
+        let points = [
+            [0.0, 0.0, 0.0],
+            [1.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0],
+            [0.0, 0.0, 1.0],
+        ]
+        .concat();
+        // facing +Z then +Y
+        let triangles = [0, 1, 2, 1, 0, 3];
+        let creased = corner_normals(&points, &triangles, Weighting::Angle, Some(PI / 4.0));
+        // point 0 is corner 0 of the first triangle and corner 4 of the second one
+        assert_close(corner(&creased, 0), [0.0, 0.0, 1.0]);
+        assert_close(corner(&creased, 4), [0.0, 1.0, 0.0]);
+        assert_close(corner(&creased, 1), [0.0, 0.0, 1.0]);
+        assert_close(corner(&creased, 3), [0.0, 1.0, 0.0]);
+
+        let smooth = corner_normals(&points, &triangles, Weighting::Angle, None);
+        assert_eq!(corner(&smooth, 0), corner(&smooth, 4));
+        let half = std::f32::consts::FRAC_1_SQRT_2;
+        assert_close(corner(&smooth, 0), [0.0, half, half]);
+    }
+}
@@ -0,0 +1,398 @@
+use std::collections::{HashMap, VecDeque};
+
+use super::{compact, cross, diagonal, dot, intersect, norm, point, sub, tri_normal, weld};
+
+/// Vertices closer than this fraction of the bounding box diagonal are considered coincident.
+const MERGE_TOLERANCE: f64 = 1e-9;
+
+/// Repairs the triangle soup `model` gathered from several shells, `tri_in_shells[i]` being
+/// the shell of the `i`th triangle.
+///
+/// The pipeline merges coincident vertices, drops degenerate and duplicate triangles, makes
+/// the orientation of every shell consistent (and outward facing when it is closed) and
+/// finally resolves the intersections between shells by splitting the triangles along the
+/// intersection curves and discarding the parts that end up inside another shell. Fails
+/// rather than returning a mesh that is not closed.
+pub fn repair(
+    model: (Vec<f64>, Vec<usize>),
+    tri_in_shells: &[usize],
+) -> Result<(Vec<f64>, Vec<usize>), String> {
+    let (points, triangles) = model;
+    let eps = diagonal(&points) * MERGE_TOLERANCE;
+    let (points, triangles) = weld(&points, &triangles, eps);
+    let (mut triangles, mut shells) = remove_degenerate(&points, &triangles, tri_in_shells, eps);
+    orient(&points, &mut triangles, &shells);
+    remove_duplicates(&mut triangles, &mut shells);
+
+    let (points, triangles, shells) = intersect::resolve(points, triangles, shells, eps)?;
+
+    let (points, triangles) = weld(&points, &triangles, eps);
+    let (mut triangles, mut shells) = remove_degenerate(&points, &triangles, &shells, eps);
+    remove_duplicates(&mut triangles, &mut shells);
+    match open_edges(&triangles) {
+        0 => Ok(compact(&points, &triangles)),
+        n => Err(format!("the repaired mesh is not closed, {} of its edges are open", n)),
+    }
+}
+
+/// Number of edges that are not matched by as many edges running the other way, i.e. that
+/// are on a boundary or between triangles of inconsistent orientations.
+fn open_edges(triangles: &[usize]) -> usize {
+    let mut balance: HashMap<(usize, usize), i32> = HashMap::new();
+    for tri in triangles.chunks(3) {
+        for k in 0..3 {
+            let (a, b) = (tri[k], tri[(k + 1) % 3]);
+            *balance.entry((a.min(b), a.max(b))).or_default() += if a < b { 1 } else { -1 };
+        }
+    }
+    balance.values().filter(|&&n| n != 0).count()
+}
+
+/// Removes the triangles with repeated vertices. Triangles with three distinct but collinear
+/// vertices are removed as well, after splitting the neighbour across their longest edge at
+/// the middle vertex so that no T-junction is left behind.
+fn remove_degenerate(
+    points: &[f64],
+    triangles: &[usize],
+    shells: &[usize],
+    eps: f64,
+) -> (Vec<usize>, Vec<usize>) {
+    let mut faces = Vec::with_capacity(triangles.len() / 3);
+    let mut face_shells = Vec::with_capacity(triangles.len() / 3);
+    for (tri, &shell) in triangles.chunks(3).zip(shells) {
+        if tri[0] != tri[1] && tri[1] != tri[2] && tri[2] != tri[0] {
+            faces.push([tri[0], tri[1], tri[2]]);
+            face_shells.push(shell);
+        }
+    }
+
+    // flat when the middle vertex is closer than `eps` to the longest edge, however small
+    // the triangle is
+    let is_flat = |f: &[usize; 3]| {
+        let [a, b, c] = f.map(|idx| point(points, idx));
+        let longest = [sub(&b, &a), sub(&c, &b), sub(&a, &c)]
+            .iter()
+            .map(norm)
+            .fold(0.0, f64::max);
+        norm(&tri_normal(&a, &b, &c)) <= eps * longest
+    };
+
+    let mut directed: HashMap<(usize, usize), usize> = HashMap::new();
+    for (i, f) in faces.iter().enumerate() {
+        for k in 0..3 {
+            directed.insert((f[k], f[(k + 1) % 3]), i);
+        }
+    }
+    let mut removed = vec![false; faces.len()];
+    for i in 0..faces.len() {
+        if removed[i] || !is_flat(&faces[i]) {
+            continue;
+        }
+        removed[i] = true;
+        // the middle vertex `c` is the one opposite to the longest edge `a -> b`
+        let f = faces[i];
+        let k = (0..3)
+            .max_by(|&x, &y| {
+                let len = |k: usize| {
+                    let d = sub(&point(points, f[k]), &point(points, f[(k + 1) % 3]));
+                    dot(&d, &d)
+                };
+                len(x).total_cmp(&len(y))
+            })
+            .unwrap();
+        let (a, b, c) = (f[k], f[(k + 1) % 3], f[(k + 2) % 3]);
+        let Some(&j) = directed.get(&(b, a)) else {
+            continue;
+        };
+        if removed[j] {
+            continue;
+        }
+        let g = faces[j];
+        let d = g.into_iter().find(|&v| v != a && v != b).unwrap();
+        if d == c {
+            continue;
+        }
+        faces[j] = [b, c, d];
+        faces.push([c, a, d]);
+        face_shells.push(face_shells[j]);
+        removed.push(false);
+        directed.insert((b, c), j);
+        directed.insert((c, d), j);
+        directed.insert((d, b), j);
+        let new_face = faces.len() - 1;
+        directed.insert((c, a), new_face);
+        directed.insert((a, d), new_face);
+        directed.insert((d, c), new_face);
+    }
+
+    let mut kept = Vec::with_capacity(faces.len() * 3);
+    let mut kept_shells = Vec::with_capacity(faces.len());
+    for ((f, shell), removed) in faces.iter().zip(face_shells).zip(removed) {
+        if !removed && !is_flat(f) {
+            kept.extend_from_slice(f);
+            kept_shells.push(shell);
+        }
+    }
+    (kept, kept_shells)
+}
+
+/// Removes the triangles spanning the same vertices as an earlier one. When two shells
+/// share a face with opposite orientations, the face is an internal wall of their union
+/// and both copies are dropped.
+fn remove_duplicates(triangles: &mut Vec<usize>, shells: &mut Vec<usize>) {
+    let mut seen: HashMap<[usize; 3], usize> = HashMap::new();
+    let mut keep = vec![true; shells.len()];
+    for (i, tri) in triangles.chunks(3).enumerate() {
+        let mut key = [tri[0], tri[1], tri[2]];
+        key.sort_unstable();
+        match seen.get(&key) {
+            Some(&first) => {
+                keep[i] = false;
+                let f = &triangles[first * 3..first * 3 + 3];
+                let same_orientation = (0..3).any(|k| f[k] == tri[0] && f[(k + 1) % 3] == tri[1]);
+                if !same_orientation && shells[first] != shells[i] {
+                    keep[first] = false;
+                }
+            }
+            None => {
+                seen.insert(key, i);
+            }
+        }
+    }
+
+    let mut kept = 0;
+    for i in 0..keep.len() {
+        if keep[i] {
+            triangles.copy_within(i * 3..i * 3 + 3, kept * 3);
+            shells[kept] = shells[i];
+            kept += 1;
+        }
+    }
+    triangles.truncate(kept * 3);
+    shells.truncate(kept);
+}
+
+/// Makes the orientation of the triangles consistent inside every connected component of a
+/// shell, then flips the closed components enclosing a negative volume so that they face
+/// outwards.
+fn orient(points: &[f64], triangles: &mut [usize], shells: &[usize]) {
+    let n_faces = shells.len();
+    // the edges of every shell, other shells touching them having no say in its orientation
+    let mut edge_faces: HashMap<(usize, usize, usize), Vec<usize>> = HashMap::new();
+    for (i, tri) in triangles.chunks(3).enumerate() {
+        for k in 0..3 {
+            let (a, b) = (tri[k], tri[(k + 1) % 3]);
+            edge_faces.entry((shells[i], a.min(b), a.max(b))).or_default().push(i);
+        }
+    }
+    let has_directed =
+        |tri: &[usize], a: usize, b: usize| (0..3).any(|k| tri[k] == a && tri[(k + 1) % 3] == b);
+
+    let mut visited = vec![false; n_faces];
+    let mut queue = VecDeque::new();
+    for seed in 0..n_faces {
+        if visited[seed] {
+            continue;
+        }
+        visited[seed] = true;
+        queue.push_back(seed);
+        let mut component = vec![];
+        let mut closed = true;
+        while let Some(f) = queue.pop_front() {
+            component.push(f);
+            for k in 0..3 {
+                let (a, b) = (triangles[f * 3 + k], triangles[f * 3 + (k + 1) % 3]);
+                let neighbors = &edge_faces[&(shells[f], a.min(b), a.max(b))];
+                closed &= neighbors.len() > 1;
+                // non-manifold edges do not tell how the neighbours should be oriented
+                if neighbors.len() != 2 {
+                    continue;
+                }
+                let g = if neighbors[0] == f {
+                    neighbors[1]
+                } else {
+                    neighbors[0]
+                };
+                if visited[g] {
+                    continue;
+                }
+                visited[g] = true;
+                if has_directed(&triangles[g * 3..g * 3 + 3], a, b) {
+                    triangles.swap(g * 3 + 1, g * 3 + 2);
+                }
+                queue.push_back(g);
+            }
+        }
+
+        let volume: f64 = component
+            .iter()
+            .map(|&f| {
+                let [a, b, c] = [0, 1, 2].map(|k| point(points, triangles[f * 3 + k]));
+                dot(&a, &cross(&b, &c))
+            })
+            .sum();
+        if closed && volume < 0.0 {
+            for f in component {
+                triangles.swap(f * 3 + 1, f * 3 + 2);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Axis aligned cube of side `size` with its lowest corner at `min`, facing outwards.
+    /// `flip` picks the other diagonal for every face.
+    fn cube(min: [f64; 3], size: f64, flip: bool) -> (Vec<f64>, Vec<usize>) {
+        let points = Vec::from_iter((0..8).flat_map(|i| {
+            [0, 1, 2].map(|axis| min[axis] + size * ((i >> axis) & 1) as f64)
+        }));
+        let quads = [
+            [0, 2, 3, 1],
+            [4, 5, 7, 6],
+            [0, 1, 5, 4],
+            [2, 6, 7, 3],
+            [0, 4, 6, 2],
+            [1, 3, 7, 5],
+        ];
+        let triangles = Vec::from_iter(quads.iter().flat_map(|&[a, b, c, d]| {
+            if flip {
+                [a, b, d, b, c, d]
+            } else {
+                [a, b, c, a, c, d]
+            }
+        }));
+        (points, triangles)
+    }
+
+    /// Concatenates `meshes`, each one being its own shell.
+    fn scene(meshes: &[(Vec<f64>, Vec<usize>)]) -> ((Vec<f64>, Vec<usize>), Vec<usize>) {
+        let mut points = vec![];
+        let mut triangles = vec![];
+        let mut shells = vec![];
+        for (shell, (p, t)) in meshes.iter().enumerate() {
+            let offset = points.len() / 3;
+            points.extend_from_slice(p);
+            triangles.extend(t.iter().map(|v| v + offset));
+            shells.resize(shells.len() + t.len() / 3, shell);
+        }
+        ((points, triangles), shells)
+    }
+
+    fn volume(points: &[f64], triangles: &[usize]) -> f64 {
+        triangles
+            .chunks(3)
+            .map(|t| {
+                let [a, b, c] = [0, 1, 2].map(|k| point(points, t[k]));
+                dot(&a, &cross(&b, &c)) / 6.0
+            })
+            .sum()
+    }
+
+    fn union(meshes: &[(Vec<f64>, Vec<usize>)]) -> (Vec<f64>, Vec<usize>) {
+        let (model, shells) = scene(meshes);
+        let (points, triangles) = repair(model, &shells).unwrap();
+        assert_eq!(open_edges(&triangles), 0);
+        (points, triangles)
+    }
+
+    fn assert_volume(mesh: &(Vec<f64>, Vec<usize>), expected: f64) {
+        let v = volume(&mesh.0, &mesh.1);
+        assert!((v - expected).abs() < 1e-9, "volume {} instead of {}", v, expected);
+    }
+
+    #[test]
+    fn weld_merges_close_points() {
+        let points = [0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 1e-12, 0.0, 0.0, -0.0, 1.0, 0.0];
+        let (merged, triangles) = weld(&points, &[0, 1, 3, 2, 1, 3], 1e-9);
+        assert_eq!(merged.len(), 9);
+        assert_eq!(triangles, [0, 1, 2, 0, 1, 2]);
+        let (merged, _) = weld(&points, &[0, 1, 3], 0.0);
+        assert_eq!(merged.len(), 12);
+    }
+
+    #[test]
+    fn removes_degenerate_triangles_without_opening_the_mesh() {
+        // a square whose lower half is split at the middle of the diagonal, the gap along
+        // the diagonal being closed by a flat triangle, plus a triangle with a repeated vertex
+        let points = [0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 1.0, 0.0, 0.0, 1.0, 0.0, 0.5, 0.5, 0.0];
+        let triangles = [0, 1, 4, 1, 2, 4, 0, 4, 2, 0, 2, 3, 1, 1, 2];
+        let (kept, shells) = remove_degenerate(&points, &triangles, &[0; 5], 1e-9);
+        assert_eq!(shells.len(), 4);
+        // the neighbour across the diagonal is split at the middle vertex
+        let mut faces = Vec::from_iter(kept.chunks(3).map(|t| {
+            let mut t = t.to_vec();
+            t.sort_unstable();
+            t
+        }));
+        faces.sort_unstable();
+        assert_eq!(faces, [vec![0, 1, 4], vec![0, 3, 4], vec![1, 2, 4], vec![2, 3, 4]]);
+    }
+
+    #[test]
+    fn removes_duplicate_triangles() {
+        let mut triangles = vec![0, 1, 2, 1, 2, 0, 3, 4, 5, 5, 4, 3];
+        let mut shells = vec![0, 0, 0, 1];
+        remove_duplicates(&mut triangles, &mut shells);
+        // the same face twice keeps one copy, opposite faces of two shells cancel out
+        assert_eq!(triangles, [0, 1, 2]);
+        assert_eq!(shells, [0]);
+    }
+
+    #[test]
+    fn orients_shells_outwards() {
+        let (points, mut triangles) = cube([0.0; 3], 1.0, false);
+        // turn the whole cube inside out, then flip two faces back
+        for t in triangles.chunks_mut(3) {
+            t.swap(1, 2);
+        }
+        triangles.swap(1, 2);
+        triangles.swap(10, 11);
+        orient(&points, &mut triangles, &[0; 12]);
+        assert_eq!(open_edges(&triangles), 0);
+        assert!((volume(&points, &triangles) - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn unites_overlapping_cubes() {
+        let a = cube([0.0; 3], 1.0, false);
+        let mesh = union(&[a.clone(), cube([0.5, 0.25, 0.25], 1.0, false)]);
+        assert_volume(&mesh, 1.71875);
+        let mesh = union(&[a.clone(), cube([0.5, 0.3, 0.2], 1.0, false)]);
+        assert_volume(&mesh, 1.72);
+        let mesh = union(&[a, cube([0.5, 0.3, 0.2], 1.0, true)]);
+        assert_volume(&mesh, 1.72);
+    }
+
+    #[test]
+    fn unites_cubes_touching_by_a_face() {
+        let a = cube([0.0; 3], 1.0, false);
+        let mesh = union(&[a.clone(), cube([1.0, 0.0, 0.0], 1.0, false)]);
+        assert_volume(&mesh, 2.0);
+        let mesh = union(&[a.clone(), cube([1.0, 0.0, 0.0], 1.0, true)]);
+        assert_volume(&mesh, 2.0);
+        let mesh = union(&[a, cube([1.0, 0.5, 0.25], 1.0, false)]);
+        assert_volume(&mesh, 2.0);
+    }
+
+    #[test]
+    fn unites_cubes_sharing_face_planes() {
+        let a = cube([0.0; 3], 1.0, false);
+        let mesh = union(&[a.clone(), cube([0.5, 0.0, 0.0], 1.0, true)]);
+        assert_volume(&mesh, 1.5);
+        let mesh = union(&[a.clone(), a.clone()]);
+        assert_volume(&mesh, 1.0);
+        let mesh = union(&[a, cube([0.25; 3], 0.5, false)]);
+        assert_volume(&mesh, 1.0);
+        assert_eq!(mesh.1.len(), 36);
+    }
+
+    #[test]
+    fn fails_on_open_meshes() {
+        let (points, triangles) = cube([0.0; 3], 1.0, false);
+        let model = (points, triangles[..30].to_vec());
+        assert!(repair(model, &[0; 10]).is_err());
+    }
+}
@@ -0,0 +1,146 @@
+use super::{Polygons, cross, dot, point, sub};
+
+/// Triangulates every polygon of `polygons`, each one into `arity - 2` consecutive triangles.
+pub fn triangulate_polygons(points: &[f64], polygons: &Polygons) -> Vec<usize> {
+    let mut triangles = Vec::with_capacity(polygons.indices.len() * 3);
+    let mut start = 0;
+    for &arity in &polygons.arities {
+        let polygon = &polygons.indices[start..start + arity];
+        for tri in triangulate(points, polygon) {
+            triangles.extend_from_slice(&tri);
+        }
+        start += arity;
+    }
+    triangles
+}
+
+/// Triangulates the planar `polygon`: convex polygons are split as a fan, concave ones by
+/// ear clipping. Always returns `polygon.len() - 2` triangles so that the polygon can be
+/// found back from the triangle list.
+pub fn triangulate(points: &[f64], polygon: &[usize]) -> Vec<[usize; 3]> {
+    let n = polygon.len();
+    if n < 3 {
+        return vec![];
+    }
+    let fan = || Vec::from_iter((1..n - 1).map(|k| [polygon[0], polygon[k], polygon[k + 1]]));
+    if n == 3 {
+        return fan();
+    }
+
+    // Newell's normal is robust to collinear and slightly non-planar vertices
+    let mut normal = [0.0; 3];
+    for k in 0..n {
+        let a = point(points, polygon[k]);
+        let b = point(points, polygon[(k + 1) % n]);
+        let c = cross(&a, &b);
+        for i in 0..3 {
+            normal[i] += c[i];
+        }
+    }
+    let turn = |a: usize, b: usize, c: usize| {
+        let [a, b, c] = [a, b, c].map(|idx| point(points, idx));
+        dot(&cross(&sub(&b, &a), &sub(&c, &b)), &normal)
+    };
+    let convex =
+        (0..n).all(|k| turn(polygon[k], polygon[(k + 1) % n], polygon[(k + 2) % n]) >= 0.0);
+    if convex {
+        return fan();
+    }
+
+    let in_triangle = |p: usize, a: usize, b: usize, c: usize| {
+        turn(a, b, p) >= 0.0 && turn(b, c, p) >= 0.0 && turn(c, a, p) >= 0.0
+    };
+    let mut remaining = polygon.to_vec();
+    let mut triangles = Vec::with_capacity(n - 2);
+    while remaining.len() > 3 {
+        let m = remaining.len();
+        let ear = (0..m).find(|&k| {
+            let (a, b, c) = (
+                remaining[(k + m - 1) % m],
+                remaining[k],
+                remaining[(k + 1) % m],
+            );
+            turn(a, b, c) > 0.0
+                && remaining
+                    .iter()
+                    .all(|&p| p == a || p == b || p == c || !in_triangle(p, a, b, c))
+        });
+        // self-intersecting or degenerate polygons have no ear left, finish them as a fan
+        let k = ear.unwrap_or(1);
+        triangles.push([
+            remaining[(k + m - 1) % m],
+            remaining[k],
+            remaining[(k + 1) % m],
+        ]);
+        remaining.remove(k);
+    }
+    triangles.push([remaining[0], remaining[1], remaining[2]]);
+    triangles
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Points of a polygon in the XY plane.
+    fn points(xy: &[[f64; 2]]) -> Vec<f64> {
+        Vec::from_iter(xy.iter().flat_map(|&[x, y]| [x, y, 0.0]))
+    }
+
+    /// Twice the signed area of the triangle, positive when counterclockwise.
+    fn signed_area(xy: &[[f64; 2]], [a, b, c]: [usize; 3]) -> f64 {
+        let [a, b, c] = [xy[a], xy[b], xy[c]];
+        (b[0] - a[0]) * (c[1] - a[1]) - (b[1] - a[1]) * (c[0] - a[0])
+    }
+
+    /// Whether `p` is inside the polygon, by the even-odd rule.
+    fn inside(xy: &[[f64; 2]], p: [f64; 2]) -> bool {
+        let mut inside = false;
+        for k in 0..xy.len() {
+            let (a, b) = (xy[k], xy[(k + 1) % xy.len()]);
+            if (a[1] > p[1]) != (b[1] > p[1])
+                && p[0] < a[0] + (p[1] - a[1]) / (b[1] - a[1]) * (b[0] - a[0])
+            {
+                inside = !inside;
+            }
+        }
+        inside
+    }
+
+    #[test]
+    fn splits_a_convex_quad_as_a_fan() {
+        let xy = [[0.0, 0.0], [2.0, 0.0], [3.0, 1.0], [0.0, 2.0]];
+        let triangles = triangulate(&points(&xy), &[0, 1, 2, 3]);
+        assert_eq!(triangles, vec![[0, 1, 2], [0, 2, 3]]);
+    }
+
+    #[test]
+    fn clips_the_ears_of_a_concave_polygon() {
+        // an L, concave at point 3
+        let xy = [[0.0, 0.0], [2.0, 0.0], [2.0, 1.0], [1.0, 1.0], [1.0, 2.0], [0.0, 2.0]];
+        let polygon = [0, 1, 2, 3, 4, 5];
+        let triangles = triangulate(&points(&xy), &polygon);
+        assert_eq!(triangles.len(), polygon.len() - 2);
+        for &tri in &triangles {
+            // same orientation as the polygon and inside it
+            assert!(signed_area(&xy, tri) > 0.0, "{:?} is flipped", tri);
+            let centroid = [0, 1].map(|i| tri.iter().map(|&idx| xy[idx][i]).sum::<f64>() / 3.0);
+            assert!(inside(&xy, centroid), "{:?} is outside the polygon", tri);
+        }
+        // the triangles cover the polygon, whose area is 3, doubled as `signed_area`
+        let area: f64 = triangles.iter().map(|&tri| signed_area(&xy, tri)).sum();
+        assert!((area - 6.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn returns_n_minus_2_triangles_for_self_intersecting_polygons() {
+        let bowtie = [[0.0, 0.0], [1.0, 1.0], [1.0, 0.0], [0.0, 1.0]];
+        let pentagram = [[0.0, 1.0], [0.59, -0.81], [-0.95, 0.31], [0.95, 0.31], [-0.59, -0.81]];
+        for xy in [&bowtie[..], &pentagram[..]] {
+            let polygon = Vec::from_iter(0..xy.len());
+            let triangles = triangulate(&points(xy), &polygon);
+            assert_eq!(triangles.len(), polygon.len() - 2);
+            assert!(triangles.iter().flatten().all(|idx| polygon.contains(idx)));
+        }
+    }
+}
@@ -1,25 +1,127 @@
-use std::{cell::RefCell, collections::HashMap, io::BufReader, rc::Rc};
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    rc::Rc,
+    sync::atomic::{AtomicU32, Ordering},
+};
 
 use js_sys::Uint8Array;
 use leptos::prelude::*;
 use leptos::task::spawn_local;
 use send_wrapper::SendWrapper;
-use tobj::Material;
-use wasm_bindgen::JsCast;
+use wasm_bindgen::{JsCast, JsValue};
 
-use crate::render::viewer::Viewer;
+use crate::mesh::{Attributes, Polygons, normals::Weighting};
+use crate::notifications::{Notifications, Toasts};
+use crate::render::viewer::{Navigation, Pick, ViewPreset, Viewer};
 
 pub type ViewerWrapper = SendWrapper<Rc<RefCell<Viewer>>>;
 
+pub use view_mesh::{io, mesh};
+
+pub mod notifications;
 pub mod render;
+pub mod worker;
 
 type RawModel = (Vec<f64>, Vec<usize>);
 
+/// What the faces of a model are colored with.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ColorSource {
+    Face,
+    Vertex,
+    /// the scalar field at this index in [`Attributes::scalars`]
+    Scalar(usize),
+}
+
+impl ColorSource {
+    fn value(&self) -> String {
+        match self {
+            Self::Face => "face".to_owned(),
+            Self::Vertex => "vertex".to_owned(),
+            Self::Scalar(idx) => format!("scalar{}", idx),
+        }
+    }
+
+    fn from_value(value: &str) -> Self {
+        match value {
+            "vertex" => Self::Vertex,
+            _ => value
+                .strip_prefix("scalar")
+                .and_then(|idx| idx.parse().ok())
+                .map_or(Self::Face, Self::Scalar),
+        }
+    }
+}
+
+/// How the normals a model is lit with are computed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Shading {
+    /// the normals read from the file
+    File,
+    Flat,
+    Smooth(Weighting),
+    /// smooth except across the edges sharper than [`Model::crease_angle`]
+    Crease(Weighting),
+}
+
+impl Shading {
+    const COMPUTED: [Self; 5] = [
+        Self::Flat,
+        Self::Smooth(Weighting::Area),
+        Self::Smooth(Weighting::Angle),
+        Self::Crease(Weighting::Area),
+        Self::Crease(Weighting::Angle),
+    ];
+
+    fn value(&self) -> &'static str {
+        match self {
+            Self::File => "file",
+            Self::Flat => "flat",
+            Self::Smooth(Weighting::Area) => "smooth-area",
+            Self::Smooth(Weighting::Angle) => "smooth-angle",
+            Self::Crease(Weighting::Area) => "crease-area",
+            Self::Crease(Weighting::Angle) => "crease-angle",
+        }
+    }
+
+    fn from_value(value: &str) -> Self {
+        Self::COMPUTED
+            .into_iter()
+            .find(|shading| shading.value() == value)
+            .unwrap_or(Self::File)
+    }
+
+    fn label(&self) -> &'static str {
+        match self {
+            Self::File => "File normals",
+            Self::Flat => "Flat",
+            Self::Smooth(Weighting::Area) => "Smooth (area)",
+            Self::Smooth(Weighting::Angle) => "Smooth (angle)",
+            Self::Crease(Weighting::Area) => "Crease (area)",
+            Self::Crease(Weighting::Angle) => "Crease (angle)",
+        }
+    }
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub struct Model {
     id: u32,
+    /// id of the model this one is a child of, for files with a node hierarchy
+    parent: Option<u32>,
+    /// number of ancestors of the model
+    depth: usize,
+    /// whether the children of the model are listed
+    expanded: RwSignal<bool>,
     name: RwSignal<String>,
     data: RwSignal<RawModel>,
+    polygons: RwSignal<Option<Polygons>>,
+    attributes: RwSignal<Attributes>,
+    material: RwSignal<Option<io::SurfaceMaterial>>,
+    color_source: RwSignal<ColorSource>,
+    shading: RwSignal<Shading>,
+    /// angle in degrees above which [`Shading::Crease`] keeps the edges sharp
+    crease_angle: RwSignal<f64>,
     show: RwSignal<bool>,
     show_edges: RwSignal<bool>,
     edge_width: RwSignal<f64>,
@@ -29,19 +131,49 @@ pub struct Model {
 }
 
 impl Model {
-    fn new(name: String, model: RawModel, id: u32) -> Self {
+    fn new(name: String, mesh: io::ImportedMesh, id: u32) -> Self {
+        let color_source = if mesh.attributes.colors.is_some() {
+            ColorSource::Vertex
+        } else {
+            ColorSource::Face
+        };
+        let face_color = mesh
+            .material
+            .as_ref()
+            .map_or("#cccccc".to_string(), |material| rgb_to_hex(material.diffuse));
+        let face_alpha = mesh.material.as_ref().map_or(1.0, |material| material.alpha as f64);
+        let shading = if mesh.attributes.normals.is_some() {
+            Shading::File
+        } else {
+            Shading::Flat
+        };
         Self {
             id,
+            parent: None,
+            depth: 0,
+            expanded: RwSignal::new(true),
             name: RwSignal::new(name),
-            data: RwSignal::new(model),
+            data: RwSignal::new((mesh.points, mesh.triangles)),
+            polygons: RwSignal::new(mesh.polygons),
+            attributes: RwSignal::new(mesh.attributes),
+            material: RwSignal::new(mesh.material),
+            color_source: RwSignal::new(color_source),
+            shading: RwSignal::new(shading),
+            crease_angle: RwSignal::new(30.0),
             show: RwSignal::new(true),
             show_edges: RwSignal::new(false),
             edge_width: RwSignal::new(1.0),
             edge_color: RwSignal::new("#000000".to_string()),
-            face_color: RwSignal::new("#cccccc".to_string()),
-            face_alpha: RwSignal::new(1.0),
+            face_color: RwSignal::new(face_color),
+            face_alpha: RwSignal::new(face_alpha),
         }
     }
+
+    fn child_of(mut self, parent: &Model) -> Self {
+        self.parent = Some(parent.id);
+        self.depth = parent.depth + 1;
+        self
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -56,13 +188,31 @@ impl Models {
         self.0.push(model);
     }
 
-    fn remove(&mut self, id: u32) {
-        self.0.retain(|m| m.id != id);
+    /// Removes the model `id` along with its descendants, and returns the ids of the
+    /// removed models.
+    fn remove(&mut self, id: u32) -> Vec<u32> {
+        let mut removed = vec![id];
+        removed.extend(self.descendants(id).map(|m| m.id));
+        self.0.retain(|m| !removed.contains(&m.id));
+        removed
+    }
+
+    /// Iterates over the descendants of the model `id`, relying on the children being
+    /// added after their parent.
+    fn descendants(&self, id: u32) -> impl Iterator<Item = &Model> {
+        let mut subtree = vec![id];
+        self.0.iter().filter(move |m| {
+            let inside = m.parent.is_some_and(|parent| subtree.contains(&parent));
+            if inside {
+                subtree.push(m.id);
+            }
+            inside
+        })
     }
 
     fn hide(&mut self) {
         for m in &mut self.0 {
-            let show = m.show.clone();
+            let show = m.show;
             if show.get() {
                 show.set(false);
             }
@@ -70,37 +220,120 @@ impl Models {
     }
 }
 
-async fn read_obj_from_file(file: web_sys::File) -> Result<RawModel, String> {
+/// A point of a model clicked on the canvas, whose row is highlighted in the model list.
+#[derive(Clone, Debug, PartialEq)]
+struct Picked {
+    pick: Pick,
+    /// where the canvas was clicked, in CSS pixels from its top left corner
+    x: f64,
+    y: f64,
+}
+
+type PickedPoint = RwSignal<Option<Picked>>;
+
+/// The face of `model` at `pick`, as an index into its polygons if it has some and into its
+/// triangles otherwise, with the vertices of that face and the vertex closest to the picked
+/// point.
+fn picked_face(model: &Model, pick: &Pick) -> Option<(usize, Vec<usize>, usize)> {
+    let corners = model.data.with(|(_, triangles)| {
+        let corners = triangles.get(3 * pick.triangle..3 * pick.triangle + 3)?;
+        Some([corners[0], corners[1], corners[2]])
+    })?;
+    let closest = (0..3)
+        .max_by(|&a, &b| pick.barycentric[a].total_cmp(&pick.barycentric[b]))
+        .map_or(corners[0], |k| corners[k]);
+    let (face, vertices) = model.polygons.with(|polygons| match polygons {
+        // the triangles of a polygon follow each other
+        Some(polygons) => {
+            let mut first_triangle = 0;
+            let mut start = 0;
+            for (face, &arity) in polygons.arities.iter().enumerate() {
+                let n_triangles = arity.saturating_sub(2);
+                if pick.triangle < first_triangle + n_triangles {
+                    return Some((face, polygons.indices[start..start + arity].to_vec()));
+                }
+                first_triangle += n_triangles;
+                start += arity;
+            }
+            None
+        }
+        None => Some((pick.triangle, corners.to_vec())),
+    })?;
+    Some((face, vertices, closest))
+}
+
+/// Extensions of the files loaded alongside the meshes as material resources.
+const IMAGE_EXTENSIONS: [&str; 6] = ["png", "jpg", "jpeg", "bmp", "gif", "webp"];
+
+pub(crate) async fn read_file_bytes(file: &web_sys::File) -> Result<Vec<u8>, String> {
     let buffer = wasm_bindgen_futures::JsFuture::from(file.array_buffer()).await;
     if let Ok(buffer) = buffer {
         let array = Uint8Array::new(&buffer);
-        let bytes = array.to_vec();
-        let bytes: &[u8] = &bytes;
-        let mut reader = BufReader::new(bytes);
-        if let Ok(models) = tobj::load_obj_buf(&mut reader, &tobj::LoadOptions::default(), |_| {
-            Ok((vec![Material::default()], HashMap::new()))
-        }) {
-            let mesh = &models.0[0].mesh;
-            return Ok((
-                mesh.positions.clone(),
-                Vec::from_iter(mesh.indices.iter().map(|idx| *idx as usize)),
-            ));
-        }
+        return Ok(array.to_vec());
     } else {
         web_sys::console::warn_1(&"failed to read file buffer".into());
     }
-    Err("failed to read obj".to_owned())
+    Err("failed to read file".to_owned())
 }
 
-fn write_obj(points: &[f64], triangles: &[usize]) -> String {
-    let mut txt = "".to_owned();
-    for p in points.chunks(3) {
-        txt.push_str(&format!("v {} {} {}\n", p[0], p[1], p[2]));
+async fn read_image(file: &web_sys::File) -> Option<web_sys::ImageBitmap> {
+    let promise = web_sys::window()?.create_image_bitmap_with_blob(file).ok()?;
+    let bitmap = wasm_bindgen_futures::JsFuture::from(promise).await.ok()?;
+    bitmap.dyn_into().ok()
+}
+
+/// Writes the models having triangles in `format` and downloads the resulting file, named
+/// after `stem`. OBJ files come with their material library in a zip when `bundle_mtl`.
+fn export_models(format: io::Format, stem: &str, models: &[Model], bundle_mtl: bool) {
+    let data = Vec::from_iter(models.iter().map(|model| {
+        let (points, triangles) = model.data.get_untracked();
+        let color = hex_to_rgba(&model.face_color.get_untracked());
+        (
+            model.name.get_untracked(),
+            points,
+            triangles,
+            model.polygons.get_untracked(),
+            [color[0], color[1], color[2]],
+            model.face_alpha.get_untracked() as f32,
+        )
+    }));
+    let meshes = Vec::from_iter(
+        data.iter()
+            .filter(|(_, _, triangles, ..)| !triangles.is_empty())
+            .map(|(name, points, triangles, polygons, color, alpha)| io::ExportedMesh {
+                name,
+                points,
+                triangles,
+                polygons: polygons.as_ref(),
+                color: *color,
+                alpha: *alpha,
+            }),
+    );
+    if meshes.is_empty() {
+        return;
     }
-    for tri in triangles.chunks(3) {
-        txt.push_str(&format!("f {} {} {}\n", tri[0] + 1, tri[1] + 1, tri[2] + 1));
+    download(&format.write(stem, &meshes, bundle_mtl));
+}
+
+fn download(file: &io::ExportedFile) {
+    let parts = js_sys::Array::of1(&unsafe { Uint8Array::view(&file.bytes).into() });
+    let properties = web_sys::BlobPropertyBag::new();
+    properties.set_type(file.mime_type);
+    if let Ok(blob) = web_sys::Blob::new_with_buffer_source_sequence_and_options(&parts, &properties)
+    {
+        let link = web_sys::window()
+            .unwrap()
+            .document()
+            .unwrap()
+            .create_element("a")
+            .unwrap();
+        let link: web_sys::HtmlAnchorElement = link.dyn_into().unwrap();
+        let url = web_sys::Url::create_object_url_with_blob(&blob).unwrap();
+        link.set_href(&url);
+        link.set_download(&file.name);
+        link.click();
+        web_sys::Url::revoke_object_url(&link.href()).unwrap();
     }
-    txt
 }
 
 fn hex_to_rgba(hex: &str) -> [f32; 4] {
@@ -111,6 +344,34 @@ fn hex_to_rgba(hex: &str) -> [f32; 4] {
     [r, g, b, 1.0]
 }
 
+fn rgb_to_hex(rgb: [f32; 3]) -> String {
+    let [r, g, b] = rgb.map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8);
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
+
+/// Maps every value of `values` to an RGB color, from blue for the minimum to red for
+/// the maximum.
+fn colormap(values: &[f64]) -> Vec<f32> {
+    const STOPS: [[f32; 3]; 5] = [
+        [0.0, 0.0, 1.0],
+        [0.0, 1.0, 1.0],
+        [0.0, 1.0, 0.0],
+        [1.0, 1.0, 0.0],
+        [1.0, 0.0, 0.0],
+    ];
+    let min = values.iter().copied().fold(f64::MAX, f64::min);
+    let max = values.iter().copied().fold(f64::MIN, f64::max);
+    let range = if max > min { max - min } else { 1.0 };
+    let mut colors = Vec::with_capacity(values.len() * 3);
+    for v in values {
+        let t = ((v - min) / range) as f32 * (STOPS.len() - 1) as f32;
+        let i = (t.floor() as usize).min(STOPS.len() - 2);
+        let f = t - i as f32;
+        colors.extend((0..3).map(|c| STOPS[i][c] + (STOPS[i + 1][c] - STOPS[i][c]) * f));
+    }
+    colors
+}
+
 #[component]
 pub fn Model(model: Model) -> impl IntoView {
     let viewer = expect_context::<ViewerWrapper>();
@@ -148,6 +409,18 @@ pub fn Model(model: Model) -> impl IntoView {
             viewer
                 .borrow_mut()
                 .set_face_color(model.id, [color[0], color[1], color[2]]);
+            // the colors of the material library take precedence over the derived ones
+            model.material.with(|material| {
+                if let Some(material) = material {
+                    let mut viewer = viewer.borrow_mut();
+                    if let Some(ambient) = material.ambient {
+                        viewer.set_ambient_color(model.id, ambient);
+                    }
+                    if let Some(specular) = material.specular {
+                        viewer.set_specular_color(model.id, specular);
+                    }
+                }
+            });
         });
     }
 
@@ -160,50 +433,136 @@ pub fn Model(model: Model) -> impl IntoView {
         });
     }
 
-    let write_to_local = move |_| {
-        let (points, triangles) = model.data.get();
-        let txt = write_obj(&points, &triangles);
-        let parts = js_sys::Array::of1(&unsafe { Uint8Array::view(txt.as_bytes()).into() });
-        let properties = web_sys::BlobPropertyBag::new();
-        properties.set_type("model/obj");
-        if let Ok(blob) =
-            web_sys::Blob::new_with_buffer_source_sequence_and_options(&parts, &properties)
+    {
+        let viewer = viewer.clone();
+        Effect::new(move |_| {
+            let source = model.color_source.get();
+            model.attributes.with(|attributes| {
+                let colors = match source {
+                    ColorSource::Face => None,
+                    ColorSource::Vertex => attributes.colors.clone(),
+                    ColorSource::Scalar(idx) => {
+                        attributes.scalars.get(idx).map(|(_, values)| colormap(values))
+                    }
+                };
+                viewer
+                    .borrow_mut()
+                    .set_vertex_colors(model.id, colors.as_deref());
+            });
+        });
+    }
+
+    {
+        let viewer = viewer.clone();
+        // the normals being computed, dropped when the shading changes before they are done
+        let job = Rc::new(RefCell::new(None::<worker::Canceller>));
         {
-            let link = web_sys::window()
-                .unwrap()
-                .document()
-                .unwrap()
-                .create_element("a")
-                .unwrap();
-            let link: web_sys::HtmlAnchorElement = link.dyn_into().unwrap();
-            let url = web_sys::Url::create_object_url_with_blob(&blob).unwrap();
-            link.set_href(&url);
-            link.set_download(&(model.name.get() + ".obj"));
-            link.click();
-            web_sys::Url::revoke_object_url(&link.href()).unwrap();
+            let job = SendWrapper::new(job.clone());
+            on_cleanup(move || {
+                if let Some(canceller) = job.borrow_mut().take() {
+                    canceller.cancel();
+                }
+            });
+        }
+        Effect::new(move |_| {
+            if let Some(canceller) = job.borrow_mut().take() {
+                canceller.cancel();
+            }
+            let (weighting, crease_angle) = match model.shading.get() {
+                Shading::File => {
+                    model.attributes.with(|attributes| match &attributes.normals {
+                        Some(normals) => viewer.borrow_mut().set_corner_normals(model.id, normals),
+                        None => viewer.borrow_mut().set_vertex_normals(model.id, None),
+                    });
+                    return;
+                }
+                Shading::Flat => {
+                    viewer.borrow_mut().set_vertex_normals(model.id, None);
+                    return;
+                }
+                Shading::Smooth(weighting) => (weighting, None),
+                Shading::Crease(weighting) => {
+                    (weighting, Some(model.crease_angle.get().to_radians()))
+                }
+            };
+            // tracked so that normals are computed again for new points or triangles
+            let (canceller, normals) = model.data.with(|(points, triangles)| {
+                worker::corner_normals_in_worker(points, triangles, weighting, crease_angle)
+            });
+            *job.borrow_mut() = Some(canceller);
+            let viewer = viewer.clone();
+            let job = job.clone();
+            spawn_local(async move {
+                let normals = match normals.await {
+                    Ok(normals) => normals,
+                    Err(worker::WorkerError::Cancelled) => return,
+                    Err(_) => model.data.with_untracked(|(points, triangles)| {
+                        mesh::normals::corner_normals(points, triangles, weighting, crease_angle)
+                    }),
+                };
+                job.borrow_mut().take();
+                viewer.borrow_mut().set_corner_normals(model.id, &normals);
+            });
+        });
+    }
+
+    let export_format = RwSignal::new(io::Format::Obj);
+    let write_to_local = {
+        let model = model.clone();
+        move |_| {
+            let models = std::slice::from_ref(&model);
+            export_models(export_format.get(), &model.name.get(), models, false)
         }
     };
 
+    let models = expect_context::<ReadSignal<Models>>();
     let set_models = expect_context::<WriteSignal<Models>>();
+    let picked = expect_context::<PickedPoint>();
+    let is_picked = move || picked.with(|p| p.as_ref().is_some_and(|p| p.pick.model == model.id));
     let viewer_for_destroy = viewer.clone();
     let destroy = move |_| {
         set_models.update(|models| {
-            let id = model.id;
-            models.remove(model.id);
-            viewer_for_destroy.borrow_mut().remove_data(id);
+            for id in models.remove(model.id) {
+                viewer_for_destroy.borrow_mut().remove_data(id);
+            }
         });
     };
+    let has_children = move || models.with(|models| models.descendants(model.id).next().is_some());
+    let toggle_expanded = move |_| {
+        model.expanded.update(|expanded| *expanded = !*expanded);
+    };
+    // showing or hiding a node applies to the whole subtree
     let toggle_show = move |_| {
-        model.show.update(|show| *show = !*show);
+        let show = !model.show.get_untracked();
+        model.show.set(show);
+        models.with_untracked(|models| {
+            for child in models.descendants(model.id) {
+                child.show.set(show);
+            }
+        });
     };
     let toggle_edges = move |_| {
         model.show_edges.update(|show| *show = !*show);
     };
+    // a group node frames the models under it
+    let frame = {
+        let viewer = viewer.clone();
+        move |_| {
+            let ids = models.with_untracked(|models| {
+                let descendants = models.descendants(model.id).map(|m| m.id);
+                Vec::from_iter(std::iter::once(model.id).chain(descendants))
+            });
+            viewer.borrow_mut().frame_models(ids);
+        }
+    };
     const SHOW_CLASS_ATTR: &str = "w-full";
     const HIDE_CLASS_ATTR: &str = "w-full text-gray-400";
     view! {
-          <li class = "group/li w-full p-2 hover:bg-emerald-100 flex flex-col">
+          <li class = "group/li w-full p-2 hover:bg-emerald-100 flex flex-col" class:bg-emerald-200 = is_picked style = format!("padding-left: {}px;", 8 + 16 * model.depth)>
               <div class = "flex flex-1 items-center justify-center w-full">
+                  <button on:click = toggle_expanded class = "w-4 h-4 mr-1 shrink-0 flex items-center justify-center" class:invisible = move || !has_children() title = "Toggle Children">
+                      <svg viewBox="0 0 24 24" fill="none" class = "w-3 h-3 stroke-2 stroke-emerald-900" class:-rotate-90 = move || !model.expanded.get()><path stroke-linecap="round" stroke-linejoin="round" d="M6 9l6 6 6-6"/></svg>
+                  </button>
                   <label class = move || if model.show.get() { SHOW_CLASS_ATTR} else {HIDE_CLASS_ATTR}>
                       {move || model.name.get() }
                   </label>
@@ -227,7 +586,25 @@ pub fn Model(model: Model) -> impl IntoView {
                              }
                         }}
                     </button>
-                  <button on:click = write_to_local class = "group/button w-6 h-6 hover:bg-emerald-200 rounded-full items-center justify-center hidden group-hover/li:flex mr-1">
+                    <button on:click = frame class = "group/button w-6 h-6 hover:bg-emerald-200 rounded-full items-center justify-center hidden group-hover/li:flex mr-1" title="Frame Model">
+                        <svg class= "w-4 h-4 stroke-2 stroke-emerald-900" fill="none" viewBox="0 0 24 24" stroke-linecap="round" stroke-linejoin="round"><path d="M4 8V4h4M16 4h4v4M20 16v4h-4M8 20H4v-4"/><circle cx="12" cy="12" r="3"/></svg>
+                    </button>
+                  <select
+                      class = "w-14 h-6 mr-1 text-xs bg-transparent hidden group-hover/li:flex"
+                      title = "Download Format"
+                      on:change = move |ev| {
+                          if let Some(format) = io::Format::from_extension(&event_target_value(&ev)) {
+                              export_format.set(format);
+                          }
+                      }
+                  >
+                      {io::Format::EXPORTS.map(|format| view! {
+                          <option value = format.extension() selected = move || export_format.get() == format>
+                              {format.extension().to_uppercase()}
+                          </option>
+                      }).collect_view()}
+                  </select>
+                  <button on:click = write_to_local title = "Download" class = "group/button w-6 h-6 hover:bg-emerald-200 rounded-full items-center justify-center hidden group-hover/li:flex mr-1">
                       <svg viewBox="0 0 24 24" class = "w-4 h-4 fill-emerald-900"><path d="M18.948 11.112C18.511 7.67 15.563 5 12.004 5c-2.756 0-5.15 1.611-6.243 4.15-2.148.642-3.757 2.67-3.757 4.85 0 2.757 2.243 5 5 5h1v-2h-1c-1.654 0-3-1.346-3-3 0-1.404 1.199-2.757 2.673-3.016l.581-.102.192-.558C8.153 8.273 9.898 7 12.004 7c2.757 0 5 2.243 5 5v1h1c1.103 0 2 .897 2 2s-.897 2-2 2h-2v2h2c2.206 0 4-1.794 4-4a4.008 4.008 0 0 0-3.056-3.888z"></path><path d="M13.004 14v-4h-2v4h-3l4 5 4-5z"></path></svg>
                   </button>
                   <button on:click = destroy class = "group/button w-6 h-6 hover:bg-emerald-200 rounded-full items-center justify-center hidden group-hover/li:flex">
@@ -252,6 +629,57 @@ pub fn Model(model: Model) -> impl IntoView {
                                 class="w-6 h-6 border-none bg-transparent"
                                 title="Face Color"
                             />
+                            <select
+                                class="w-20 bg-transparent"
+                                class:hidden=move || model.attributes.with(|a| a.colors.is_none() && a.scalars.is_empty())
+                                title="Color Source"
+                                on:change=move |ev| model.color_source.set(ColorSource::from_value(&event_target_value(&ev)))
+                            >
+                                <option value=ColorSource::Face.value() selected=move || model.color_source.get() == ColorSource::Face>
+                                    "Face"
+                                </option>
+                                {move || model.attributes.with(|a| a.colors.is_some()).then(|| view! {
+                                    <option value=ColorSource::Vertex.value() selected=move || model.color_source.get() == ColorSource::Vertex>
+                                        "Vertex"
+                                    </option>
+                                })}
+                                {move || model.attributes.with(|a| {
+                                    a.scalars.iter().enumerate().map(|(idx, (name, _))| {
+                                        let source = ColorSource::Scalar(idx);
+                                        view! {
+                                            <option value=source.value() selected=move || model.color_source.get() == source>
+                                                {name.clone()}
+                                            </option>
+                                        }
+                                    }).collect_view()
+                                })}
+                            </select>
+                         </div>
+                         <div class="flex items-center space-x-2 mt-2 px-2 text-xs w-full">
+                            <span class="w-8">Shade:</span>
+                            <select
+                                class="w-28 bg-transparent"
+                                title="Shading"
+                                on:change=move |ev| model.shading.set(Shading::from_value(&event_target_value(&ev)))
+                            >
+                                {move || model.attributes.with(|a| a.normals.is_some()).then(|| view! {
+                                    <option value=Shading::File.value() selected=move || model.shading.get() == Shading::File>
+                                        {Shading::File.label()}
+                                    </option>
+                                })}
+                                {Shading::COMPUTED.map(|shading| view! {
+                                    <option value=shading.value() selected=move || model.shading.get() == shading>
+                                        {shading.label()}
+                                    </option>
+                                }).collect_view()}
+                            </select>
+                            <input type="number" min="0" max="180" step="5"
+                                class="w-12 bg-transparent"
+                                class:hidden=move || !matches!(model.shading.get(), Shading::Crease(_))
+                                prop:value=move || model.crease_angle.get()
+                                on:change=move |ev| model.crease_angle.set(event_target_value(&ev).parse::<f64>().unwrap_or(30.0).clamp(0.0, 180.0))
+                                title="Crease Angle (degrees)"
+                            />
                          </div>
                      }.into_any()
                  } else {
@@ -282,12 +710,240 @@ pub fn Model(model: Model) -> impl IntoView {
       }
 }
 
+/// A file being parsed, listed with its progress until its models are added.
+#[derive(Clone)]
+struct LoadingFile {
+    key: u32,
+    name: String,
+    /// fraction of the parsing done
+    progress: RwSignal<f64>,
+    canceller: SendWrapper<worker::Canceller>,
+    /// unset once the file is parsed on the page, which cannot be stopped
+    cancellable: RwSignal<bool>,
+}
+
+type LoadingFiles = RwSignal<Vec<LoadingFile>>;
+
+/// The files picked along with the meshes that the meshes may refer to.
+#[derive(Default)]
+struct Resources {
+    /// material libraries and glTF buffers
+    files: Vec<web_sys::File>,
+    /// textures, by file name
+    images: HashMap<String, web_sys::File>,
+}
+
+static NEXT_LOADING_KEY: AtomicU32 = AtomicU32::new(0);
+
+/// Loads the meshes of `files` as new models, the material libraries, glTF buffers and
+/// images among them being used as resources of the meshes. The objects of a file are
+/// merged into one model when `merge_objects` is set. Each mesh file is parsed in its own
+/// worker and listed in `loading` meanwhile, the failures being reported to `notifications`.
+async fn load_files(
+    files: Vec<web_sys::File>,
+    viewer: ViewerWrapper,
+    set_models: WriteSignal<Models>,
+    loading: LoadingFiles,
+    notifications: Notifications,
+    merge_objects: bool,
+) {
+    let extension = |file: &web_sys::File| {
+        let name = file.name();
+        name.rsplit_once('.')
+            .map(|(_, ext)| ext.to_ascii_lowercase())
+            .unwrap_or_default()
+    };
+    let mut resources = Resources::default();
+    let mut mesh_files = vec![];
+    for file in files {
+        let ext = extension(&file);
+        if ext == "mtl" || ext == "bin" {
+            resources.files.push(file);
+        } else if IMAGE_EXTENSIONS.contains(&ext.as_str()) {
+            resources.images.insert(file.name(), file);
+        } else {
+            mesh_files.push(file);
+        }
+    }
+
+    let resources = Rc::new(resources);
+    for file in mesh_files {
+        spawn_local(load_mesh_file(
+            file,
+            resources.clone(),
+            viewer.clone(),
+            set_models,
+            loading,
+            notifications,
+            merge_objects,
+        ));
+    }
+}
+
+async fn load_mesh_file(
+    file: web_sys::File,
+    resources: Rc<Resources>,
+    viewer: ViewerWrapper,
+    set_models: WriteSignal<Models>,
+    loading: LoadingFiles,
+    notifications: Notifications,
+    merge_objects: bool,
+) {
+    let stem = io::file_stem(&file.name()).to_owned();
+    let progress = RwSignal::new(0.0);
+    let (canceller, parsed) =
+        worker::parse_in_worker(&file, &resources.files, merge_objects, move |p| progress.set(p));
+    let key = NEXT_LOADING_KEY.fetch_add(1, Ordering::Relaxed);
+    let cancellable = RwSignal::new(true);
+    loading.update(|loading| {
+        loading.push(LoadingFile {
+            key,
+            name: file.name(),
+            progress,
+            canceller: SendWrapper::new(canceller),
+            cancellable,
+        })
+    });
+    let parsed = match parsed.await {
+        Err(worker::WorkerError::Unavailable) => {
+            cancellable.set(false);
+            parse_on_page(&file, &resources.files, merge_objects).await
+        }
+        Err(worker::WorkerError::Cancelled) => {
+            loading.update(|loading| loading.retain(|f| f.key != key));
+            return;
+        }
+        Err(worker::WorkerError::Failed(e)) => Err(e),
+        Ok(parsed) => Ok(parsed),
+    };
+    loading.update(|loading| loading.retain(|f| f.key != key));
+    let parsed = match parsed {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            notifications.read_error(&file.name(), &e);
+            return;
+        }
+    };
+
+    let single = parsed.len() == 1;
+    // models created for the meshes of the file, to find the parents back
+    let mut created: Vec<Model> = vec![];
+    for mut mesh in parsed {
+        let name = match mesh.name.take() {
+            Some(name) if !single => name,
+            _ => stem.clone(),
+        };
+        let id = viewer.borrow_mut().append_mesh(
+            &mesh.points,
+            &mesh.triangles,
+            None,
+        );
+        let id = match id {
+            Ok(id) => id,
+            Err(e) => {
+                notifications.error("Failed to display a mesh", format!("{}: {}", name, e));
+                continue;
+            }
+        };
+        {
+            let mut viewer = viewer.borrow_mut();
+            if let Some(polygons) = &mesh.polygons {
+                viewer.set_polygons(id, &polygons.arities);
+            }
+            if let Some(normals) = &mesh.attributes.normals {
+                viewer.set_corner_normals(id, normals);
+            }
+            if let Some(uvs) = &mesh.attributes.uvs {
+                viewer.set_uvs(id, uvs);
+            }
+        }
+        let texture = mesh
+            .material
+            .as_ref()
+            .and_then(|material| material.diffuse_texture.as_ref())
+            .and_then(|name| resources.images.get(name));
+        if let (Some(file), true) = (texture, mesh.attributes.uvs.is_some()) {
+            match read_image(file).await {
+                Some(bitmap) => viewer.borrow_mut().set_texture(id, Some(bitmap)),
+                None => notifications.warning(
+                    "Failed to load a texture",
+                    format!("{} is not a supported image", file.name()),
+                ),
+            }
+        }
+        let parent = mesh.parent.and_then(|idx| created.get(idx).cloned());
+        let mut model = Model::new(name, mesh, id);
+        if let Some(parent) = &parent {
+            model = model.child_of(parent);
+        }
+        created.push(model.clone());
+        set_models.update(|models| {
+            models.add(model);
+        });
+    }
+}
+
+/// Parses `file` on the page, for browsers where the worker cannot start.
+async fn parse_on_page(
+    file: &web_sys::File,
+    resources: &[web_sys::File],
+    merge_objects: bool,
+) -> Result<Vec<io::ImportedMesh>, io::ReadError> {
+    let mut resource_bytes = HashMap::new();
+    for resource in resources {
+        resource_bytes.insert(resource.name(), read_file_bytes(resource).await?);
+    }
+    let bytes = read_file_bytes(file).await?;
+    worker::parse(&file.name(), &bytes, &resource_bytes, merge_objects, |_| {})
+}
+
+/// Collects the files of the dropped `entries`, walking through the directories.
+async fn read_entries(entries: Vec<web_sys::FileSystemEntry>) -> Vec<web_sys::File> {
+    let mut stack = entries;
+    let mut files = vec![];
+    while let Some(entry) = stack.pop() {
+        if entry.is_file() {
+            let entry: web_sys::FileSystemFileEntry = entry.unchecked_into();
+            let promise = js_sys::Promise::new(&mut |resolve, reject| {
+                entry.file_with_callback_and_callback(&resolve, &reject);
+            });
+            if let Ok(file) = wasm_bindgen_futures::JsFuture::from(promise).await {
+                files.push(file.unchecked_into());
+            }
+        } else if entry.is_directory() {
+            let entry: web_sys::FileSystemDirectoryEntry = entry.unchecked_into();
+            let reader = entry.create_reader();
+            // a directory reader hands out its entries in batches until an empty one
+            loop {
+                let promise = js_sys::Promise::new(&mut |resolve, reject| {
+                    if let Err(e) = reader.read_entries_with_callback_and_callback(&resolve, &reject) {
+                        let _ = reject.call1(&JsValue::NULL, &e);
+                    }
+                });
+                let Ok(batch) = wasm_bindgen_futures::JsFuture::from(promise).await else {
+                    break;
+                };
+                let batch: js_sys::Array = batch.unchecked_into();
+                if batch.length() == 0 {
+                    break;
+                }
+                stack.extend(batch.iter().map(|entry| entry.unchecked_into()));
+            }
+        }
+    }
+    files
+}
+
 #[component]
 pub fn ModelList(
     models: ReadSignal<Models>,
     set_models: WriteSignal<Models>,
+    /// load the objects of a multi-object file as a single model
+    merge_objects: RwSignal<bool>,
 ) -> impl IntoView {
     let viewer = expect_context::<ViewerWrapper>();
+    let loading = expect_context::<LoadingFiles>();
+    let notifications = expect_context::<Notifications>();
     let (fix, set_fix) = signal(false);
     
     // Virtualization state
@@ -299,7 +955,7 @@ pub fn ModelList(
 
     // Computed visible range
     let visible_models = move || {
-        let all_models = models.get().0;
+        let all_models = models.with(listed_models);
         let total_count = all_models.len();
         
         let start_idx = (scroll_top.get() / ITEM_HEIGHT).floor() as usize;
@@ -316,6 +972,39 @@ pub fn ModelList(
         (subset, padding_top, padding_bottom)
     };
 
+    // the row of the model picked on the canvas is brought into view, expanding its parents
+    let list_ref = NodeRef::<leptos::html::Div>::new();
+    let picked = expect_context::<PickedPoint>();
+    Effect::new(move |_| {
+        let Some(id) = picked.with(|picked| picked.as_ref().map(|picked| picked.pick.model)) else {
+            return;
+        };
+        let index = untrack(|| {
+            models.with(|models| {
+                let find = |id| models.0.iter().find(|m| m.id == id);
+                let mut parent = find(id).and_then(|m| m.parent);
+                while let Some(m) = parent.and_then(find) {
+                    m.expanded.set(true);
+                    parent = m.parent;
+                }
+                listed_models(models).iter().position(|m| m.id == id)
+            })
+        });
+        let (Some(index), Some(list)) = (index, list_ref.get_untracked()) else {
+            return;
+        };
+        // once the rows of the expanded parents make the list tall enough
+        request_animation_frame(move || {
+            let top = index as f64 * ITEM_HEIGHT;
+            let scroll = list.scroll_top() as f64;
+            let height = list.client_height() as f64;
+            if top < scroll || top + ITEM_HEIGHT > scroll + height {
+                // centered, the scroll event moving the listed subset
+                list.set_scroll_top((top - (height - ITEM_HEIGHT) / 2.0).max(0.0) as i32);
+            }
+        });
+    });
+
     let fix_model = {
         let viewer = viewer.clone();
         move |_| {
@@ -339,18 +1028,33 @@ pub fn ModelList(
                     idx += 1;
                     n_points = points.len() / 3;
                 }
-                // TODO: gpf dependency was removed, fix functionality disabled
-                let raw_model: RawModel = (points, triangles);
-                set_models.update(|models| {
-                    models.hide();
-                });
+                let raw_model: RawModel =
+                    match mesh::repair::repair((points, triangles), &tri_in_shells) {
+                        Ok(raw_model) => raw_model,
+                        Err(e) => {
+                            notifications.error("Failed to repair the models", e);
+                            set_fix.set(false);
+                            return;
+                        }
+                    };
                 let id = viewer
                     .borrow_mut()
                     .append_mesh(&raw_model.0, &raw_model.1, None);
+                let id = match id {
+                    Ok(id) => id,
+                    Err(e) => {
+                        notifications.error("Failed to display the repaired mesh", e);
+                        set_fix.set(false);
+                        return;
+                    }
+                };
+                set_models.update(|models| {
+                    models.hide();
+                });
                 set_models.update(|models| {
                     models.add(Model::new(
                         format!("model{}", models.0.len()),
-                        raw_model,
+                        raw_model.into(),
                         id,
                     ));
                 });
@@ -359,35 +1063,29 @@ pub fn ModelList(
         }
     };
 
+    let scene_format = RwSignal::new(io::Format::Obj);
+    let export_scene = move |_| {
+        let visible = models.with(|models| {
+            Vec::from_iter(models.0.iter().filter(|m| m.show.get()).cloned())
+        });
+        export_models(scene_format.get(), "scene", &visible, true);
+    };
+
     let file_input: NodeRef<leptos::html::Input> = NodeRef::new();
     let viewer_clone = viewer.clone();
     let on_change = move |_| {
         if let Some(input_el) = file_input.get() {
             let input_el: &web_sys::HtmlInputElement = &input_el;
             if let Some(files) = input_el.files() {
-                for i in 0..files.length() {
-                    if let Some(file) = files.item(i) {
-                        if let Some(name) = file
-                            .name()
-                            .strip_suffix(".obj")
-                            .and_then(|s| Some(s.to_owned()))
-                        {
-                            let viewer_clone = viewer_clone.clone();
-                            spawn_local(async move {
-                                if let Ok(raw_model) = read_obj_from_file(file).await {
-                                    let id = viewer_clone.borrow_mut().append_mesh(
-                                        &raw_model.0,
-                                        &raw_model.1,
-                                        None,
-                                    );
-                                    set_models.update(|models| {
-                                        models.add(Model::new(name, raw_model, id));
-                                    });
-                                }
-                            });
-                        }
-                    }
-                }
+                let files = Vec::from_iter((0..files.length()).filter_map(|i| files.item(i)));
+                spawn_local(load_files(
+                    files,
+                    viewer_clone.clone(),
+                    set_models,
+                    loading,
+                    notifications,
+                    merge_objects.get_untracked(),
+                ));
             }
         }
     };
@@ -395,7 +1093,7 @@ pub fn ModelList(
     view! {
         <div class = "flex h-full flex-col w-full overflow-hidden">
             <div class="flex w-full mb-2 p-2 bg-gray-50 border-b border-gray-200 shrink-0">
-                <input type = "file" node_ref = file_input id = "add" on:change = on_change accept = ".obj" multiple class ="opacity-0 hidden"/>
+                <input type = "file" node_ref = file_input id = "add" on:change = on_change accept = ".obj,.stl,.ply,.gltf,.glb,.mtl,.bin,.png,.jpg,.jpeg,.bmp,.gif,.webp" multiple class ="opacity-0 hidden"/>
                 <label for = "add" class = "">
                     <svg viewBox="0 0 24 24" stroke-linecap ="round" class = "w-8 h-8 stroke-emerald-900 bg-emerald-100 stroke-1 hover:stroke-2 hover:bg-emerald-200 rounded-full cursor-pointer"><line x1="12" y1="5" x2="12" y2="19"></line><line x1="5" y1="12" x2="19" y2="12"></line></svg>
                 </label>
@@ -409,9 +1107,62 @@ pub fn ModelList(
                         if fix.get() { "Fixing" } else { "Fix " }
                     }}
                 </button>
+                <div class = "flex items-center ml-2" class:hidden = move || models.get().0.is_empty()>
+                    <select
+                        class = "w-14 h-7 text-xs bg-transparent"
+                        title = "Scene Format"
+                        on:change = move |ev| {
+                            if let Some(format) = io::Format::from_extension(&event_target_value(&ev)) {
+                                scene_format.set(format);
+                            }
+                        }
+                    >
+                        {io::Format::EXPORTS.map(|format| view! {
+                            <option value = format.extension() selected = move || scene_format.get() == format>
+                                {format.extension().to_uppercase()}
+                            </option>
+                        }).collect_view()}
+                    </select>
+                    <button
+                        class = "h-fit p-1 px-2 rounded-full border border-emerald-600 bg-emerald-100 hover:bg-emerald-200"
+                        title = "Download the visible models as one file"
+                        on:click = export_scene
+                    >
+                        "Export"
+                    </button>
+                </div>
+                <label class = "flex items-center ml-auto text-xs" title = "Load the objects of a file as one model">
+                    <input type = "checkbox" class = "mr-1"
+                        prop:checked = move || merge_objects.get()
+                        on:change = move |ev| merge_objects.set(event_target_checked(&ev))
+                    />
+                    "Merge objects"
+                </label>
             </div>
 
+            <ul class = "w-full shrink-0 divide-y divide-gray-100 bg-white" class:hidden = move || loading.with(|l| l.is_empty())>
+                <For
+                    each = move || loading.get()
+                    key = |file| file.key
+                    let:file
+                >
+                    <li class = "flex items-center gap-2 px-4 py-2 text-sm text-gray-700">
+                        <span class = "w-28 truncate" title = file.name.clone()>{file.name.clone()}</span>
+                        <progress class = "flex-1 h-2 accent-emerald-600" max = "1" value = move || file.progress.get()/>
+                        <button
+                            class = "w-6 h-6 flex items-center justify-center hover:bg-emerald-200 rounded-full"
+                            class:invisible = move || !file.cancellable.get()
+                            title = "Cancel"
+                            on:click = move |_| file.canceller.cancel()
+                        >
+                            <svg viewBox="0 0 24 24" stroke-linecap="round" class = "w-4 h-4 stroke-2 stroke-emerald-900"><line x1="18" y1="6" x2="6" y2="18"></line><line x1="6" y1="6" x2="18" y2="18"></line></svg>
+                        </button>
+                    </li>
+                </For>
+            </ul>
+
             <div 
+                node_ref = list_ref
                 class = "flex-1 w-full divide-y divide-gray-100 shadow bg-white overflow-y-auto"
                 on:scroll = move |ev| {
                      let target: web_sys::HtmlElement = event_target(&ev);
@@ -440,29 +1191,328 @@ pub fn ModelList(
     }
 }
 
+/// The models listed in the model list, the descendants of collapsed models being left out.
+fn listed_models(models: &Models) -> Vec<Model> {
+    // ids of the models whose children are not listed
+    let mut folded = vec![];
+    let mut listed = vec![];
+    for m in &models.0 {
+        if m.parent.is_some_and(|parent| folded.contains(&parent)) {
+            folded.push(m.id);
+            continue;
+        }
+        if !m.expanded.get() {
+            folded.push(m.id);
+        }
+        listed.push(m.clone());
+    }
+    listed
+}
+
 // Helper to calculate height of the rendered subset (not strictly needed if we use padding bottom correctly, 
 // but let's stick to the padding top/bottom approach which is robust)
 fn subset_height(count: usize) -> f64 {
     count as f64 * 60.0 // ITEM_HEIGHT matches constant inside ModelList
 }
 
+/// Settings of the camera over the canvas, with the view cube.
+#[component]
+pub fn ViewToolbar() -> impl IntoView {
+    let viewer = expect_context::<ViewerWrapper>();
+    let navigation = RwSignal::new(viewer.borrow().navigation());
+    let sensitivity = RwSignal::new(viewer.borrow().sensitivity(navigation.get_untracked()));
+    let orthographic = RwSignal::new(viewer.borrow().orthographic());
+    let reversed_z = viewer.borrow().reversed_z();
+    let frame_new_models = viewer.borrow().frame_new_models();
+
+    let on_navigation = {
+        let viewer = viewer.clone();
+        move |ev| {
+            if let Some(mode) = Navigation::from_value(&event_target_value(&ev)) {
+                let mut viewer = viewer.borrow_mut();
+                viewer.set_navigation(mode);
+                navigation.set(mode);
+                // each mode keeps its own sensitivity
+                sensitivity.set(viewer.sensitivity(mode));
+            }
+        }
+    };
+    let on_sensitivity = {
+        let viewer = viewer.clone();
+        move |ev| {
+            if let Ok(value) = event_target_value(&ev).parse::<f32>() {
+                viewer
+                    .borrow_mut()
+                    .set_sensitivity(navigation.get_untracked(), value);
+                sensitivity.set(value);
+            }
+        }
+    };
+    let toggle_projection = {
+        let viewer = viewer.clone();
+        move |_| {
+            let ortho = !orthographic.get_untracked();
+            viewer.borrow_mut().set_orthographic(ortho);
+            orthographic.set(ortho);
+        }
+    };
+    let on_reversed_z = {
+        let viewer = viewer.clone();
+        move |ev| viewer.borrow_mut().set_reversed_z(event_target_checked(&ev))
+    };
+    let fit_all = {
+        let viewer = viewer.clone();
+        move |_| viewer.borrow_mut().fit_all()
+    };
+    let on_frame_new_models =
+        move |ev| viewer.borrow_mut().set_frame_new_models(event_target_checked(&ev));
+
+    view! {
+        <div class = "absolute top-2 left-2 z-10 flex items-center gap-2 px-2 py-1 rounded-lg bg-white/80 shadow text-xs text-gray-700">
+            <select class = "bg-transparent" title = "Navigation" on:change = on_navigation>
+                {Navigation::ALL.map(|mode| view! {
+                    <option value = mode.value() selected = move || navigation.get() == mode>
+                        {mode.label()}
+                    </option>
+                }).collect_view()}
+            </select>
+            <input type = "range" min = "0.1" max = "5.0" step = "0.1"
+                class = "w-20 accent-emerald-600"
+                title = move || format!("Sensitivity \u{d7}{:.1}", sensitivity.get())
+                prop:value = move || sensitivity.get()
+                on:input = on_sensitivity
+            />
+            <button
+                class = "w-12 px-1 rounded border border-gray-300 hover:bg-emerald-100"
+                title = "Switch between the perspective and orthographic projections"
+                on:click = toggle_projection
+            >
+                {move || if orthographic.get() { "Ortho" } else { "Persp" }}
+            </button>
+            <label
+                class = "flex items-center gap-1"
+                title = "Keep more depth precision far from the camera, against flickering faces"
+            >
+                <input type = "checkbox" class = "accent-emerald-600" checked = reversed_z on:change = on_reversed_z/>
+                "Reversed-Z"
+            </label>
+            <button
+                class = "px-1 rounded border border-gray-300 hover:bg-emerald-100"
+                title = "Frame every model"
+                on:click = fit_all
+            >
+                "Fit all"
+            </button>
+            <label class = "flex items-center gap-1" title = "Frame the whole scene again whenever a model is loaded">
+                <input type = "checkbox" class = "accent-emerald-600" checked = frame_new_models on:change = on_frame_new_models/>
+                "Frame new"
+            </label>
+        </div>
+        <ViewCube z_up = Signal::derive(move || navigation.get() == Navigation::TurntableZUp)/>
+    }
+}
+
+/// A cube turning with the scene in the corner of the canvas, whose faces move the camera to
+/// the view of that side.
+#[component]
+pub fn ViewCube(
+    /// whether the Z axis of the scene is the vertical one, which changes the sides
+    z_up: Signal<bool>,
+) -> impl IntoView {
+    let viewer = expect_context::<ViewerWrapper>();
+    let rotation = RwSignal::new(cgmath::Quaternion::new(1.0, 0.0, 0.0, 0.0));
+    viewer
+        .borrow_mut()
+        .on_rotate(move |quat| rotation.set(quat));
+
+    view! {
+        <div class = "absolute top-2 right-2 z-10 flex flex-col items-center gap-1">
+            <div class = "relative w-24 h-24">
+                {ViewPreset::FACES.map(|preset| {
+                    let viewer = viewer.clone();
+                    view! {
+                        <button
+                            class = "absolute left-5 top-5 w-14 h-14 flex items-center justify-center border border-gray-400 bg-white/80 text-[10px] font-semibold text-gray-700 hover:bg-emerald-100 select-none"
+                            style:transform = move || face_transform(rotation.get(), preset, z_up.get())
+                            style:backface-visibility = "hidden"
+                            title = format!("{} view", preset.label())
+                            on:click = move |_| viewer.borrow_mut().set_view(preset)
+                        >
+                            {preset.label()}
+                        </button>
+                    }
+                }).collect_view()}
+            </div>
+            <button
+                class = "px-2 rounded border border-gray-300 bg-white/80 text-xs text-gray-700 hover:bg-emerald-100"
+                title = "Isometric view"
+                on:click = move |_| viewer.borrow_mut().set_view(ViewPreset::Isometric)
+            >
+                {ViewPreset::Isometric.label()}
+            </button>
+        </div>
+    }
+}
+
+/// CSS transform of the face of the view cube for `preset`, the scene being rotated by
+/// `rotation` in eye space.
+fn face_transform(rotation: cgmath::Quaternion<f32>, preset: ViewPreset, z_up: bool) -> String {
+    use cgmath::{Matrix4, Rotation};
+    // the face is turned from the front of the screen to its side, upright in its view
+    let face = rotation * preset.rotation(z_up).invert();
+    // the Y axis of CSS goes down
+    let flip = Matrix4::from_nonuniform_scale(1.0, -1.0, 1.0);
+    let matrix: [[f32; 4]; 4] = (flip * Matrix4::from(face) * flip).into();
+    let values = Vec::from_iter(matrix.as_flattened().iter().map(|v| v.to_string()));
+    // half the size of the face
+    format!("matrix3d({}) translateZ(28px)", values.join(","))
+}
+
 #[component]
 pub fn App(
     canvas: NodeRef<leptos::html::Canvas>,
     viewer: ViewerWrapper,
+    /// shown over the canvas, shared with the render loop
+    notifications: Notifications,
 ) -> impl IntoView {
     web_sys::console::log_1(&"App component rendering".into());
     let (models, set_models) = signal(Models::new());
+    provide_context(models);
     provide_context(set_models);
     provide_context(viewer.clone());
+    let merge_objects = RwSignal::new(false);
+    let loading: LoadingFiles = RwSignal::new(vec![]);
+    provide_context(loading);
+    provide_context(notifications);
+    let picked: PickedPoint = RwSignal::new(None);
+    provide_context(picked);
+
+    // a click picks what is under the cursor, unlike the end of a drag
+    let pressed_at = StoredValue::new((0.0, 0.0));
+    let on_mouse_down = move |ev: web_sys::MouseEvent| {
+        pressed_at.set_value((ev.offset_x() as f64, ev.offset_y() as f64));
+    };
+    let on_click = {
+        let viewer = viewer.clone();
+        move |ev: web_sys::MouseEvent| {
+            let (x, y) = (ev.offset_x() as f64, ev.offset_y() as f64);
+            let (pressed_x, pressed_y) = pressed_at.get_value();
+            if (x - pressed_x).hypot(y - pressed_y) > 3.0 {
+                return;
+            }
+            let ratio = web_sys::window().map_or(1.0, |window| window.device_pixel_ratio());
+            let pending =
+                Viewer::pick(&viewer, winit::dpi::PhysicalPosition::new(x * ratio, y * ratio));
+            spawn_local(async move {
+                picked.set(pending.await.map(|pick| Picked { pick, x, y }));
+            });
+        }
+    };
+    let tooltip = move || {
+        let picked = picked.get()?;
+        let model = models.with(|models| {
+            models.0.iter().find(|m| m.id == picked.pick.model).cloned()
+        })?;
+        let (face, vertices, closest) = picked_face(&model, &picked.pick)?;
+        let vertices = Vec::from_iter(vertices.iter().map(|v| v.to_string())).join(", ");
+        let [x, y, z] = picked.pick.position;
+        Some(view! {
+            <div
+                class = "absolute z-10 px-2 py-1 rounded bg-white/90 shadow text-xs text-gray-700 pointer-events-none"
+                style:left = format!("{}px", picked.x + 12.0)
+                style:top = format!("{}px", picked.y + 12.0)
+            >
+                <div class = "font-semibold">{model.name.get()}</div>
+                <div>{format!("Face {}: vertices {}", face, vertices)}</div>
+                <div>{format!("Closest vertex {}", closest)}</div>
+                <div class = "text-gray-500">{format!("({:.4}, {:.4}, {:.4})", x, y, z)}</div>
+            </div>
+        })
+    };
+
+    // files and folders dropped onto the canvas are loaded like the picked ones
+    let dragging = RwSignal::new(false);
+    let on_drag_over = move |ev: web_sys::DragEvent| {
+        ev.prevent_default();
+        if let Some(transfer) = ev.data_transfer() {
+            transfer.set_drop_effect("copy");
+        }
+        dragging.set(true);
+    };
+    let on_drag_leave = move |ev: web_sys::DragEvent| {
+        // leaving for a child element is not leaving the drop target
+        let target: Option<web_sys::Node> = ev.current_target().and_then(|t| t.dyn_into().ok());
+        let related: Option<web_sys::Node> = ev.related_target().and_then(|t| t.dyn_into().ok());
+        if target.is_none_or(|target| !target.contains(related.as_ref())) {
+            dragging.set(false);
+        }
+    };
+    let on_drop = move |ev: web_sys::DragEvent| {
+        ev.prevent_default();
+        dragging.set(false);
+        let Some(transfer) = ev.data_transfer() else {
+            return;
+        };
+        // the entries must be taken before the end of the event, the files can be read after
+        let items = transfer.items();
+        let entries = Vec::from_iter(
+            (0..items.length())
+                .filter_map(|i| items.get(i))
+                .filter_map(|item| item.webkit_get_as_entry().ok().flatten()),
+        );
+        // browsers without entries only give the dropped files
+        let dropped = transfer
+            .files()
+            .map(|files| Vec::from_iter((0..files.length()).filter_map(|i| files.item(i))))
+            .unwrap_or_default();
+        let viewer = viewer.clone();
+        spawn_local(async move {
+            let files = if entries.is_empty() {
+                dropped
+            } else {
+                read_entries(entries).await
+            };
+            load_files(
+                files,
+                viewer,
+                set_models,
+                loading,
+                notifications,
+                merge_objects.get_untracked(),
+            )
+            .await;
+        });
+    };
 
     view! {
         <div class = "flex w-full h-full overflow-hidden">
             <div class="w-80 h-full bg-gray-50 border-r border-gray-200 shadow-md z-10 flex flex-col">
-                <ModelList models set_models/>
+                <ModelList models set_models merge_objects/>
             </div>
-            <div class = "flex-1 h-full relative">
-                <canvas node_ref = canvas class = "w-full h-full block"/>
+            <div
+                class = "flex-1 h-full relative"
+                on:dragenter = on_drag_over
+                on:dragover = on_drag_over
+                on:dragleave = on_drag_leave
+                on:drop = on_drop
+            >
+                // the right button pans the view instead of opening the context menu
+                <canvas
+                    node_ref = canvas
+                    class = "w-full h-full block"
+                    on:contextmenu = |ev: web_sys::MouseEvent| ev.prevent_default()
+                    on:mousedown = on_mouse_down
+                    on:click = on_click
+                />
+                {tooltip}
+                <div
+                    class = "absolute inset-2 flex items-center justify-center rounded-lg border-4 border-dashed border-emerald-500 bg-emerald-100/50 text-emerald-900 text-lg pointer-events-none"
+                    class:hidden = move || !dragging.get()
+                >
+                    "Drop files or folders to load them"
+                </div>
+                <ViewToolbar/>
+                <Toasts notifications/>
             </div>
         </div>
     }
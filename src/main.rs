@@ -9,10 +9,12 @@ use send_wrapper::SendWrapper;
 use view::render::render::Renderer;
 use view::render::viewer::{self, MousePressed, Viewer};
 use view::ViewerWrapper;
+use view::notifications::Notifications;
 use web_sys::HtmlCanvasElement;
 use winit::application::ApplicationHandler;
 use winit::event::WindowEvent;
 use winit::event_loop::ActiveEventLoop;
+use winit::keyboard::PhysicalKey;
 use winit::platform::web::WindowAttributesExtWebSys;
 use winit::window::Window;
 use winit::{event, event_loop};
@@ -29,14 +31,16 @@ struct WinitApp {
     window: Option<Window>,
     canvas: Option<HtmlCanvasElement>,
     viewer: Rc<RefCell<Viewer>>,
+    notifications: Notifications,
 }
 
 impl WinitApp {
-    fn new(viewer: Rc<RefCell<Viewer>>) -> Self {
+    fn new(viewer: Rc<RefCell<Viewer>>, notifications: Notifications) -> Self {
         Self {
             window: None,
             canvas: None,
             viewer,
+            notifications,
         }
     }
 }
@@ -83,7 +87,15 @@ impl ApplicationHandler<AppEvent> for WinitApp {
     ) {
         match event {
             WindowEvent::KeyboardInput { event, .. } => {
-                web_sys::console::log_1(&format!("{:?}", event).into());
+                if let PhysicalKey::Code(code) = event.physical_key {
+                    self.viewer
+                        .borrow_mut()
+                        .key_input(code, event.state == event::ElementState::Pressed);
+                }
+            }
+
+            WindowEvent::Focused(false) => {
+                self.viewer.borrow_mut().release_keys();
             }
 
             WindowEvent::CursorMoved { position, .. } => {
@@ -91,27 +103,36 @@ impl ApplicationHandler<AppEvent> for WinitApp {
             }
 
             WindowEvent::MouseInput { state, button, .. } => match state {
-                event::ElementState::Pressed => match button {
-                    event::MouseButton::Left => {
-                        self.viewer.borrow_mut().pressed_state = MousePressed::Left(None);
+                event::ElementState::Pressed => {
+                    match button {
+                        event::MouseButton::Left => {
+                            self.viewer.borrow_mut().pressed_state = MousePressed::Left(None);
+                        }
+                        event::MouseButton::Right | event::MouseButton::Middle => {
+                            self.viewer.borrow_mut().pressed_state = MousePressed::Right(None);
+                        }
+                        _ => {}
                     }
-                    _ => {}
-                },
+                }
                 event::ElementState::Released => {
                     self.viewer.borrow_mut().pressed_state = MousePressed::None;
                 }
             },
 
-            WindowEvent::MouseWheel { delta, .. } => match delta {
-                event::MouseScrollDelta::PixelDelta(size) => {
-                    self.viewer.borrow_mut().mouse_scroll(size.y);
-                }
-                _ => {}
-            },
+            WindowEvent::MouseWheel { delta, .. } => {
+                // browsers scrolling by lines, like Firefox with a mouse wheel, give line deltas
+                const PIXELS_PER_LINE: f64 = 40.0;
+                let delta_y = match delta {
+                    event::MouseScrollDelta::LineDelta(_, y) => y as f64 * PIXELS_PER_LINE,
+                    event::MouseScrollDelta::PixelDelta(size) => size.y,
+                };
+                self.viewer.borrow_mut().mouse_scroll(delta_y);
+            }
 
             WindowEvent::RedrawRequested => {
                 if let Err(msg) = self.viewer.borrow_mut().render() {
                     web_sys::console::error_1(&format!("failed to render because {:?}", msg).into());
+                    self.notifications.error("Failed to render", format!("{:#}", msg));
                 } else {
                     if let Some(window) = self.window.as_ref() {
                         window.request_redraw();
@@ -128,6 +149,7 @@ impl ApplicationHandler<AppEvent> for WinitApp {
                     if let Some(canvas) = &self.canvas {
                         let canvas = canvas.clone();
                         let viewer = self.viewer.clone();
+                        let notifications = self.notifications;
                          spawn_local(async move {
                             match Renderer::new(canvas.clone(), size.width, size.height).await {
                                 Ok(r) => {
@@ -135,6 +157,7 @@ impl ApplicationHandler<AppEvent> for WinitApp {
                                 }
                                 Err(e) => {
                                     web_sys::console::error_1(&format!("create viewer failed by {:?}", e).into());
+                                    notifications.error("Failed to start the GPU renderer", format!("{:#}", e));
                                 }
                             }
                         })
@@ -153,6 +176,10 @@ fn main() -> Result<()> {
     let render: Rc<RefCell<Option<Renderer>>> = Default::default();
     let viewer = Rc::new(RefCell::new(viewer::Viewer::new(render.clone())));
     let viewer_wrapper: ViewerWrapper = SendWrapper::new(viewer.clone());
+    let notifications = Notifications::new();
+    viewer.borrow_mut().on_upload_error(move |e| {
+        notifications.error("Failed to upload a mesh to the GPU", e)
+    });
     
     let event_loop = event_loop::EventLoop::<AppEvent>::with_user_event().build()?;
     let proxy = event_loop.create_proxy();
@@ -160,24 +187,23 @@ fn main() -> Result<()> {
     let canvas_ref: NodeRef<Canvas> = NodeRef::new();
     
     mount_to_body(move || {
-        let canvas_ref = canvas_ref.clone();
         let proxy = proxy.clone();
         
         Effect::new(move |_| {
             if let Some(c) = canvas_ref.get() {
-                 let canvas_el: HtmlCanvasElement = c.into();
+                 let canvas_el: HtmlCanvasElement = c;
                  web_sys::console::log_1(&"Canvas found in Effect, sending to proxy".into());
                  let _ = proxy.send_event(AppEvent::CanvasReady(canvas_el));
             }
         });
         
-        view! { <view::App canvas=canvas_ref viewer=viewer_wrapper.clone() /> } 
+        view! { <view::App canvas=canvas_ref viewer=viewer_wrapper.clone() notifications /> } 
     });
     web_sys::console::log_1(&"Leptos app mounted".into());
 
     event_loop.set_control_flow(event_loop::ControlFlow::Wait);
 
-    let mut app = WinitApp::new(viewer);
+    let mut app = WinitApp::new(viewer, notifications);
     web_sys::console::log_1(&"Starting event loop".into());
     event_loop.run_app(&mut app)?;
 
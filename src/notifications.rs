@@ -0,0 +1,150 @@
+//! Messages shown over the canvas, so that failures do not end up in the console only.
+
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use leptos::prelude::*;
+
+use crate::io::ReadError;
+
+static NEXT_NOTIFICATION_ID: AtomicU32 = AtomicU32::new(0);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Level {
+    Error,
+    Warning,
+}
+
+/// A message listed until the user dismisses it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Notification {
+    id: u32,
+    pub level: Level,
+    pub title: String,
+    pub detail: String,
+    /// name of the file the message is about
+    pub file: Option<String>,
+    /// line of `file` the message is about, starting at 1
+    pub line: Option<usize>,
+    /// number of times the same message was posted
+    pub count: usize,
+}
+
+/// The messages of the app, to copy into whatever can fail.
+#[derive(Clone, Copy)]
+pub struct Notifications(RwSignal<Vec<Notification>>);
+
+impl Default for Notifications {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Notifications {
+    pub fn new() -> Self {
+        Self(RwSignal::new(vec![]))
+    }
+
+    pub fn error(&self, title: impl Into<String>, detail: impl Into<String>) {
+        self.push(Level::Error, title.into(), detail.into(), None, None);
+    }
+
+    pub fn warning(&self, title: impl Into<String>, detail: impl Into<String>) {
+        self.push(Level::Warning, title.into(), detail.into(), None, None);
+    }
+
+    /// Reports that the meshes of the file called `file` could not be read.
+    pub fn read_error(&self, file: &str, error: &ReadError) {
+        self.push(
+            Level::Error,
+            "Failed to load a file".to_owned(),
+            error.message.clone(),
+            Some(file.to_owned()),
+            error.line,
+        );
+    }
+
+    pub fn dismiss(&self, id: u32) {
+        self.0.update(|list| list.retain(|n| n.id != id));
+    }
+
+    /// Adds a message, or counts it again if the same one is still listed.
+    fn push(
+        &self,
+        level: Level,
+        title: String,
+        detail: String,
+        file: Option<String>,
+        line: Option<usize>,
+    ) {
+        self.0.update(|list| {
+            let same = list.iter_mut().find(|n| {
+                n.level == level
+                    && n.title == title
+                    && n.detail == detail
+                    && n.file == file
+                    && n.line == line
+            });
+            if let Some(same) = same {
+                same.count += 1;
+                return;
+            }
+            list.push(Notification {
+                id: NEXT_NOTIFICATION_ID.fetch_add(1, Ordering::Relaxed),
+                level,
+                title,
+                detail,
+                file,
+                line,
+                count: 1,
+            });
+        });
+    }
+}
+
+#[component]
+pub fn Toasts(notifications: Notifications) -> impl IntoView {
+    view! {
+        <div class = "absolute bottom-4 right-4 z-20 flex w-96 max-w-[80%] flex-col gap-2">
+            <For
+                each = move || notifications.0.get()
+                key = |n| (n.id, n.count)
+                let:notification
+            >
+                {
+                    let error = notification.level == Level::Error;
+                    let location = notification.file.clone().map(|file| match notification.line {
+                        Some(line) => format!("{}, line {}", file, line),
+                        None => file,
+                    });
+                    let id = notification.id;
+                    view! {
+                        <div
+                            role = "alert"
+                            class = "flex items-start gap-2 p-3 rounded-lg border-l-4 bg-white shadow-md text-sm"
+                            class:border-red-500 = error
+                            class:border-amber-400 = !error
+                        >
+                            <div class = "flex-1 min-w-0">
+                                <div class = "font-semibold" class:text-red-800 = error class:text-amber-800 = !error>
+                                    {notification.title.clone()}
+                                    {(notification.count > 1).then(|| format!(" (\u{d7}{})", notification.count))}
+                                </div>
+                                {location.map(|location| view! {
+                                    <div class = "text-xs text-gray-500 truncate" title = location.clone()>{location.clone()}</div>
+                                })}
+                                <div class = "text-gray-700 break-words">{notification.detail.clone()}</div>
+                            </div>
+                            <button
+                                class = "w-6 h-6 flex shrink-0 items-center justify-center hover:bg-gray-100 rounded-full"
+                                title = "Dismiss"
+                                on:click = move |_| notifications.dismiss(id)
+                            >
+                                <svg viewBox="0 0 24 24" stroke-linecap="round" class = "w-4 h-4 stroke-2 stroke-gray-700"><line x1="18" y1="6" x2="6" y2="18"></line><line x1="6" y1="6" x2="18" y2="18"></line></svg>
+                            </button>
+                        </div>
+                    }
+                }
+            </For>
+        </div>
+    }
+}
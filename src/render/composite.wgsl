@@ -0,0 +1,27 @@
+// Resolves the weighted blended order-independent transparency drawn by `fs_transparent` in
+// shader.wgsl: the accumulated colors are averaged and blended over the opaque models.
+
+@group(0) @binding(0)
+var accum_texture: texture_2d<f32>;
+@group(0) @binding(1)
+var weight_texture: texture_2d<f32>;
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    // a triangle covering the screen
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+@fragment
+fn fs_main(@builtin(position) frag_coord: vec4<f32>) -> @location(0) vec4<f32> {
+    let pixel = vec2<i32>(frag_coord.xy);
+    let accum = textureLoad(accum_texture, pixel, 0);
+    let weight = textureLoad(weight_texture, pixel, 0).r;
+    // product of the transparencies of the fragments
+    let revealage = accum.a;
+    if (revealage >= 1.0) {
+        discard;
+    }
+    return vec4<f32>(accum.rgb / max(weight, 1e-5), 1.0 - revealage);
+}
@@ -1,10 +1,12 @@
 use cgmath::Vector3;
 
+#[allow(clippy::module_inception)]
 pub mod render;
 pub mod view_core;
 mod view_data;
 pub mod viewer;
 
+#[derive(Clone, Copy, PartialEq)]
 pub(crate) struct BBox {
     min: Vector3<f32>,
     max: Vector3<f32>,
@@ -36,6 +38,36 @@ impl BBox {
         self.merge(&other.max);
     }
 
+    /// Whether no point was merged into the box.
+    fn is_empty(&self) -> bool {
+        self.min.x > self.max.x
+    }
+
+    /// The eight corners of the box.
+    fn corners(&self) -> [Vector3<f32>; 8] {
+        std::array::from_fn(|k| {
+            Vector3::new(
+                if k & 1 == 0 { self.min.x } else { self.max.x },
+                if k & 2 == 0 { self.min.y } else { self.max.y },
+                if k & 4 == 0 { self.min.z } else { self.max.z },
+            )
+        })
+    }
+
+    /// Whether the ray from `origin` along `dir` goes through the box.
+    fn hit_by_ray(&self, origin: &Vector3<f32>, dir: &Vector3<f32>) -> bool {
+        let mut t_min = 0.0f32;
+        let mut t_max = f32::INFINITY;
+        for i in 0..3 {
+            let inv = 1.0 / dir[i];
+            let t0 = (self.min[i] - origin[i]) * inv;
+            let t1 = (self.max[i] - origin[i]) * inv;
+            t_min = t_min.max(t0.min(t1));
+            t_max = t_max.min(t0.max(t1));
+        }
+        t_min <= t_max
+    }
+
     #[inline]
     fn max_len(&self) -> f32 {
         let d = self.max - self.min;
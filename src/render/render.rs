@@ -10,19 +10,36 @@ pub struct Renderer {
     pub device: Device,
     pub queue: Queue,
     pub depth_texture_view: TextureView,
+    /// whether the GPU can render and blend into [`TRANSPARENCY_FORMAT`], which the
+    /// order-independent transparency needs, the transparent models being sorted otherwise
+    pub oit_supported: bool,
+    /// the single pixel the model ids are drawn into when picking, see
+    /// [`Renderer::create_pick_textures`]
+    pub pick_texture: wgpu::Texture,
+    pub pick_depth_texture_view: TextureView,
 }
 
+/// Format of the targets accumulating the transparent models.
+pub(crate) const TRANSPARENCY_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+/// Format of the target of the picking pass. WebGL2 only reads integer pixels back as four
+/// 32-bit components.
+pub(crate) const PICK_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba32Uint;
+
 impl Renderer {
     pub async fn new(canvas: HtmlCanvasElement, width: u32, height: u32) -> Result<Self> {
         let instance = wgpu::Instance::default();
-        let surface = instance.create_surface(SurfaceTarget::Canvas(canvas))?;
+        let surface = instance
+            .create_surface(SurfaceTarget::Canvas(canvas))
+            .map_err(|e| anyhow::anyhow!("Failed to create the canvas surface: {}", e))?;
         let adapter = instance
             .request_adapter(&wgpu::RequestAdapterOptions {
                 power_preference: wgpu::PowerPreference::default(),
                 force_fallback_adapter: false,
                 compatible_surface: Some(&surface),
             })
-            .await?;
+            .await
+            .map_err(|e| anyhow::anyhow!("No suitable GPU adapter: {}", e))?;
         let (device, queue) = adapter
             .request_device(
                 &DeviceDescriptor {
@@ -37,7 +54,15 @@ impl Renderer {
                 },
             )
             .await
-            .or_else(|_| Err(anyhow::anyhow!("Failed to create device")))?;
+            .map_err(|e| anyhow::anyhow!("Failed to create device: {}", e))?;
+
+        // WebGL2 renders to float textures only with EXT_color_buffer_float
+        let transparency_features = adapter.get_texture_format_features(TRANSPARENCY_FORMAT);
+        let oit_supported = transparency_features.allowed_usages.contains(
+            wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        ) && transparency_features
+            .flags
+            .contains(wgpu::TextureFormatFeatureFlags::BLENDABLE);
 
         let surface_caps = surface.get_capabilities(&adapter);
         let texture_format = surface_caps.formats[0];
@@ -56,12 +81,16 @@ impl Renderer {
         surface.configure(&device, &config);
 
         let depth_texture_view = Self::create_depth_texture(&config, &device);
+        let (pick_texture, pick_depth_texture_view) = Self::create_pick_textures(&device);
         Ok(Self {
             surface,
             device,
             config,
             queue,
             depth_texture_view,
+            oit_supported,
+            pick_texture,
+            pick_depth_texture_view,
         })
     }
 
@@ -93,6 +122,41 @@ impl Renderer {
         depth_texture.create_view(&wgpu::TextureViewDescriptor::default())
     }
 
+    /// Creates the target of the picking pass and its depth, a single pixel that the
+    /// projection moves under the cursor.
+    fn create_pick_textures(device: &Device) -> (wgpu::Texture, TextureView) {
+        let create = |label, format, usage| {
+            device.create_texture(&wgpu::TextureDescriptor {
+                label: Some(label),
+                size: wgpu::Extent3d {
+                    width: 1,
+                    height: 1,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage,
+                view_formats: &[],
+            })
+        };
+        let pick_texture = create(
+            "pick_texture",
+            PICK_FORMAT,
+            wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+        );
+        let depth_texture = create(
+            "pick_depth_texture",
+            wgpu::TextureFormat::Depth32Float,
+            wgpu::TextureUsages::RENDER_ATTACHMENT,
+        );
+        (
+            pick_texture,
+            depth_texture.create_view(&wgpu::TextureViewDescriptor::default()),
+        )
+    }
+
     pub fn resize(&mut self, w: u32, h: u32) {
         self.config.width = w;
         self.config.height = h;
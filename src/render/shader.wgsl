@@ -1,7 +1,11 @@
 struct VertexInput {
     @location(0) point: vec3<f32>,
-    @location(1) normal: vec3<f32>,
-    @builtin(vertex_index) vertex_index: u32,
+    // the fourth component of the normal is padding, the alpha of the color is unused
+    @location(1) normal: vec4<f32>,
+    @location(2) color: vec4<f32>,
+    // bits 3 and 4: corner of the vertex in its triangles, bits 0 to 2: hidden edges
+    @location(3) corner_flags: u32,
+    @location(4) uv: vec2<f32>,
 }
 
 struct VertexOutput {
@@ -9,6 +13,9 @@ struct VertexOutput {
     @location(0) pos_in_eye: vec3<f32>,
     @location(1) normal: vec3<f32>,
     @location(2) barycentric: vec3<f32>,
+    @location(3) color: vec3<f32>,
+    @location(4) @interpolate(flat) hidden_edges: u32,
+    @location(5) uv: vec2<f32>,
 }
 
 // Because Downlevel flags BUFFER_BINDINGS_NOT_16_BYTE_ALIGNED are required but not supported on web
@@ -19,9 +26,11 @@ struct Material {
     ks: vec4<f32>,
     edge_color: vec4<f32>,
     edge_width: f32,
-    _pad1: f32,
-    _pad2: f32,
-    _pad3: f32,
+    vertex_color: f32,
+    textured: f32,
+    flat_shading: f32,
+    // id of the model plus one, 0 being the background of the picking pass
+    pick_id: u32,
 }
 
 @group(0) @binding(0)
@@ -39,6 +48,10 @@ var<uniform> normal_mat: mat4x4<f32>;
 
 @group(1) @binding(0)
 var<uniform> material: Material;
+@group(1) @binding(1)
+var diffuse_texture: texture_2d<f32>;
+@group(1) @binding(2)
+var diffuse_sampler: sampler;
 
 @vertex
 fn vs_main(v: VertexInput) -> VertexOutput {
@@ -50,50 +63,95 @@ fn vs_main(v: VertexInput) -> VertexOutput {
     let pos = proj * pos_in_eye;
     out.clip_pos = pos;
 
-    var normal_in_eye = (normal_mat * vec4f(v.normal, 1.0)).xyz;
+    var normal_in_eye = (normal_mat * vec4f(v.normal.xyz, 1.0)).xyz;
     out.normal = normalize(normal_in_eye);
+    out.color = v.color.rgb;
+    out.hidden_edges = v.corner_flags & 7u;
+    out.uv = v.uv;
     
-    let idx = v.vertex_index % 3u;
-    if (idx == 0u) {
-        out.barycentric = vec3<f32>(1.0, 0.0, 0.0);
-    } else if (idx == 1u) {
-        out.barycentric = vec3<f32>(0.0, 1.0, 0.0);
-    } else {
-        out.barycentric = vec3<f32>(0.0, 0.0, 1.0);
-    }
+    let corner = v.corner_flags >> 3u;
+    out.barycentric = vec3<f32>(f32(corner == 0u), f32(corner == 1u), f32(corner == 2u));
     
     return out;
 }
 
+// Outputs of the weighted blended order-independent transparency, resolved by composite.wgsl.
+// Both targets are blended with the same state, adding the colors and multiplying the alphas
+// by one minus the source alpha.
+struct TransparentOutput {
+    // the sum of the weighted premultiplied colors, and the product of the transparencies
+    @location(0) accum: vec4<f32>,
+    // the sum of the weighted alphas in the red channel
+    @location(1) weight: vec4<f32>,
+}
+
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    var base_color = vec4f(0.0, 0.0, 0.0, material.kd.w);
-
-    if (material.kd.w < 0.999) {
-        base_color = material.kd;
-    } else {
-        // ambient intesensity
-        let ia = material.ka.xyz;
-
-        let eye_to_light = normalize(light_pos.xyz - in.pos_in_eye);
-        let normal = normalize(in.normal);
-        let face_normal = faceForward(normal, in.pos_in_eye, normal);
-        let dot_prod = max(dot(eye_to_light, face_normal), 0.0);
-        // diffuse intensity
-        let id = material.kd.xyz * dot_prod;
-    
-        let reflect_in_eye = reflect(-eye_to_light, face_normal);
-        let surface_to_viewer_eye = normalize(-in.pos_in_eye);
-        let dot_prod_specular = max(dot(reflect_in_eye, surface_to_viewer_eye), 0.0);
-        let specular_factor = pow(dot_prod_specular, 35.0);
-        // secular intensity
-        let is = material.ks.xyz * specular_factor;
-        
-        base_color = vec4f(ia + id + is, material.kd.w);
+    return shade(in);
+}
+
+@fragment
+fn fs_transparent(in: VertexOutput) -> TransparentOutput {
+    let color = shade(in);
+    let alpha = color.a;
+    // the closer fragments weigh more, by their distance to the eye since the depth of the
+    // buffer moves with the near and far planes and may be reversed
+    let z = abs(in.pos_in_eye.z);
+    let depth_weight = clamp(10.0 / (1e-5 + pow(z / 5.0, 2.0) + pow(z / 200.0, 6.0)), 1e-2, 3e3);
+    let weight = alpha * depth_weight;
+    var out: TransparentOutput;
+    out.accum = vec4f(color.rgb * weight, alpha);
+    out.weight = vec4f(weight, 0.0, 0.0, 0.0);
+    return out;
+}
+
+fn shade(in: VertexOutput) -> vec4<f32> {
+    // sampled before any branch since sampling requires uniform control flow
+    let texel = textureSample(diffuse_texture, diffuse_sampler, in.uv).xyz;
+    // the plane of the triangle, for flat shading without per triangle vertices
+    let face_normal_in_eye = cross(dpdx(in.pos_in_eye), dpdy(in.pos_in_eye));
+    var kd = material.kd.xyz;
+    var ka = material.ka.xyz;
+    if (material.vertex_color > 0.5) {
+        kd = in.color;
+        ka = in.color * 0.1;
+    }
+    if (material.textured > 0.5) {
+        kd = kd * texel;
+        ka = ka * texel;
+    }
+
+    // the back faces of transparent models are lit with the normal turned towards the viewer
+    // ambient intesensity
+    let ia = ka;
+
+    let eye_to_light = normalize(light_pos.xyz - in.pos_in_eye);
+    var normal = normalize(in.normal);
+    if (material.flat_shading > 0.5 && length(face_normal_in_eye) > 0.0) {
+        normal = normalize(face_normal_in_eye);
     }
+    let face_normal = faceForward(normal, in.pos_in_eye, normal);
+    let dot_prod = max(dot(eye_to_light, face_normal), 0.0);
+    // diffuse intensity
+    let id = kd * dot_prod;
+
+    let reflect_in_eye = reflect(-eye_to_light, face_normal);
+    let surface_to_viewer_eye = normalize(-in.pos_in_eye);
+    let dot_prod_specular = max(dot(reflect_in_eye, surface_to_viewer_eye), 0.0);
+    let specular_factor = pow(dot_prod_specular, 35.0);
+    // secular intensity
+    let is = material.ks.xyz * specular_factor;
+
+    let base_color = vec4f(ia + id + is, material.kd.w);
 
     if (material.edge_width > 0.0) {
-        let d = min(min(in.barycentric.x, in.barycentric.y), in.barycentric.z);
+        // the edges hidden inside polygons never get close to the fragment
+        var d = 1.0;
+        for (var k = 0u; k < 3u; k++) {
+            if ((in.hidden_edges & (1u << k)) == 0u) {
+                d = min(d, in.barycentric[k]);
+            }
+        }
         // Compute derivatives to get screen-space rate of change
         let dd = fwidth(d);
         // Calculate edge intensity using smoothstep for anti-aliasing
@@ -105,3 +163,10 @@ fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
 
     return base_color;
 }
+
+// Writes the model and the distance to the eye along the ray through the pixel, for
+// `ViewCore::pick`.
+@fragment
+fn fs_pick(in: VertexOutput) -> @location(0) vec4<u32> {
+    return vec4<u32>(material.pick_id, bitcast<u32>(-in.pos_in_eye.z), 0u, 0u);
+}
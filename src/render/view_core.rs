@@ -1,8 +1,11 @@
-use cgmath::{Matrix4, Point3, Quaternion, SquareMatrix, Vector3};
+use cgmath::{
+    Angle, InnerSpace, Matrix4, One, Point3, Quaternion, Rotation, SquareMatrix, Vector3,
+};
+use winit::dpi::PhysicalPosition;
 
 use super::{
-    render::Renderer,
-    view_data::{DirtyFlags, ViewData},
+    render::{Renderer, PICK_FORMAT, TRANSPARENCY_FORMAT},
+    view_data::{DirtyFlags, UploadError, ViewData},
     BBox,
 };
 
@@ -12,6 +15,68 @@ use wgpu::{
 
 use crate::render::view_data::Vertex;
 
+#[rustfmt::skip]
+const OPENGL_TO_WGPU: Matrix4<f32> = Matrix4::new(
+    1.0, 0.0, 0.0, 0.0,
+    0.0, 1.0, 0.0, 0.0,
+    0.0, 0.0, 0.5, 0.0,
+    0.0, 0.0, 0.5, 1.0,
+);
+
+/// Maps the depth in [0, 1] to 1 minus it, for [`ViewCore::reversed_z`].
+#[rustfmt::skip]
+const REVERSE_Z: Matrix4<f32> = Matrix4::new(
+    1.0, 0.0, 0.0, 0.0,
+    0.0, 1.0, 0.0, 0.0,
+    0.0, 0.0, -1.0, 0.0,
+    0.0, 0.0, 1.0, 1.0,
+);
+
+/// A half line, see [`ViewCore::cursor_ray`].
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Ray {
+    pub(crate) origin: Vector3<f32>,
+    pub(crate) dir: Vector3<f32>,
+}
+
+impl Ray {
+    #[inline]
+    pub(crate) fn at(&self, t: f32) -> Vector3<f32> {
+        self.origin + self.dir * t
+    }
+}
+
+/// Models for [`ViewCore::render`] to center the view on and zoom to.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Framing {
+    /// every visible model with geometry
+    All,
+    Models(Vec<u32>),
+}
+
+/// Targets of the order-independent transparency and the bind group compositing them,
+/// created on the first transparent frame and again when the canvas changes size.
+struct TransparencyTargets {
+    width: u32,
+    height: u32,
+    /// the weighted colors with the product of the transparencies
+    accum: wgpu::TextureView,
+    /// the sum of the weights
+    weight: wgpu::TextureView,
+    composite_bind_group: BindGroup,
+}
+
+/// A move of the camera to a view, see [`ViewCore::animate_to`].
+struct Animation {
+    /// time the animation started, in milliseconds, or `None` for the next frame
+    start: Option<f64>,
+    from_angle: Quaternion<f32>,
+    to_angle: Quaternion<f32>,
+    from_translation: Vector3<f32>,
+    from_look: Quaternion<f32>,
+    from_zoom: f32,
+}
+
 struct ViewBuffer {
     camera_bind_group_layout: BindGroupLayout,
     camera_bind_group: BindGroup,
@@ -26,22 +91,47 @@ pub(crate) struct ViewCore {
     camera_base_zoom: f32,
     pub(crate) camera_zoom: f32,
     camera_base_translation: Vector3<f32>,
+    /// translation of the view in eye space, moved by panning
+    pub(crate) camera_translation: Vector3<f32>,
+    /// rotation of the camera around itself, applied after `camera_translation`, to look
+    /// around when flying
+    pub(crate) camera_look: Quaternion<f32>,
 
-    camera_near: f32,
-    camera_far: f32,
+    /// whether the depth goes from 1 at the near plane to 0 at the far one, which spreads
+    /// the precision of the floating point depth evenly
+    pub(crate) reversed_z: bool,
+    /// `reversed_z` when the pipelines were created
+    pipelines_reversed_z: bool,
+    /// box of the visible models, which the near and far planes are fitted to
+    scene_bbox: Option<BBox>,
     camera_fov: cgmath::Rad<f32>,
+    /// whether the projection is orthographic, sized like the perspective one at the center
+    /// of the scene
+    pub(crate) orthographic: bool,
 
     camera_eye: Point3<f32>,
     camera_center: Point3<f32>,
     camera_up: Vector3<f32>,
 
     pub trackball_angle: Quaternion<f32>,
+    animation: Option<Animation>,
 
     view_buffer: Option<ViewBuffer>,
 
     pub(crate) material_bind_group_layout: Option<BindGroupLayout>,
-    pub(crate) pipeline_cull_back: Option<RenderPipeline>,
-    pub(crate) pipeline_cull_front: Option<RenderPipeline>,
+    pub(crate) pipeline_opaque: Option<RenderPipeline>,
+    /// draws the transparent models into the targets of the weighted blended
+    /// order-independent transparency, without culling nor depth writes, or straight onto
+    /// the canvas when [`Renderer::oit_supported`] is not set
+    pub(crate) pipeline_transparent: Option<RenderPipeline>,
+    /// draw the model ids for [`ViewCore::pick`], culling the back faces like the opaque
+    /// pipeline or not like the transparent one
+    pipeline_pick_opaque: Option<RenderPipeline>,
+    pipeline_pick_transparent: Option<RenderPipeline>,
+    composite_bind_group_layout: Option<BindGroupLayout>,
+    /// blends the transparent models accumulated by `pipeline_transparent` over the opaque ones
+    pipeline_composite: Option<RenderPipeline>,
+    transparency_targets: Option<TransparencyTargets>,
 }
 
 impl Default for ViewCore {
@@ -51,35 +141,47 @@ impl Default for ViewCore {
             camera_base_zoom: 1.0,
             camera_zoom: 1.0,
             camera_base_translation: Vector3::new(0.0, 0.0, 0.0),
+            camera_translation: Vector3::new(0.0, 0.0, 0.0),
+            camera_look: Quaternion::<f32>::new(1.0, 0.0, 0.0, 0.0),
 
-            camera_near: 1.0,
-            camera_far: 100.0,
+            reversed_z: true,
+            pipelines_reversed_z: true,
+            scene_bbox: None,
             camera_fov: cgmath::Deg(45.0).into(),
+            orthographic: false,
 
             camera_eye: Point3::new(0.0, 0.0, 5.0),
             camera_center: Point3::new(0.0, 0.0, 0.0),
             camera_up: Vector3::new(0.0, 1.0, 0.0),
 
             trackball_angle: Quaternion::<f32>::new(1.0, 0.0, 0.0, 0.0),
+            animation: None,
 
             view_buffer: None,
 
             material_bind_group_layout: None,
-            pipeline_cull_back: None,
-            pipeline_cull_front: None,
+            pipeline_opaque: None,
+            pipeline_transparent: None,
+            pipeline_pick_opaque: None,
+            pipeline_pick_transparent: None,
+            composite_bind_group_layout: None,
+            pipeline_composite: None,
+            transparency_targets: None,
         }
     }
 }
 
 impl ViewCore {
+    /// Uploads what changed in the models and draws the opaque ones, returning the errors of
+    /// the uploads with the id of their model.
     pub(crate) fn render<'b, 'a: 'b>(
         &'a mut self,
         render: &'a Renderer,
         render_pass: &'b mut wgpu::RenderPass<'a>,
         data_map: &'a mut std::collections::HashMap<u32, ViewData>,
-        update_box: bool,
+        frame: Option<Framing>,
         update_matrix: bool,
-    ) {
+    ) -> Vec<(u32, UploadError)> {
         if self.view_buffer.is_none() {
             let camera_bind_group_layout =
                 render
@@ -208,18 +310,40 @@ impl ViewCore {
                     .device
                     .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
                         label: Some("camera_bind_group_layout"),
-                        entries: &[wgpu::BindGroupLayoutEntry {
-                            binding: 0,
-                            visibility: wgpu::ShaderStages::FRAGMENT,
-                            ty: wgpu::BindingType::Buffer {
-                                ty: wgpu::BufferBindingType::Uniform,
-                                has_dynamic_offset: false,
-                                min_binding_size: None,
+                        entries: &[
+                            wgpu::BindGroupLayoutEntry {
+                                binding: 0,
+                                visibility: wgpu::ShaderStages::FRAGMENT,
+                                ty: wgpu::BindingType::Buffer {
+                                    ty: wgpu::BufferBindingType::Uniform,
+                                    has_dynamic_offset: false,
+                                    min_binding_size: None,
+                                },
+                                count: None,
                             },
-                            count: None,
-                        }],
+                            wgpu::BindGroupLayoutEntry {
+                                binding: 1,
+                                visibility: wgpu::ShaderStages::FRAGMENT,
+                                ty: wgpu::BindingType::Texture {
+                                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                                    view_dimension: wgpu::TextureViewDimension::D2,
+                                    multisampled: false,
+                                },
+                                count: None,
+                            },
+                            wgpu::BindGroupLayoutEntry {
+                                binding: 2,
+                                visibility: wgpu::ShaderStages::FRAGMENT,
+                                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                                count: None,
+                            },
+                        ],
                     });
+            self.material_bind_group_layout = Some(material_bind_group_layout);
+        }
 
+        // the depth test of the pipelines follows the depth buffer
+        if self.pipeline_opaque.is_none() || self.pipelines_reversed_z != self.reversed_z {
             let shader = render
                 .device
                 .create_shader_module(wgpu::ShaderModuleDescriptor {
@@ -238,7 +362,7 @@ impl ViewCore {
                                 .as_ref()
                                 .unwrap()
                                 .camera_bind_group_layout,
-                            &material_bind_group_layout,
+                            self.material_bind_group_layout.as_ref().unwrap(),
                         ],
                         immediate_size: 0,
                     });
@@ -272,7 +396,11 @@ impl ViewCore {
                 depth_stencil: Some(wgpu::DepthStencilState {
                     format: TextureFormat::Depth32Float,
                     depth_write_enabled: true,
-                    depth_compare: CompareFunction::Less,
+                    depth_compare: if self.reversed_z {
+                        CompareFunction::Greater
+                    } else {
+                        CompareFunction::Less
+                    },
                     stencil: wgpu::StencilState::default(),
                     bias: wgpu::DepthBiasState::default(),
                 }),
@@ -280,55 +408,197 @@ impl ViewCore {
                 multiview_mask: None,
             };
 
-            let render_pipeline_cull_back =
+            let render_pipeline_opaque =
                 render.device.create_render_pipeline(&pipeline_desc_base);
 
-            let mut pipeline_desc_inv = pipeline_desc_base.clone();
-            pipeline_desc_inv.primitive.cull_mode = Some(wgpu::Face::Front);
-            let render_pipeline_cull_front =
-                render.device.create_render_pipeline(&pipeline_desc_inv);
+            // the colors are added and the alphas multiply the transparency, on both targets
+            // since WebGL2 cannot blend them differently
+            let transparent_target = wgpu::ColorTargetState {
+                format: TRANSPARENCY_FORMAT,
+                blend: Some(wgpu::BlendState {
+                    color: wgpu::BlendComponent {
+                        src_factor: wgpu::BlendFactor::One,
+                        dst_factor: wgpu::BlendFactor::One,
+                        operation: wgpu::BlendOperation::Add,
+                    },
+                    alpha: wgpu::BlendComponent {
+                        src_factor: wgpu::BlendFactor::Zero,
+                        dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+                        operation: wgpu::BlendOperation::Add,
+                    },
+                }),
+                write_mask: wgpu::ColorWrites::ALL,
+            };
+            let transparent_targets = [Some(transparent_target.clone()), Some(transparent_target)];
+            let mut pipeline_desc_transparent = pipeline_desc_base.clone();
+            pipeline_desc_transparent.label = Some("transparent_pipeline");
+            // without the targets, the models are blended over each other from back to front
+            if render.oit_supported {
+                pipeline_desc_transparent.fragment = Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: Some("fs_transparent"),
+                    compilation_options: Default::default(),
+                    targets: &transparent_targets,
+                });
+            }
+            pipeline_desc_transparent.primitive.cull_mode = None;
+            if let Some(depth_stencil) = pipeline_desc_transparent.depth_stencil.as_mut() {
+                depth_stencil.depth_write_enabled = false;
+            }
+            let render_pipeline_transparent =
+                render.device.create_render_pipeline(&pipeline_desc_transparent);
 
-            self.material_bind_group_layout = Some(material_bind_group_layout);
-            self.pipeline_cull_back = Some(render_pipeline_cull_back);
-            self.pipeline_cull_front = Some(render_pipeline_cull_front);
+            let pick_targets = [Some(wgpu::ColorTargetState {
+                format: PICK_FORMAT,
+                blend: None,
+                write_mask: wgpu::ColorWrites::ALL,
+            })];
+            let mut pipeline_desc_pick = pipeline_desc_base.clone();
+            pipeline_desc_pick.label = Some("pick_pipeline");
+            pipeline_desc_pick.fragment = Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some("fs_pick"),
+                compilation_options: Default::default(),
+                targets: &pick_targets,
+            });
+            let pipeline_pick_opaque = render.device.create_render_pipeline(&pipeline_desc_pick);
+            pipeline_desc_pick.primitive.cull_mode = None;
+            let pipeline_pick_transparent =
+                render.device.create_render_pipeline(&pipeline_desc_pick);
+
+            self.pipeline_opaque = Some(render_pipeline_opaque);
+            self.pipeline_transparent = Some(render_pipeline_transparent);
+            self.pipeline_pick_opaque = Some(pipeline_pick_opaque);
+            self.pipeline_pick_transparent = Some(pipeline_pick_transparent);
+            self.pipelines_reversed_z = self.reversed_z;
+        }
+
+        if self.pipeline_composite.is_none() && render.oit_supported {
+            let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+                binding,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            };
+            let composite_bind_group_layout =
+                render
+                    .device
+                    .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                        label: Some("composite_bind_group_layout"),
+                        entries: &[texture_entry(0), texture_entry(1)],
+                    });
+            let shader = render
+                .device
+                .create_shader_module(wgpu::ShaderModuleDescriptor {
+                    label: Some("composite_shader"),
+                    source: wgpu::ShaderSource::Wgsl(include_str!("composite.wgsl").into()),
+                });
+            let layout = render
+                .device
+                .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    label: Some("composite_pipeline_layout"),
+                    bind_group_layouts: &[&composite_bind_group_layout],
+                    immediate_size: 0,
+                });
+            let pipeline_composite =
+                render
+                    .device
+                    .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                        label: Some("composite_pipeline"),
+                        layout: Some(&layout),
+                        cache: None,
+                        vertex: wgpu::VertexState {
+                            module: &shader,
+                            entry_point: Some("vs_main"),
+                            compilation_options: Default::default(),
+                            buffers: &[],
+                        },
+                        fragment: Some(wgpu::FragmentState {
+                            module: &shader,
+                            entry_point: Some("fs_main"),
+                            compilation_options: Default::default(),
+                            targets: &[Some(wgpu::ColorTargetState {
+                                format: render.config.format,
+                                blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                                write_mask: wgpu::ColorWrites::ALL,
+                            })],
+                        }),
+                        primitive: wgpu::PrimitiveState::default(),
+                        depth_stencil: None,
+                        multisample: wgpu::MultisampleState::default(),
+                        multiview_mask: None,
+                    });
+            self.composite_bind_group_layout = Some(composite_bind_group_layout);
+            self.pipeline_composite = Some(pipeline_composite);
         }
         let mut has_dirty_data = false;
-        for data in data_map.values_mut() {
-            if !data.visible {
+        let mut upload_errors = vec![];
+        for (&id, data) in data_map.iter_mut() {
+            // group nodes without geometry have nothing to upload
+            if !data.visible || data.is_empty() {
                 continue;
             }
 
             if data.pipeline.is_none() {
-                data.init_resources(
+                has_dirty_data = true;
+                let error = data.init_resources(
                     render,
-                    &self.material_bind_group_layout.as_ref().unwrap(),
+                    self.material_bind_group_layout.as_ref().unwrap(),
                 );
+                upload_errors.push((id, error));
             }
+            // new triangles change the chunks, which uploads the vertices as well
+            if data.dirty.contains(DirtyFlags::DIRTY_INDEX) {
+                has_dirty_data = true;
+                upload_errors.push((id, data.update_chunks(render)));
+            }
+
             if data.dirty.contains(DirtyFlags::DIRTY_VERTEX) {
                 has_dirty_data = true;
-                data.update_vertex_buffer(render);
-                data.dirty.remove(DirtyFlags::DIRTY_VERTEX);
+                upload_errors.push((id, data.update_vertex_buffer(render)));
             }
 
             if data.dirty.contains(DirtyFlags::DIRTY_MATERIAL) {
                 data.update_material(render);
                 data.dirty.remove(DirtyFlags::DIRTY_MATERIAL);
             }
+
+            if data.dirty.contains(DirtyFlags::DIRTY_TEXTURE) {
+                data.update_texture(
+                    render,
+                    self.material_bind_group_layout.as_ref().unwrap(),
+                );
+                data.dirty.remove(DirtyFlags::DIRTY_TEXTURE);
+            }
         }
 
-        if update_box || has_dirty_data {
-            if !data_map.is_empty() {
-                let mut bbox = BBox::default();
-                for data in data_map.values() {
+        if let Some(frame) = frame {
+            let mut bbox = BBox::default();
+            let shown = data_map.iter().filter(|(_, data)| data.visible && !data.is_empty());
+            for (id, data) in shown {
+                if match &frame {
+                    Framing::All => true,
+                    Framing::Models(ids) => ids.contains(id),
+                } {
                     bbox.merge_box(&data.bbox);
                 }
-                let center = (bbox.min + bbox.max) / 2.0;
-                self.camera_base_translation = -center;
-                self.camera_base_zoom = 1.0 / bbox.max_len();
             }
+            self.frame_box(&bbox);
         }
 
-        if has_dirty_data || update_matrix {
+        // the near and far planes follow the visible models
+        let mut scene_bbox = None;
+        for data in data_map.values().filter(|data| data.visible && !data.is_empty()) {
+            scene_bbox.get_or_insert_with(BBox::default).merge_box(&data.bbox);
+        }
+        let scene_changed = scene_bbox != self.scene_bbox;
+        self.scene_bbox = scene_bbox;
+
+        if has_dirty_data || update_matrix || scene_changed {
             self.update_matrix(render);
         }
 
@@ -337,28 +607,391 @@ impl ViewCore {
             &self.view_buffer.as_ref().unwrap().camera_bind_group,
             &[],
         );
-        for data in data_map.values() {
-            if data.visible {
-                data.render(
-                    render_pass,
-                    self.pipeline_cull_back.as_ref().unwrap(),
-                    self.pipeline_cull_front.as_ref().unwrap(),
-                );
+        for data in drawn_data(data_map, false) {
+            data.render(render_pass, self.pipeline_opaque.as_ref().unwrap());
+        }
+        upload_errors
+    }
+
+    /// Draws the transparent models over `target`, where [`ViewCore::render`] drew the
+    /// opaque ones and left their depth. The transparent models are accumulated with
+    /// weights decreasing with the depth, then composited, so that their order does not
+    /// matter. When the GPU cannot render into the targets, they are blended over the opaque
+    /// ones from the farthest to the closest instead.
+    pub(crate) fn render_transparent(
+        &mut self,
+        render: &Renderer,
+        encoder: &mut wgpu::CommandEncoder,
+        target: &wgpu::TextureView,
+        data_map: &std::collections::HashMap<u32, ViewData>,
+    ) {
+        let mut transparent = drawn_data(data_map, true);
+        if transparent.is_empty() {
+            return;
+        }
+        let depth_attachment = Some(wgpu::RenderPassDepthStencilAttachment {
+            view: &render.depth_texture_view,
+            depth_ops: Some(wgpu::Operations {
+                load: wgpu::LoadOp::Load,
+                store: wgpu::StoreOp::Discard,
+            }),
+            stencil_ops: None,
+        });
+        if !render.oit_supported {
+            // the models are sorted by the depth of their center, eye space looking down -Z
+            let view = self.view_matrix();
+            let depth = |data: &ViewData| {
+                let center = (data.bbox.min + data.bbox.max) / 2.0;
+                (view * center.extend(1.0)).z
+            };
+            transparent.sort_by(|a, b| depth(a).total_cmp(&depth(b)));
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("transparent_pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: target,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                    depth_slice: None,
+                })],
+                depth_stencil_attachment: depth_attachment,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+                multiview_mask: None,
+            });
+            render_pass.set_bind_group(
+                0,
+                &self.view_buffer.as_ref().unwrap().camera_bind_group,
+                &[],
+            );
+            for data in transparent {
+                data.render(&mut render_pass, self.pipeline_transparent.as_ref().unwrap());
+            }
+            return;
+        }
+
+        let (width, height) = (render.w(), render.h());
+        if self
+            .transparency_targets
+            .as_ref()
+            .is_none_or(|targets| (targets.width, targets.height) != (width, height))
+        {
+            self.transparency_targets = Some(self.create_transparency_targets(render));
+        }
+        let targets = self.transparency_targets.as_ref().unwrap();
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("transparent_pass"),
+                color_attachments: &[
+                    Some(wgpu::RenderPassColorAttachment {
+                        view: &targets.accum,
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            // nothing is covered yet, the transparency is 1
+                            load: wgpu::LoadOp::Clear(wgpu::Color {
+                                r: 0.0,
+                                g: 0.0,
+                                b: 0.0,
+                                a: 1.0,
+                            }),
+                            store: wgpu::StoreOp::Store,
+                        },
+                        depth_slice: None,
+                    }),
+                    Some(wgpu::RenderPassColorAttachment {
+                        view: &targets.weight,
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                            store: wgpu::StoreOp::Store,
+                        },
+                        depth_slice: None,
+                    }),
+                ],
+                depth_stencil_attachment: depth_attachment,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+                multiview_mask: None,
+            });
+            render_pass.set_bind_group(
+                0,
+                &self.view_buffer.as_ref().unwrap().camera_bind_group,
+                &[],
+            );
+            for data in transparent {
+                data.render(&mut render_pass, self.pipeline_transparent.as_ref().unwrap());
             }
         }
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("composite_pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: target,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+                depth_slice: None,
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+            multiview_mask: None,
+        });
+        render_pass.set_pipeline(self.pipeline_composite.as_ref().unwrap());
+        render_pass.set_bind_group(0, &targets.composite_bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
     }
 
-    fn update_matrix(&self, render: &Renderer) {
-        let view = Matrix4::look_at_rh(self.camera_eye, self.camera_center, self.camera_up)
+    /// Creates the targets the transparent models are accumulated into, of the size of the
+    /// canvas, with the bind group the composite pass reads them through.
+    fn create_transparency_targets(&self, render: &Renderer) -> TransparencyTargets {
+        let create = |label| {
+            render
+                .device
+                .create_texture(&wgpu::TextureDescriptor {
+                    label: Some(label),
+                    size: wgpu::Extent3d {
+                        width: render.w(),
+                        height: render.h(),
+                        depth_or_array_layers: 1,
+                    },
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D2,
+                    format: TRANSPARENCY_FORMAT,
+                    usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                        | wgpu::TextureUsages::TEXTURE_BINDING,
+                    view_formats: &[],
+                })
+                .create_view(&wgpu::TextureViewDescriptor::default())
+        };
+        let accum = create("accum_texture");
+        let weight = create("weight_texture");
+        let composite_bind_group = render.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: self.composite_bind_group_layout.as_ref().unwrap(),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&accum),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&weight),
+                },
+            ],
+            label: Some("composite_bind_group"),
+        });
+        TransparencyTargets {
+            width: render.w(),
+            height: render.h(),
+            accum,
+            weight,
+            composite_bind_group,
+        }
+    }
+
+    /// Moves the view by `dx` and `dy` pixels from the translation `from`, so that the
+    /// points in the plane of the center of the scene follow the cursor.
+    pub(crate) fn pan(&mut self, render: &Renderer, from: Vector3<f32>, dx: f64, dy: f64) {
+        // size of a pixel in eye space at the depth of the center
+        let depth = (self.camera_eye - self.camera_center).magnitude() - from.z;
+        let pixel = 2.0 * depth * (self.camera_fov / 2.0).tan() / render.h() as f32;
+        let moved = Vector3::new(dx as f32 * pixel, -dy as f32 * pixel, 0.0);
+        self.camera_translation = from + self.camera_look.invert().rotate_vector(moved);
+    }
+
+    /// Moves the camera by `moved`, in eye space.
+    pub(crate) fn walk(&mut self, moved: Vector3<f32>) {
+        self.camera_translation -= self.camera_look.invert().rotate_vector(moved);
+    }
+
+    /// Distance from the eye to the plane of the center of the scene, which sets the size
+    /// of the orthographic view.
+    fn center_depth(&self) -> f32 {
+        (self.camera_eye - self.camera_center).magnitude() - self.camera_translation.z
+    }
+
+    /// The ray through the pixel at `pos`, in the coordinates of the models and in eye
+    /// space. A point of the ray has the same parameter in both.
+    pub(crate) fn cursor_ray(&self, render: &Renderer, pos: PhysicalPosition<f64>) -> (Ray, Ray) {
+        let w = render.w() as f32;
+        let h = render.h() as f32;
+        let x = 2.0 * pos.x as f32 / w - 1.0;
+        let y = 1.0 - 2.0 * pos.y as f32 / h;
+        let tan = (self.camera_fov / 2.0).tan();
+        let in_eye = if self.orthographic {
+            let half_h = self.center_depth() * tan;
+            Ray {
+                origin: Vector3::new(x * half_h * w / h, y * half_h, 0.0),
+                dir: -Vector3::unit_z(),
+            }
+        } else {
+            Ray {
+                origin: Vector3::new(0.0, 0.0, 0.0),
+                dir: Vector3::new(x * tan * w / h, y * tan, -1.0),
+            }
+        };
+        let eye_to_model = self
+            .view_matrix()
+            .invert()
+            .expect("failed to invert the view matrix");
+        let in_model = Ray {
+            origin: (eye_to_model * in_eye.origin.extend(1.0)).truncate(),
+            dir: (eye_to_model * in_eye.dir.extend(0.0)).truncate(),
+        };
+        (in_model, in_eye)
+    }
+
+    /// Moves the view towards the point at parameter `hit` of `ray_in_eye` (see
+    /// [`ViewCore::cursor_ray`]), or towards the point of the ray in the plane of the
+    /// center of the scene when it hits nothing. The distance to that point is divided by
+    /// `exp(amount)`, zooming out when `amount` is negative. The orthographic view shrinks
+    /// around the point the same way.
+    pub(crate) fn zoom_towards(&mut self, ray_in_eye: &Ray, hit: Option<f32>, amount: f32) {
+        let center_depth = self.center_depth();
+        let mut target = ray_in_eye.at(hit.unwrap_or(center_depth));
+        if self.orthographic {
+            // the size of the view follows the depth of the center
+            target.z = -center_depth;
+        }
+        let moved = (1.0 - (-amount).exp()) * target;
+        // the scene, scaled to a size of 1, stays large enough to be seen
+        const MAX_CENTER_DEPTH: f32 = 50.0;
+        if center_depth + moved.z > MAX_CENTER_DEPTH {
+            return;
+        }
+        self.camera_translation -= self.camera_look.invert().rotate_vector(moved);
+    }
+
+    /// Starts moving the camera to the scene rotated by `angle`, centered and framed, at
+    /// the time `now` in milliseconds.
+    pub(crate) fn animate_to(&mut self, angle: Quaternion<f32>, now: f64) {
+        self.animation = Some(Animation {
+            start: Some(now),
+            from_angle: self.trackball_angle,
+            to_angle: angle,
+            from_translation: self.camera_translation,
+            from_look: self.camera_look,
+            from_zoom: self.camera_zoom,
+        });
+    }
+
+    /// Moves the camera along the animation started by [`ViewCore::animate_to`], for the
+    /// time `now` in milliseconds.
+    pub(crate) fn animate(&mut self, now: f64) {
+        const DURATION: f64 = 400.0;
+        let Some(animation) = &mut self.animation else {
+            return;
+        };
+        let start = *animation.start.get_or_insert(now);
+        let t = ((now - start) / DURATION).clamp(0.0, 1.0) as f32;
+        // eased in and out
+        let t = t * t * (3.0 - 2.0 * t);
+        let mut to_angle = animation.to_angle;
+        // the shortest way round
+        if animation.from_angle.dot(to_angle) < 0.0 {
+            to_angle = -to_angle;
+        }
+        self.trackball_angle = animation.from_angle.slerp(to_angle, t);
+        self.camera_look = animation.from_look.slerp(Quaternion::one(), t);
+        self.camera_translation = animation.from_translation * (1.0 - t);
+        self.camera_zoom = animation.from_zoom + (1.0 - animation.from_zoom) * t;
+        if t >= 1.0 {
+            self.animation = None;
+        }
+    }
+
+    /// Centers the view on `bbox` and zooms so that it fits, keeping the rotation, or the one
+    /// an animation in progress was turning to. The camera moves there from the current
+    /// view, unless nothing was shown before.
+    fn frame_box(&mut self, bbox: &BBox) {
+        let len = bbox.max_len();
+        if bbox.is_empty() || len <= 0.0 {
+            return;
+        }
+        let base_translation = -(bbox.min + bbox.max) / 2.0;
+        let base_zoom = 1.0 / len;
+        if self.scene_bbox.is_some() {
+            // the same view from the new base, which the animation then resets
+            let scale = self.camera_base_zoom * self.camera_zoom;
+            self.camera_translation += self
+                .trackball_angle
+                .rotate_vector(self.camera_base_translation - base_translation)
+                * scale;
+            self.camera_zoom *= self.camera_base_zoom / base_zoom;
+            let to_angle = self
+                .animation
+                .as_ref()
+                .map_or(self.trackball_angle, |animation| animation.to_angle);
+            self.animation = Some(Animation {
+                start: None,
+                from_angle: self.trackball_angle,
+                to_angle,
+                from_translation: self.camera_translation,
+                from_look: self.camera_look,
+                from_zoom: self.camera_zoom,
+            });
+        }
+        self.camera_base_translation = base_translation;
+        self.camera_base_zoom = base_zoom;
+    }
+
+    /// Leaves the camera where the animation brought it, for the user to move it.
+    pub(crate) fn stop_animation(&mut self) {
+        self.animation = None;
+    }
+
+    pub(crate) fn is_animating(&self) -> bool {
+        self.animation.is_some()
+    }
+
+    /// The transform from the coordinates of the models to eye space.
+    pub(crate) fn view_matrix(&self) -> Matrix4<f32> {
+        Matrix4::from(self.camera_look)
+            * Matrix4::from_translation(self.camera_translation)
+            * Matrix4::look_at_rh(self.camera_eye, self.camera_center, self.camera_up)
             * Matrix4::from_scale(self.camera_base_zoom * self.camera_zoom)
             * Matrix4::from(self.trackball_angle)
-            * Matrix4::from_translation(self.camera_base_translation);
+            * Matrix4::from_translation(self.camera_base_translation)
+    }
+
+    /// Depth the depth buffer is cleared to, the farthest one.
+    pub(crate) fn depth_clear(&self) -> f32 {
+        if self.reversed_z { 0.0 } else { 1.0 }
+    }
+
+    /// Near and far planes enclosing the visible models for the transform `view`. In
+    /// perspective, the near plane cannot get closer to the eye than a fraction of the far
+    /// one, given by the precision of the depth buffer.
+    fn depth_range(&self, view: &Matrix4<f32>) -> (f32, f32) {
+        let Some(bbox) = &self.scene_bbox else {
+            return (0.1, 100.0);
+        };
+        let (min, max) = bbox.corners().iter().fold((f32::MAX, f32::MIN), |(min, max), corner| {
+            let depth = -(view * corner.extend(1.0)).z;
+            (min.min(depth), max.max(depth))
+        });
+        // the faces lying on the box are not clipped
+        let margin = (max - min) * 0.01 + 1e-4;
+        let (near, far) = (min - margin, max + margin);
+        if self.orthographic {
+            return (near, far);
+        }
+        let min_ratio = if self.reversed_z { 1e-6 } else { 1e-4 };
+        let far = far.max(1e-3);
+        (near.max(far * min_ratio), far)
+    }
+
+    fn update_matrix(&self, render: &Renderer) {
+        let view = self.view_matrix();
         let mut normal_mat = view.invert().expect("failed to invert the view matrix");
         normal_mat.transpose_self();
 
-        let w = render.w() as f32;
-        let h = render.h() as f32;
-        let proj = cgmath::perspective(self.camera_fov, w / h, self.camera_near, self.camera_far);
+        let proj = self.projection(render, &view);
 
         let view_data: [[f32; 4]; 4] = view.into();
         let normal_mat_data: [[f32; 4]; 4] = normal_mat.into();
@@ -379,4 +1012,143 @@ impl ViewCore {
             bytemuck::cast_slice(&normal_mat_data),
         );
     }
+
+    /// The projection of eye space for the wgpu depth, the models being transformed by
+    /// `view`.
+    fn projection(&self, render: &Renderer, view: &Matrix4<f32>) -> Matrix4<f32> {
+        let w = render.w() as f32;
+        let h = render.h() as f32;
+        let (near, far) = self.depth_range(view);
+        let proj = if self.orthographic {
+            // as large as the perspective view at the center of the scene
+            let half_h = self.center_depth() * (self.camera_fov / 2.0).tan();
+            let half_w = half_h * w / h;
+            cgmath::ortho(-half_w, half_w, -half_h, half_h, near, far)
+        } else {
+            cgmath::perspective(self.camera_fov, w / h, near, far)
+        };
+        // from the depth in [-1, 1] of OpenGL to the [0, 1] of wgpu
+        let mut proj = OPENGL_TO_WGPU * proj;
+        if self.reversed_z {
+            proj = REVERSE_Z * proj;
+        }
+        proj
+    }
+
+    /// Draws the ids of the visible models at the pixel `pos` and copies them to a buffer
+    /// to be mapped, whose first four `u32` are the id plus one of the model there, 0 for
+    /// none, and the bits of its distance along [`ViewCore::cursor_ray`]. The projection is
+    /// moved so that the pixel fills the single pixel target. Nothing is drawn before the
+    /// first frame.
+    pub(crate) fn pick(
+        &self,
+        render: &Renderer,
+        data_map: &std::collections::HashMap<u32, ViewData>,
+        pos: PhysicalPosition<f64>,
+    ) -> Option<Buffer> {
+        let view_buffer = self.view_buffer.as_ref()?;
+        let pipeline_opaque = self.pipeline_pick_opaque.as_ref()?;
+        let pipeline_transparent = self.pipeline_pick_transparent.as_ref()?;
+
+        let w = render.w() as f32;
+        let h = render.h() as f32;
+        let x = 2.0 * pos.x as f32 / w - 1.0;
+        let y = 1.0 - 2.0 * pos.y as f32 / h;
+        let proj = self.projection(render, &self.view_matrix());
+        let to_pixel = Matrix4::from_nonuniform_scale(w, h, 1.0)
+            * Matrix4::from_translation(Vector3::new(-x, -y, 0.0));
+        let write_proj = |proj: Matrix4<f32>| {
+            let proj_data: [[f32; 4]; 4] = proj.into();
+            render
+                .queue
+                .write_buffer(&view_buffer.proj_buffer, 0, bytemuck::cast_slice(&proj_data));
+        };
+        write_proj(to_pixel * proj);
+
+        let mut encoder = render
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("pick") });
+        {
+            let target = render
+                .pick_texture
+                .create_view(&wgpu::TextureViewDescriptor::default());
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("pick_pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &target,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                        store: wgpu::StoreOp::Store,
+                    },
+                    depth_slice: None,
+                })],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &render.pick_depth_texture_view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(self.depth_clear()),
+                        store: wgpu::StoreOp::Discard,
+                    }),
+                    stencil_ops: None,
+                }),
+                timestamp_writes: None,
+                occlusion_query_set: None,
+                multiview_mask: None,
+            });
+            render_pass.set_bind_group(0, &view_buffer.camera_bind_group, &[]);
+            // the models shown since the last frame have nothing uploaded yet
+            let pipelines = [(false, pipeline_opaque), (true, pipeline_transparent)];
+            for (transparent, pipeline) in pipelines {
+                for data in drawn_data(data_map, transparent) {
+                    if data.pipeline.is_some() {
+                        data.render(&mut render_pass, pipeline);
+                    }
+                }
+            }
+        }
+        let buffer = render.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("pick_buffer"),
+            size: wgpu::COPY_BYTES_PER_ROW_ALIGNMENT as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        encoder.copy_texture_to_buffer(
+            wgpu::TexelCopyTextureInfo {
+                texture: &render.pick_texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::TexelCopyBufferInfo {
+                buffer: &buffer,
+                layout: wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT),
+                    rows_per_image: None,
+                },
+            },
+            wgpu::Extent3d {
+                width: 1,
+                height: 1,
+                depth_or_array_layers: 1,
+            },
+        );
+        render.queue.submit(std::iter::once(encoder.finish()));
+        // the next frame draws with the projection of the whole canvas
+        write_proj(proj);
+        Some(buffer)
+    }
+}
+
+/// The visible models with geometry that are transparent or not, by id so that the order
+/// of the draws does not change between frames.
+fn drawn_data(
+    data_map: &std::collections::HashMap<u32, ViewData>,
+    transparent: bool,
+) -> Vec<&ViewData> {
+    let mut drawn = Vec::from_iter(data_map.iter().filter(|(_, data)| {
+        data.visible && !data.is_empty() && data.is_transparent() == transparent
+    }));
+    drawn.sort_unstable_by_key(|&(&id, _)| id);
+    Vec::from_iter(drawn.into_iter().map(|(_, data)| data))
 }
@@ -1,6 +1,8 @@
 use super::{render::Renderer, BBox};
 
-use cgmath::Vector3;
+use std::{future::Future, ops::Range, pin::Pin};
+
+use cgmath::{InnerSpace, Vector3};
 use wgpu::{util::DeviceExt, Buffer, RenderPass, RenderPipeline};
 
 bitflags::bitflags! {
@@ -11,7 +13,9 @@ bitflags::bitflags! {
         const DIRTY_EDGE = 0b00000010;
         const DIRTY_FACE = 0b00000100;
         const DIRTY_MATERIAL = 0b00001000;
-        const DIRTY_ALL = Self::DIRTY_VERTEX.bits() | Self::DIRTY_EDGE.bits() | Self::DIRTY_FACE.bits() | Self::DIRTY_MATERIAL.bits();
+        const DIRTY_TEXTURE = 0b00010000;
+        const DIRTY_INDEX = 0b00100000;
+        const DIRTY_ALL = Self::DIRTY_VERTEX.bits() | Self::DIRTY_EDGE.bits() | Self::DIRTY_FACE.bits() | Self::DIRTY_MATERIAL.bits() | Self::DIRTY_TEXTURE.bits() | Self::DIRTY_INDEX.bits();
     }
 }
 
@@ -19,7 +23,53 @@ bitflags::bitflags! {
 #[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
 pub(crate) struct Vertex {
     pub(crate) point: [f32; 3],
-    pub(crate) normal: [f32; 3],
+    /// unit normal in snorm16, the fourth value being padding, see [`pack_normal`]
+    pub(crate) normal: [i16; 4],
+    /// RGBA in unorm8, see [`pack_color`]
+    pub(crate) color: [u8; 4],
+    /// bits 3 and 4 hold the corner `k` the vertex is in all of its triangles, which gives
+    /// its barycentric coordinates. Bit `k` of the first three is set when the edge opposite
+    /// to corner `k` is hidden, only the vertices of a single triangle having such bits.
+    pub(crate) corner_flags: u32,
+    pub(crate) uv: [f32; 2],
+}
+
+const _: () = assert!(std::mem::size_of::<Vertex>() == 36);
+
+const CORNER_SHIFT: u32 = 3;
+const HIDDEN_EDGES: u32 = 0b111;
+
+/// Packs a normal into the snorm16 values of [`Vertex::normal`], normalizing it first since
+/// the components are clamped to [-1, 1]. The shader normalizes it again after the
+/// interpolation, so the rounding does not show.
+fn pack_normal(normal: [f32; 3]) -> [i16; 4] {
+    let len = (normal[0] * normal[0] + normal[1] * normal[1] + normal[2] * normal[2]).sqrt();
+    let scale = if len > 0.0 { i16::MAX as f32 / len } else { 0.0 };
+    let [x, y, z] = normal.map(|c| (c * scale).round() as i16);
+    [x, y, z, 0]
+}
+
+fn unpack_normal(normal: [i16; 4]) -> [f32; 3] {
+    [normal[0], normal[1], normal[2]].map(|c| c as f32 / i16::MAX as f32)
+}
+
+/// Packs an RGB color into the unorm8 values of [`Vertex::color`].
+fn pack_color(color: &[f32]) -> [u8; 4] {
+    let [r, g, b] =
+        [color[0], color[1], color[2]].map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8);
+    [r, g, b, 255]
+}
+
+impl Vertex {
+    #[inline]
+    fn corner(&self) -> u32 {
+        self.corner_flags >> CORNER_SHIFT
+    }
+
+    #[inline]
+    fn hidden_edges(&self) -> u32 {
+        self.corner_flags & HIDDEN_EDGES
+    }
 }
 
 impl Vertex {
@@ -34,9 +84,24 @@ impl Vertex {
                     format: wgpu::VertexFormat::Float32x3,
                 },
                 wgpu::VertexAttribute {
-                    offset: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+                    offset: std::mem::offset_of!(Vertex, normal) as wgpu::BufferAddress,
                     shader_location: 1,
-                    format: wgpu::VertexFormat::Float32x3,
+                    format: wgpu::VertexFormat::Snorm16x4,
+                },
+                wgpu::VertexAttribute {
+                    offset: std::mem::offset_of!(Vertex, color) as wgpu::BufferAddress,
+                    shader_location: 2,
+                    format: wgpu::VertexFormat::Unorm8x4,
+                },
+                wgpu::VertexAttribute {
+                    offset: std::mem::offset_of!(Vertex, corner_flags) as wgpu::BufferAddress,
+                    shader_location: 3,
+                    format: wgpu::VertexFormat::Uint32,
+                },
+                wgpu::VertexAttribute {
+                    offset: std::mem::offset_of!(Vertex, uv) as wgpu::BufferAddress,
+                    shader_location: 4,
+                    format: wgpu::VertexFormat::Float32x2,
                 },
             ],
         }
@@ -56,7 +121,15 @@ pub(crate) struct Material {
     pub(crate) edge_color: [f32; 4],
     /// edge width
     pub(crate) edge_width: f32,
-    pub(crate) _pad: [f32; 3],
+    /// 1.0 to use the vertex colors instead of `kd`
+    pub(crate) vertex_color: f32,
+    /// 1.0 to modulate `kd` with the diffuse texture
+    pub(crate) textured: f32,
+    /// 1.0 to light the triangles with their own normal instead of the vertex normals
+    pub(crate) flat_shading: f32,
+    /// id of the model plus one, drawn by the picking pass where 0 is the background
+    pub(crate) pick_id: u32,
+    _padding: [u32; 3],
 }
 
 impl Material {
@@ -71,44 +144,132 @@ impl Material {
             ks: [ks.x, ks.y, ks.z, 1.0],
             edge_color: [0.0, 0.0, 0.0, 1.0],
             edge_width: 0.0,
-            _pad: [0.0; 3],
+            vertex_color: 0.0,
+            textured: 0.0,
+            flat_shading: 1.0,
+            pick_id: 0,
+            _padding: [0; 3],
         }
     }
 }
 
+/// The error raised by the GPU while uploading buffers, see [`checked_upload`].
+pub(crate) type UploadError = Pin<Box<dyn Future<Output = Option<wgpu::Error>>>>;
+
 pub(crate) struct MeshResources {
     material_bind_group: wgpu::BindGroup,
     material_buffer: Buffer,
+    chunks: Vec<Chunk>,
+    sampler: wgpu::Sampler,
+}
+
+/// Consecutive triangles of a mesh whose vertex and index buffers are each under the
+/// `max_buffer_size` of the device, see [`split_chunks`].
+struct Chunk {
+    /// range of the triangle corners of the chunk in `ViewData::indices`
+    corners: Range<usize>,
+    /// the vertices of the mesh used by the chunk, in the order of its vertex buffer
+    vertex_ids: Vec<u32>,
+    bbox: BBox,
     vertex_buffer: Buffer,
+    index_buffer: Buffer,
+}
+/// A triangle crossed by a ray, see [`ViewData::ray_hits`].
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct TriangleHit {
+    /// parameter of the point along the ray
+    pub(crate) t: f32,
+    /// index of the triangle among the triangles of the mesh
+    pub(crate) triangle: usize,
+    /// weights of the three corners of the triangle at the point
+    pub(crate) barycentric: [f32; 3],
 }
+
 pub(crate) struct ViewData {
+    /// the points of the mesh, split where the triangles sharing a point need different
+    /// vertices, see [`split_points`]
     vertices: Vec<Vertex>,
+    /// three vertices per triangle
+    indices: Vec<u32>,
+    /// index of the mesh point each vertex was split from
+    point_indices: Vec<u32>,
+    /// whether the vertices were split to give the corners of a point different normals
+    corner_normals: bool,
+    /// whether the vertices were split to give the corners of a point different texture
+    /// coordinates
+    corner_uvs: bool,
     pub(crate) material: Material,
+    texture: Option<web_sys::ImageBitmap>,
     pub(crate) dirty: DirtyFlags,
+    /// box of the vertices, known before they are uploaded
     pub(crate) bbox: BBox,
     pub(crate) pipeline: Option<MeshResources>,
     pub(crate) visible: bool,
+    /// time the model was hidden, in milliseconds
+    hidden_since: Option<f64>,
 }
 
 impl ViewData {
-    pub(crate) fn new(vertices: Vec<Vertex>, material: Material) -> Self {
-        Self {
+    pub(crate) fn new(
+        points: &[f64],
+        triangles: &[usize],
+        material: Material,
+    ) -> Result<Self, String> {
+        let n_points = points.len() / 3;
+        if n_points > u32::MAX as usize {
+            return Err(format!(
+                "the mesh has {} points, more than the {} that can be indexed",
+                n_points,
+                u32::MAX
+            ));
+        }
+        // a point gets at most one vertex per corner, so this bounds every split of the
+        // points, u32::MAX being left to mark the end of the vertices of a point
+        if triangles.len() >= u32::MAX as usize {
+            return Err(format!(
+                "the mesh has {} triangle corners, more than the {} vertices that can be indexed",
+                triangles.len(),
+                u32::MAX - 1
+            ));
+        }
+        let template = |idx: u32| {
+            let start = idx as usize * 3;
+            let p = &points[start..(start + 3)];
+            Vertex {
+                point: [p[0] as f32, p[1] as f32, p[2] as f32],
+                normal: [0; 4],
+                color: [255; 4],
+                corner_flags: 0,
+                uv: [0.0; 2],
+            }
+        };
+        let corner_points = Vec::from_iter(triangles.iter().map(|&idx| idx as u32));
+        let hidden_edges = vec![0; triangles.len() / 3];
+        let (vertices, indices, point_indices) =
+            split_points(n_points, template, &corner_points, &hidden_edges, None, None);
+        let bbox = box_from_points(&vertices);
+        Ok(Self {
             vertices,
+            indices,
+            point_indices,
+            corner_normals: false,
+            corner_uvs: false,
             material,
+            texture: None,
             dirty: DirtyFlags::DIRTY_ALL,
-            bbox: BBox::default(),
+            bbox,
             pipeline: None,
             visible: true,
-        }
+            hidden_since: None,
+        })
     }
 
+    /// Creates the buffers of the mesh, returning the error of the upload of its chunks.
     pub(crate) fn init_resources(
         &mut self,
         render: &Renderer,
         material_bind_group_layout: &wgpu::BindGroupLayout,
-    ) {
-        let material_bind_group_layout = material_bind_group_layout;
-
+    ) -> UploadError {
         let material_buffer = render
             .device
             .create_buffer_init(&wgpu::util::BufferInitDescriptor {
@@ -117,52 +278,348 @@ impl ViewData {
                 usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
             });
 
-        let material_bind_group = render.device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &material_bind_group_layout,
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: material_buffer.as_entire_binding(),
-            }],
-            label: None,
+        let sampler = render.device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::Repeat,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
         });
-
-
-
-        let vertex_buffer = render
-            .device
-            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: Some("vertex_buffer"),
-                contents: bytemuck::cast_slice(&self.vertices),
-                usage: wgpu::BufferUsages::VERTEX |
-                // allow it to be the destination for [`Queue::write_buffer`] operation
-                wgpu::BufferUsages::COPY_DST,
-            });
+        let texture_view = self.create_texture_view(render);
+        let material_bind_group = create_material_bind_group(
+            render,
+            material_bind_group_layout,
+            &material_buffer,
+            &texture_view,
+            &sampler,
+        );
+        self.dirty.remove(DirtyFlags::DIRTY_TEXTURE);
 
         self.pipeline = Some(MeshResources {
             material_bind_group,
             material_buffer,
-            vertex_buffer,
+            chunks: vec![],
+            sampler,
+        });
+        self.update_chunks(render)
+    }
+
+    /// Uploads the diffuse texture, or a single white texel when there is none.
+    fn create_texture_view(&self, render: &Renderer) -> wgpu::TextureView {
+        let (width, height) = self
+            .texture
+            .as_ref()
+            .map_or((1, 1), |bitmap| (bitmap.width(), bitmap.height()));
+        let size = wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        };
+        let texture = render.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("diffuse_texture"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_DST
+                | wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        });
+        match &self.texture {
+            Some(bitmap) => render.queue.copy_external_image_to_texture(
+                &wgpu::CopyExternalImageSourceInfo {
+                    source: wgpu::ExternalImageSource::ImageBitmap(bitmap.clone()),
+                    origin: wgpu::Origin2d::ZERO,
+                    flip_y: false,
+                },
+                wgpu::CopyExternalImageDestInfo {
+                    texture: &texture,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d::ZERO,
+                    aspect: wgpu::TextureAspect::All,
+                    color_space: wgpu::PredefinedColorSpace::Srgb,
+                    premultiplied_alpha: false,
+                },
+                size,
+            ),
+            None => render.queue.write_texture(
+                wgpu::TexelCopyTextureInfo {
+                    texture: &texture,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d::ZERO,
+                    aspect: wgpu::TextureAspect::All,
+                },
+                &[255; 4],
+                wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(4),
+                    rows_per_image: None,
+                },
+                size,
+            ),
+        }
+        texture.create_view(&wgpu::TextureViewDescriptor::default())
+    }
+
+    /// Sets the per point `normals` (three values per mesh point), or goes back to the
+    /// face normals when `None`.
+    pub(crate) fn set_vertex_normals(&mut self, normals: Option<&[f32]>) {
+        if self.corner_normals {
+            self.corner_normals = false;
+            self.resplit(None, None, None);
+        }
+        if let Some(normals) = normals {
+            for (v, &idx) in self.vertices.iter_mut().zip(&self.point_indices) {
+                let start = idx as usize * 3;
+                v.normal = pack_normal([normals[start], normals[start + 1], normals[start + 2]]);
+            }
+            self.material.flat_shading = 0.0;
+            self.dirty.insert(DirtyFlags::DIRTY_VERTEX);
+        } else {
+            // the face normals are computed by the fragment shader
+            self.material.flat_shading = 1.0;
+        }
+        self.dirty.insert(DirtyFlags::DIRTY_MATERIAL);
+    }
+
+    /// Sets one normal per triangle corner, three values per corner, splitting the points
+    /// whose corners have different normals.
+    pub(crate) fn set_corner_normals(&mut self, normals: &[f32]) {
+        self.resplit(None, Some(normals), None);
+        self.corner_normals = true;
+        self.material.flat_shading = 0.0;
+        self.dirty.insert(DirtyFlags::DIRTY_MATERIAL);
+    }
+
+    /// The mesh point of every triangle corner.
+    fn corner_points(&self) -> Vec<u32> {
+        Vec::from_iter(self.indices.iter().map(|&v| self.point_indices[v as usize]))
+    }
+
+    /// Splits the points again for new hidden edges, corner normals or corner texture
+    /// coordinates, keeping the current ones when `None`. The other attributes of the points
+    /// are kept. Only the vertices are uploaded again when the triangles keep their vertices,
+    /// as with smooth normals or a crease angle that splits the same points.
+    fn resplit(
+        &mut self,
+        hidden_edges: Option<Vec<u32>>,
+        corner_normals: Option<&[f32]>,
+        corner_uvs: Option<&[f32]>,
+    ) {
+        let corner_points = self.corner_points();
+        let hidden_edges = hidden_edges.unwrap_or_else(|| {
+            Vec::from_iter(
+                self.indices
+                    .chunks_exact(3)
+                    .map(|tri| self.vertices[tri[0] as usize].hidden_edges()),
+            )
         });
+        let current_normals;
+        let corner_normals = match corner_normals {
+            Some(normals) => Some(normals),
+            None if self.corner_normals => {
+                current_normals = Vec::from_iter(
+                    self.indices
+                        .iter()
+                        .flat_map(|&v| unpack_normal(self.vertices[v as usize].normal)),
+                );
+                Some(current_normals.as_slice())
+            }
+            None => None,
+        };
+        let current_uvs;
+        let corner_uvs = match corner_uvs {
+            Some(uvs) => Some(uvs),
+            None if self.corner_uvs => {
+                current_uvs =
+                    Vec::from_iter(self.indices.iter().flat_map(|&v| self.vertices[v as usize].uv));
+                Some(current_uvs.as_slice())
+            }
+            None => None,
+        };
+        let n_points = self.point_indices.iter().max().map_or(0, |&idx| idx as usize + 1);
+        // any vertex of a point carries its attributes
+        let mut vertex_of_point = vec![0; n_points];
+        for (v, &idx) in self.point_indices.iter().enumerate() {
+            vertex_of_point[idx as usize] = v;
+        }
+        let template = |idx: u32| self.vertices[vertex_of_point[idx as usize]];
+        let (vertices, indices, point_indices) = split_points(
+            n_points,
+            template,
+            &corner_points,
+            &hidden_edges,
+            corner_normals,
+            corner_uvs,
+        );
+        if indices != self.indices {
+            self.dirty.insert(DirtyFlags::DIRTY_INDEX);
+        }
+        self.vertices = vertices;
+        self.indices = indices;
+        self.point_indices = point_indices;
+        self.dirty.insert(DirtyFlags::DIRTY_VERTEX);
     }
 
+    /// Sets one pair of texture coordinates per triangle corner, splitting the points whose
+    /// corners have different ones.
+    pub(crate) fn set_uvs(&mut self, uvs: &[f32]) {
+        // OBJ puts the origin at the bottom left of the image, wgpu at the top left
+        let uvs = Vec::from_iter(uvs.chunks_exact(2).flat_map(|uv| [uv[0], 1.0 - uv[1]]));
+        self.resplit(None, None, Some(&uvs));
+        self.corner_uvs = true;
+    }
+
+    /// Sets the diffuse texture sampled with the texture coordinates.
+    pub(crate) fn set_texture(&mut self, texture: Option<web_sys::ImageBitmap>) {
+        self.material.textured = if texture.is_some() { 1.0 } else { 0.0 };
+        self.texture = texture;
+        self.dirty
+            .insert(DirtyFlags::DIRTY_TEXTURE | DirtyFlags::DIRTY_MATERIAL);
+    }
+
+    /// Sets the per point `colors` (three values per mesh point), or goes back to the
+    /// material color when `None`.
+    pub(crate) fn set_vertex_colors(&mut self, colors: Option<&[f32]>) {
+        if let Some(colors) = colors {
+            for (v, &idx) in self.vertices.iter_mut().zip(&self.point_indices) {
+                let start = idx as usize * 3;
+                v.color = pack_color(&colors[start..start + 3]);
+            }
+            self.material.vertex_color = 1.0;
+            self.dirty.insert(DirtyFlags::DIRTY_VERTEX);
+        } else {
+            self.material.vertex_color = 0.0;
+        }
+        self.dirty.insert(DirtyFlags::DIRTY_MATERIAL);
+    }
+
+    /// Hides the diagonals of the polygons the triangles were made from, i.e. the edges
+    /// shared by two triangles of the same polygon.
+    pub(crate) fn set_polygons(&mut self, arities: &[usize]) {
+        let n_triangles: usize = arities.iter().map(|&n| n.saturating_sub(2)).sum();
+        if n_triangles * 3 != self.indices.len() {
+            return;
+        }
+        let corner_points = self.corner_points();
+        let mut hidden_edges = vec![0; n_triangles];
+        let mut start = 0;
+        for &arity in arities {
+            let end = start + arity.saturating_sub(2) * 3;
+            let corners = &corner_points[start..end];
+            let edge = |tri: &[u32], k: usize| {
+                let (a, b) = (tri[(k + 1) % 3], tri[(k + 2) % 3]);
+                (a.min(b), a.max(b))
+            };
+            for (t, tri) in corners.chunks(3).enumerate() {
+                let mut hidden = 0;
+                for k in 0..3 {
+                    let e = edge(tri, k);
+                    let shared = corners
+                        .chunks(3)
+                        .enumerate()
+                        .any(|(s, other)| s != t && (0..3).any(|j| edge(other, j) == e));
+                    if shared {
+                        hidden |= 1 << k;
+                    }
+                }
+                hidden_edges[start / 3 + t] = hidden;
+            }
+            start = end;
+        }
+        self.resplit(Some(hidden_edges), None, None);
+    }
+
+    #[inline]
+    pub(crate) fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    /// Shows or hides the model at the time `now` in milliseconds.
     #[inline]
-    pub(crate) fn set_visible(&mut self, visible: bool) {
+    pub(crate) fn set_visible(&mut self, visible: bool, now: f64) {
+        if visible {
+            self.hidden_since = None;
+        } else if self.visible {
+            self.hidden_since = Some(now);
+        }
         self.visible = visible;
     }
 
+    /// Frees the GPU buffers of the model if it was hidden for more than `delay`
+    /// milliseconds at the time `now`. They are created again when it is shown.
+    pub(crate) fn release_if_hidden(&mut self, now: f64, delay: f64) {
+        if self.hidden_since.is_some_and(|since| now - since > delay) {
+            self.pipeline = None;
+        }
+    }
+
     #[inline]
     fn update_box(&mut self) {
-        self.bbox = box_from_points(&self.vertices);
+        let mut bbox = BBox::default();
+        for chunk in &self.pipeline.as_ref().unwrap().chunks {
+            bbox.merge_box(&chunk.bbox);
+        }
+        self.bbox = bbox;
     }
 
-    #[inline]
-    pub(crate) fn update_vertex_buffer(&mut self, render: &Renderer) {
-        render.queue.write_buffer(
-            &self.pipeline.as_ref().unwrap().vertex_buffer,
-            0,
-            bytemuck::cast_slice(&self.vertices),
-        );
+    /// Uploads the vertices again, the triangles being unchanged.
+    pub(crate) fn update_vertex_buffer(&mut self, render: &Renderer) -> UploadError {
+        let resources = self.pipeline.as_mut().unwrap();
+        let error = checked_upload(render, || {
+            for chunk in &mut resources.chunks {
+                let vertices =
+                    Vec::from_iter(chunk.vertex_ids.iter().map(|&v| self.vertices[v as usize]));
+                write_buffer(render, &mut chunk.vertex_buffer, bytemuck::cast_slice(&vertices));
+                chunk.bbox = box_from_points(&vertices);
+            }
+        });
+        self.update_box();
+        self.dirty.remove(DirtyFlags::DIRTY_VERTEX);
+        error
+    }
+
+    /// Splits the triangles into chunks again and uploads their vertices and indices.
+    pub(crate) fn update_chunks(&mut self, render: &Renderer) -> UploadError {
+        let max_buffer_size = render.device.limits().max_buffer_size;
+        let chunks = split_chunks(&self.indices, self.vertices.len(), max_buffer_size);
+        let resources = self.pipeline.as_mut().unwrap();
+        let error = checked_upload(render, || {
+            let chunks = chunks.into_iter().map(|(corners, vertex_ids, indices)| {
+                let vertices =
+                    Vec::from_iter(vertex_ids.iter().map(|&v| self.vertices[v as usize]));
+                let vertex_buffer = render
+                    .device
+                    .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                        label: Some("vertex_buffer"),
+                        contents: bytemuck::cast_slice(&vertices),
+                        usage: wgpu::BufferUsages::VERTEX |
+                        // allow it to be the destination for [`Queue::write_buffer`] operation
+                        wgpu::BufferUsages::COPY_DST,
+                    });
+                let index_buffer = render
+                    .device
+                    .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                        label: Some("index_buffer"),
+                        contents: bytemuck::cast_slice(&indices),
+                        usage: wgpu::BufferUsages::INDEX | wgpu::BufferUsages::COPY_DST,
+                    });
+                Chunk {
+                    corners,
+                    vertex_ids,
+                    bbox: box_from_points(&vertices),
+                    vertex_buffer,
+                    index_buffer,
+                }
+            });
+            resources.chunks = Vec::from_iter(chunks);
+        });
         self.update_box();
+        self.dirty
+            .remove(DirtyFlags::DIRTY_VERTEX | DirtyFlags::DIRTY_INDEX);
+        error
     }
 
     #[inline]
@@ -174,31 +631,296 @@ impl ViewData {
         )
     }
 
+    pub(crate) fn update_texture(
+        &mut self,
+        render: &Renderer,
+        material_bind_group_layout: &wgpu::BindGroupLayout,
+    ) {
+        let texture_view = self.create_texture_view(render);
+        let resources = self.pipeline.as_mut().unwrap();
+        resources.material_bind_group = create_material_bind_group(
+            render,
+            material_bind_group_layout,
+            &resources.material_buffer,
+            &texture_view,
+            &resources.sampler,
+        );
+    }
+
+    /// Every triangle hit by the ray from `origin` along `dir`, both in the coordinates of
+    /// the points. Only the drawn chunks whose box the ray goes through are tested.
+    pub(crate) fn ray_hits(&self, origin: &Vector3<f32>, dir: &Vector3<f32>) -> Vec<TriangleHit> {
+        let Some(resources) = &self.pipeline else {
+            return vec![];
+        };
+        let point = |v: u32| Vector3::from(self.vertices[v as usize].point);
+        let mut hits = vec![];
+        for chunk in resources.chunks.iter().filter(|chunk| chunk.bbox.hit_by_ray(origin, dir)) {
+            let first = chunk.corners.start / 3;
+            for (k, tri) in self.indices[chunk.corners.clone()].chunks_exact(3).enumerate() {
+                let hit = ray_triangle(origin, dir, [point(tri[0]), point(tri[1]), point(tri[2])]);
+                if let Some((t, u, v)) = hit {
+                    hits.push(TriangleHit {
+                        t,
+                        triangle: first + k,
+                        barycentric: [1.0 - u - v, u, v],
+                    });
+                }
+            }
+        }
+        hits
+    }
+
+    /// Whether the model is drawn with the transparent ones, after the opaque ones.
+    #[inline]
+    pub(crate) fn is_transparent(&self) -> bool {
+        self.material.kd[3] < 0.999
+    }
+
     pub(crate) fn render<'b, 'a: 'b>(
         &'a self,
         render_pass: &'b mut RenderPass<'a>,
-        pipeline_cull_back: &'a RenderPipeline,
-        pipeline_cull_front: &'a RenderPipeline,
+        pipeline: &'a RenderPipeline,
     ) {
         let pipeline_data = self.pipeline.as_ref().unwrap();
+        render_pass.set_pipeline(pipeline);
         render_pass.set_bind_group(1, &pipeline_data.material_bind_group, &[]);
-        render_pass.set_vertex_buffer(0, pipeline_data.vertex_buffer.slice(..));
-        
-        // Check alpha for transparency
-        if self.material.kd[3] < 0.999 {
-            // Pass 1: Draw back faces (Cull Front)
-            render_pass.set_pipeline(pipeline_cull_front);
-            render_pass.draw(0..self.vertices.len() as u32, 0..1);
-
-            // Pass 2: Draw front faces (Cull Back)
-            render_pass.set_pipeline(pipeline_cull_back);
-            render_pass.draw(0..self.vertices.len() as u32, 0..1);
-        } else {
-             // Opaque: Draw front faces (Cull Back)
-             render_pass.set_pipeline(pipeline_cull_back);
-             render_pass.draw(0..self.vertices.len() as u32, 0..1);
+        for chunk in &pipeline_data.chunks {
+            render_pass.set_vertex_buffer(0, chunk.vertex_buffer.slice(..));
+            render_pass.set_index_buffer(
+                chunk.index_buffer.slice(..),
+                wgpu::IndexFormat::Uint32,
+            );
+            let n_indices = chunk.corners.len() as u32;
+            render_pass.draw_indexed(0..n_indices, 0, 0..1);
+        }
+    }
+}
+
+/// Parameter along `dir` of the point where the ray from `origin` hits the triangle, and
+/// the weights of its second and third corners at that point, with the Möller–Trumbore
+/// algorithm.
+fn ray_triangle(
+    origin: &Vector3<f32>,
+    dir: &Vector3<f32>,
+    [a, b, c]: [Vector3<f32>; 3],
+) -> Option<(f32, f32, f32)> {
+    let ab = b - a;
+    let ac = c - a;
+    let p = dir.cross(ac);
+    let det = ab.dot(p);
+    // relative to the sizes, as small or finely tessellated meshes give tiny determinants
+    if det.abs() <= f32::EPSILON * ab.magnitude() * ac.magnitude() * dir.magnitude() {
+        return None;
+    }
+    let inv_det = 1.0 / det;
+    let ao = origin - a;
+    let u = ao.dot(p) * inv_det;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+    let q = ao.cross(ab);
+    let v = dir.dot(q) * inv_det;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+    let t = ac.dot(q) * inv_det;
+    (t > 0.0).then_some((t, u, v))
+}
+
+/// Runs `upload` in error scopes of the device, so that running out of memory or exceeding
+/// its limits is reported rather than leaving buffers that draw nothing. Gives the out of
+/// memory error rather than the validation one when both happened.
+fn checked_upload(render: &Renderer, upload: impl FnOnce()) -> UploadError {
+    let out_of_memory = render.device.push_error_scope(wgpu::ErrorFilter::OutOfMemory);
+    let validation = render.device.push_error_scope(wgpu::ErrorFilter::Validation);
+    upload();
+    // the scopes are popped in the reverse order
+    let validation = validation.pop();
+    let out_of_memory = out_of_memory.pop();
+    Box::pin(async move {
+        let validation = validation.await;
+        out_of_memory.await.or(validation)
+    })
+}
+
+/// Writes `contents` to `buffer`, creating a new buffer with the same usage when the size
+/// changed.
+fn write_buffer(render: &Renderer, buffer: &mut Buffer, contents: &[u8]) {
+    if buffer.size() == contents.len() as wgpu::BufferAddress {
+        render.queue.write_buffer(buffer, 0, contents);
+    } else {
+        *buffer = render
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: None,
+                contents,
+                usage: buffer.usage(),
+            });
+    }
+}
+
+/// Splits the points so that the three vertices of every triangle are in three distinct
+/// corners, giving the barycentric coordinates the edges are drawn with. A point gets a
+/// vertex for each corner it is in, plus one per normal when there are `corner_normals`
+/// (three values per triangle corner) and per pair of texture coordinates when there are
+/// `corner_uvs` (two values per triangle corner), while the triangles with `hidden_edges`
+/// get their own three vertices. `template` gives the vertex of a point, and the triangles
+/// are given by `corner_points`. Returns the vertices, the triangles and the point of each
+/// vertex.
+fn split_points(
+    n_points: usize,
+    template: impl Fn(u32) -> Vertex,
+    corner_points: &[u32],
+    hidden_edges: &[u32],
+    corner_normals: Option<&[f32]>,
+    corner_uvs: Option<&[f32]>,
+) -> (Vec<Vertex>, Vec<u32>, Vec<u32>) {
+    const NONE: u32 = u32::MAX;
+    let mut vertices: Vec<Vertex> = Vec::with_capacity(n_points);
+    let mut point_indices = Vec::with_capacity(n_points);
+    let mut indices = Vec::with_capacity(corner_points.len());
+    // the vertices of a point are chained from `first[point]` through `next[vertex]`
+    let mut first = vec![NONE; n_points];
+    let mut next = Vec::with_capacity(n_points);
+    for (t, tri) in corner_points.chunks_exact(3).enumerate() {
+        let hidden = hidden_edges[t];
+        // corners taken by the previous vertices of the triangle
+        let mut taken = 0u32;
+        for (k, &idx) in tri.iter().enumerate() {
+            let normal = corner_normals.map(|normals| {
+                let start = (t * 3 + k) * 3;
+                // compared once packed, as the normals of the vertices are
+                pack_normal([normals[start], normals[start + 1], normals[start + 2]])
+            });
+            let uv = corner_uvs.map(|uvs| {
+                let start = (t * 3 + k) * 2;
+                [uvs[start], uvs[start + 1]]
+            });
+            let mut found = NONE;
+            if hidden == 0 {
+                let mut v = first[idx as usize];
+                while v != NONE {
+                    let vertex = &vertices[v as usize];
+                    if taken & (1 << vertex.corner()) == 0
+                        && normal.is_none_or(|normal| vertex.normal == normal)
+                        && uv.is_none_or(|uv| vertex.uv == uv)
+                    {
+                        found = v;
+                        break;
+                    }
+                    v = next[v as usize];
+                }
+            }
+            if found == NONE {
+                let corner = if hidden == 0 {
+                    (!taken).trailing_zeros()
+                } else {
+                    k as u32
+                };
+                let mut vertex = template(idx);
+                vertex.corner_flags = (corner << CORNER_SHIFT) | hidden;
+                if let Some(normal) = normal {
+                    vertex.normal = normal;
+                }
+                if let Some(uv) = uv {
+                    vertex.uv = uv;
+                }
+                found = u32::try_from(vertices.len())
+                    .ok()
+                    .filter(|&v| v != NONE)
+                    .expect("at most one vertex per corner, checked by ViewData::new");
+                vertices.push(vertex);
+                point_indices.push(idx);
+                if hidden == 0 {
+                    next.push(first[idx as usize]);
+                    first[idx as usize] = found;
+                } else {
+                    next.push(NONE);
+                }
+            }
+            taken |= 1 << vertices[found as usize].corner();
+            indices.push(found);
+        }
+    }
+    (vertices, indices, point_indices)
+}
+
+/// Splits the triangles given by `indices` into runs of consecutive triangles whose
+/// vertices and indices each fit in `max_buffer_size` bytes. Returns, for every chunk, the
+/// range of its corners in `indices`, the vertices it uses and its triangles indexing them.
+fn split_chunks(
+    indices: &[u32],
+    n_vertices: usize,
+    max_buffer_size: u64,
+) -> Vec<(Range<usize>, Vec<u32>, Vec<u32>)> {
+    const NONE: u32 = u32::MAX;
+    let max_vertices = (max_buffer_size / std::mem::size_of::<Vertex>() as u64) as usize;
+    let max_indices = (max_buffer_size / std::mem::size_of::<u32>() as u64) as usize;
+    let mut chunks = vec![];
+    // index of each vertex of the mesh in the current chunk
+    let mut local = vec![NONE; n_vertices];
+    let mut start = 0;
+    let mut vertex_ids = vec![];
+    let mut chunk_indices = vec![];
+    for (t, tri) in indices.chunks_exact(3).enumerate() {
+        let new_vertices = tri
+            .iter()
+            .enumerate()
+            .filter(|&(k, &v)| local[v as usize] == NONE && !tri[..k].contains(&v))
+            .count();
+        if vertex_ids.len() + new_vertices > max_vertices || chunk_indices.len() + 3 > max_indices
+        {
+            for &v in &vertex_ids {
+                local[v as usize] = NONE;
+            }
+            let end = t * 3;
+            chunks.push((
+                start..end,
+                std::mem::take(&mut vertex_ids),
+                std::mem::take(&mut chunk_indices),
+            ));
+            start = end;
         }
+        for &v in tri {
+            if local[v as usize] == NONE {
+                local[v as usize] = vertex_ids.len() as u32;
+                vertex_ids.push(v);
+            }
+            chunk_indices.push(local[v as usize]);
+        }
+    }
+    if !chunk_indices.is_empty() {
+        chunks.push((start..indices.len(), vertex_ids, chunk_indices));
     }
+    chunks
+}
+
+fn create_material_bind_group(
+    render: &Renderer,
+    layout: &wgpu::BindGroupLayout,
+    material_buffer: &Buffer,
+    texture_view: &wgpu::TextureView,
+    sampler: &wgpu::Sampler,
+) -> wgpu::BindGroup {
+    render.device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: material_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::TextureView(texture_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::Sampler(sampler),
+            },
+        ],
+        label: None,
+    })
 }
 
 #[inline]
@@ -1,17 +1,171 @@
 use anyhow::Result;
-use cgmath::{InnerSpace, Quaternion, Rad, Rotation3, Vector3};
-use std::{cell::RefCell, collections::HashMap, rc::Rc};
-use winit::dpi::PhysicalPosition;
+use cgmath::{InnerSpace, One, Quaternion, Rad, Rotation, Rotation3, Vector3, Zero};
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    f32::consts::{FRAC_1_SQRT_2, FRAC_PI_2, FRAC_PI_4, PI},
+    rc::Rc,
+};
+use winit::{dpi::PhysicalPosition, keyboard::KeyCode};
 
-use crate::render::view_data::{Material, Vertex};
+use crate::render::view_data::Material;
 
-use super::{render::Renderer, view_core::ViewCore, view_data::ViewData};
+use super::{
+    render::Renderer,
+    view_core::{Framing, Ray, ViewCore},
+    view_data::ViewData,
+};
 pub enum MousePressed {
+    /// rotating from the position and the rotation of the scene, or the orientation of the
+    /// camera in [`Navigation::Fly`], when the drag started
     Left(Option<(PhysicalPosition<f64>, Quaternion<f32>)>),
-    Right(Option<PhysicalPosition<f64>>),
+    /// the right or the middle button, panning from the position and the translation of
+    /// the view when the drag started
+    Right(Option<(PhysicalPosition<f64>, Vector3<f32>)>),
     None,
 }
 
+/// How the left drag and the keyboard move the camera.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Navigation {
+    /// rotates around the axes of the screen, keeping the vertical axis of the scene in the
+    /// vertical plane
+    TwoAxisValuator,
+    /// rolls the scene like a ball under the cursor
+    Trackball,
+    /// spins the scene around its Z axis, kept upright
+    TurntableZUp,
+    /// spins the scene around its Y axis, kept upright
+    TurntableYUp,
+    /// looks around with the drag and walks with the W, A, S and D keys, Q and E going down
+    /// and up
+    Fly,
+}
+
+impl Navigation {
+    pub const ALL: [Self; 5] = [
+        Self::TwoAxisValuator,
+        Self::Trackball,
+        Self::TurntableZUp,
+        Self::TurntableYUp,
+        Self::Fly,
+    ];
+
+    pub fn value(self) -> &'static str {
+        match self {
+            Self::TwoAxisValuator => "two-axis",
+            Self::Trackball => "trackball",
+            Self::TurntableZUp => "turntable-z",
+            Self::TurntableYUp => "turntable-y",
+            Self::Fly => "fly",
+        }
+    }
+
+    pub fn from_value(value: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|navigation| navigation.value() == value)
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::TwoAxisValuator => "Two-axis",
+            Self::Trackball => "Trackball",
+            Self::TurntableZUp => "Turntable (Z up)",
+            Self::TurntableYUp => "Turntable (Y up)",
+            Self::Fly => "Fly (WASD)",
+        }
+    }
+}
+
+/// Standard views of the scene, centered and framed, see [`Viewer::set_view`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ViewPreset {
+    Front,
+    Back,
+    Left,
+    Right,
+    Top,
+    Bottom,
+    /// from the front, right and top
+    Isometric,
+}
+
+impl ViewPreset {
+    /// The views facing a side of the scene, the faces of the view cube.
+    pub const FACES: [Self; 6] = [
+        Self::Front,
+        Self::Back,
+        Self::Left,
+        Self::Right,
+        Self::Top,
+        Self::Bottom,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Front => "Front",
+            Self::Back => "Back",
+            Self::Left => "Left",
+            Self::Right => "Right",
+            Self::Top => "Top",
+            Self::Bottom => "Bottom",
+            Self::Isometric => "Iso",
+        }
+    }
+
+    /// Rotation of the scene for this view, the Z axis of the scene going up when `z_up`
+    /// and its Y axis otherwise.
+    pub fn rotation(self, z_up: bool) -> Quaternion<f32> {
+        let around_x = |angle| Quaternion::from_axis_angle(Vector3::unit_x(), Rad(angle));
+        let around_y = |angle| Quaternion::from_axis_angle(Vector3::unit_y(), Rad(angle));
+        let y_up = match self {
+            Self::Front => Quaternion::one(),
+            Self::Back => around_y(PI),
+            Self::Left => around_y(FRAC_PI_2),
+            Self::Right => around_y(-FRAC_PI_2),
+            Self::Top => around_x(FRAC_PI_2),
+            Self::Bottom => around_x(-FRAC_PI_2),
+            // the diagonal of the cube towards the viewer
+            Self::Isometric => around_x(FRAC_1_SQRT_2.atan()) * around_y(-FRAC_PI_4),
+        };
+        if z_up {
+            // the Z axis goes up the screen, the front being the side of -Y
+            y_up * around_x(-FRAC_PI_2)
+        } else {
+            y_up
+        }
+    }
+}
+
+/// What is drawn at a pixel of the canvas, see [`Viewer::pick`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Pick {
+    /// id of the model, as returned by [`Viewer::append_mesh`]
+    pub model: u32,
+    /// index of the triangle among the triangles of the mesh
+    pub triangle: usize,
+    /// weights of the three corners of the triangle at the picked point
+    pub barycentric: [f32; 3],
+    /// the picked point, in the coordinates of the points of the mesh
+    pub position: [f32; 3],
+}
+
+/// The point scrolling zooms towards, found by the GPU like [`Viewer::pick`] and kept while
+/// the cursor and the view stay in place, see [`Viewer::mouse_scroll`].
+#[derive(Default)]
+struct ZoomTarget {
+    /// the point under the cursor in the coordinates of the models, `Some(None)` being the
+    /// background, `None` until a pick resolves
+    point: Option<Option<Vector3<f32>>>,
+    /// the result of the last pick, for the scroll received while it was read back
+    resolved: Option<Option<Vector3<f32>>>,
+    picking: bool,
+    /// scroll received while picking, in pixels
+    pending_scroll: f64,
+    /// counts the moves of the cursor and the view, the picks started before the last one
+    /// only serving the scroll that started them
+    generation: u32,
+}
+
 pub struct Viewer {
     pub render: Rc<RefCell<Option<Renderer>>>,
     data: HashMap<u32, ViewData>,
@@ -20,7 +174,24 @@ pub struct Viewer {
 
     current_pos: PhysicalPosition<f64>,
     pub pressed_state: MousePressed,
-    data_dirty: bool,
+    /// models to frame at the next frame, once their boxes are known
+    pending_frame: Option<Framing>,
+    /// whether the view frames the whole scene again when a model is added
+    frame_new_models: bool,
+
+    navigation: Navigation,
+    /// multiplier of the speed of each navigation mode, in the order of [`Navigation::ALL`]
+    sensitivities: [f32; 5],
+    keys_down: HashSet<KeyCode>,
+    /// time of the last frame walked in [`Navigation::Fly`], in milliseconds
+    last_walk: Option<f64>,
+    /// called with the rotation of the scene in eye space when it changes
+    on_rotate: Option<Box<dyn Fn(Quaternion<f32>)>>,
+    last_rotation: Option<Quaternion<f32>>,
+    /// called with the GPU errors raised while uploading a model
+    on_upload_error: Option<Rc<dyn Fn(String)>>,
+    /// shared with the pick of the point under the cursor being read back
+    zoom_target: Rc<RefCell<ZoomTarget>>,
 }
 
 impl Viewer {
@@ -32,47 +203,175 @@ impl Viewer {
             view_core: ViewCore::default(),
             current_pos: PhysicalPosition { x: 0.0, y: 0.0 },
             pressed_state: MousePressed::None,
-            data_dirty: false,
+            pending_frame: None,
+            frame_new_models: false,
+            navigation: Navigation::TwoAxisValuator,
+            sensitivities: [1.0; 5],
+            keys_down: HashSet::new(),
+            last_walk: None,
+            on_rotate: None,
+            last_rotation: None,
+            on_upload_error: None,
+            zoom_target: Default::default(),
+        }
+    }
+
+    pub fn orthographic(&self) -> bool {
+        self.view_core.orthographic
+    }
+
+    pub fn set_orthographic(&mut self, orthographic: bool) {
+        self.view_core.orthographic = orthographic;
+        self.forget_zoom_target();
+    }
+
+    pub fn reversed_z(&self) -> bool {
+        self.view_core.reversed_z
+    }
+
+    /// Sets whether the depth buffer stores 1 at the near plane and 0 at the far one rather
+    /// than the other way around, which avoids the flickering of close faces far away.
+    pub fn set_reversed_z(&mut self, reversed_z: bool) {
+        self.view_core.reversed_z = reversed_z;
+    }
+
+    pub fn frame_new_models(&self) -> bool {
+        self.frame_new_models
+    }
+
+    /// Sets whether adding a model frames the whole scene again. The first model is framed
+    /// either way.
+    pub fn set_frame_new_models(&mut self, frame_new_models: bool) {
+        self.frame_new_models = frame_new_models;
+    }
+
+    /// Moves the camera so that every model is in view, keeping its orientation.
+    pub fn fit_all(&mut self) {
+        self.pending_frame = Some(Framing::All);
+        self.forget_zoom_target();
+    }
+
+    /// Moves the camera so that the models `ids` fill the view, keeping its orientation.
+    pub fn frame_models(&mut self, ids: Vec<u32>) {
+        self.pending_frame = Some(Framing::Models(ids));
+        self.forget_zoom_target();
+    }
+
+    /// Whether the Z axis of the scene is its vertical one, for the views and the view cube.
+    pub fn z_up(&self) -> bool {
+        self.navigation == Navigation::TurntableZUp
+    }
+
+    /// Moves the camera smoothly to `preset`, looking at the center of the whole scene.
+    pub fn set_view(&mut self, preset: ViewPreset) {
+        // framed again on the way, the models shown may have changed since the last framing
+        self.pending_frame = Some(Framing::All);
+        self.view_core
+            .animate_to(preset.rotation(self.z_up()), js_sys::Date::now());
+        self.forget_zoom_target();
+    }
+
+    /// Sets the function called with the rotation of the scene in eye space whenever it
+    /// changes, to orient the view cube.
+    pub fn on_rotate(&mut self, on_rotate: impl Fn(Quaternion<f32>) + 'static) {
+        self.on_rotate = Some(Box::new(on_rotate));
+        self.last_rotation = None;
+    }
+
+    /// Sets the function called with the error of the GPU when it fails to take the buffers
+    /// of a model, running out of memory for instance.
+    pub fn on_upload_error(&mut self, on_upload_error: impl Fn(String) + 'static) {
+        self.on_upload_error = Some(Rc::new(on_upload_error));
+    }
+
+    pub fn navigation(&self) -> Navigation {
+        self.navigation
+    }
+
+    /// Changes the navigation mode. The turntables put the scene upright, seen from the
+    /// front, and leaving the fly mode looks at the center of the scene again.
+    pub fn set_navigation(&mut self, navigation: Navigation) {
+        if navigation == self.navigation {
+            return;
         }
+        self.view_core.stop_animation();
+        match navigation {
+            Navigation::TurntableZUp => {
+                self.view_core.trackball_angle = ViewPreset::Front.rotation(true);
+            }
+            Navigation::TurntableYUp => {
+                self.view_core.trackball_angle = ViewPreset::Front.rotation(false);
+            }
+            _ => {}
+        }
+        if self.navigation == Navigation::Fly {
+            self.view_core.camera_look = Quaternion::one();
+        }
+        self.navigation = navigation;
+    }
+
+    pub fn sensitivity(&self, navigation: Navigation) -> f32 {
+        self.sensitivities[navigation as usize]
     }
 
+    pub fn set_sensitivity(&mut self, navigation: Navigation, sensitivity: f32) {
+        self.sensitivities[navigation as usize] = sensitivity;
+    }
+
+    /// Records the keys held down, which move the camera in [`Navigation::Fly`].
+    pub fn key_input(&mut self, key: KeyCode, pressed: bool) {
+        if pressed {
+            self.keys_down.insert(key);
+        } else {
+            self.keys_down.remove(&key);
+        }
+        self.forget_zoom_target();
+    }
+
+    /// Forgets the keys held down, whose release will not be received.
+    pub fn release_keys(&mut self) {
+        self.keys_down.clear();
+    }
+
+    /// Moves the camera in [`Navigation::Fly`] along the keys held down, for the time
+    /// elapsed since the last frame.
+    fn walk(&mut self) {
+        // scene sizes per second, the scene being scaled to a size of 1
+        const WALK_SPEED: f32 = 0.5;
+        let mut dir = Vector3::zero();
+        for key in &self.keys_down {
+            match key {
+                KeyCode::KeyW => dir.z -= 1.0,
+                KeyCode::KeyS => dir.z += 1.0,
+                KeyCode::KeyA => dir.x -= 1.0,
+                KeyCode::KeyD => dir.x += 1.0,
+                KeyCode::KeyQ => dir.y -= 1.0,
+                KeyCode::KeyE => dir.y += 1.0,
+                _ => {}
+            }
+        }
+        if self.navigation != Navigation::Fly || dir == Vector3::zero() {
+            self.last_walk = None;
+            return;
+        }
+        let now = js_sys::Date::now();
+        let elapsed = self
+            .last_walk
+            .replace(now)
+            .map_or(0.0, |last| ((now - last) / 1000.0) as f32);
+        let speed = WALK_SPEED * self.sensitivity(Navigation::Fly);
+        self.view_core.walk(dir.normalize() * speed * elapsed);
+        self.forget_zoom_target();
+    }
+
+    /// Adds a mesh to the scene and returns its id, or why it cannot be drawn by the GPU.
+    /// Meshes too large for a single buffer are drawn in chunks.
     pub fn append_mesh(
         &mut self,
         points: &[f64],
         triangles: &[usize],
         color: Option<Vector3<f32>>,
-    ) -> u32 {
-        let point = |idx| {
-            let start = idx * 3;
-            &points[start..(start + 3)]
-        };
-        let vertices = Vec::from_iter(
-            triangles
-                .chunks(3)
-                .map(|f| {
-                    let verts = [point(f[0]), point(f[1]), point(f[2])]
-                        .map(|p| Vector3::<f32>::new(p[0] as f32, p[1] as f32, p[2] as f32));
-                    let vab = verts[1] - verts[0];
-                    let vac = verts[2] - verts[0];
-                    let normal = vab.cross(vac).normalize();
-                    [
-                        Vertex {
-                            point: verts[0].into(),
-                            normal: normal.into(),
-                        },
-                        Vertex {
-                            point: verts[1].into(),
-                            normal: normal.into(),
-                        },
-                        Vertex {
-                            point: verts[2].into(),
-                            normal: normal.into(),
-                        },
-                    ]
-                })
-                .flatten(),
-        );
-
+    ) -> Result<u32, String> {
         let data_color = if let Some(color) = color {
             color
         } else {
@@ -82,14 +381,42 @@ impl Viewer {
                 js_sys::Math::random() as f32,
             )
         };
-        let data = ViewData::new(vertices, Material::new(data_color));
         let id = self.next_data_id;
+        let mut material = Material::new(data_color);
+        material.pick_id = id + 1;
+        let data = ViewData::new(points, triangles, material)?;
+        let nothing_shown = self.data.values().all(|data| !data.visible || data.is_empty());
+        if self.frame_new_models || nothing_shown {
+            self.pending_frame = Some(Framing::All);
+        }
         self.next_data_id += 1;
         self.data.insert(id, data);
-        id
+        self.forget_zoom_target();
+        Ok(id)
     }
 
     pub fn render(&mut self) -> Result<()> {
+        // milliseconds after which the GPU memory of a hidden model is freed, as sessions
+        // may hold many more models than are shown
+        const RELEASE_HIDDEN_AFTER: f64 = 30_000.0;
+        let now = js_sys::Date::now();
+        for data in self.data.values_mut() {
+            data.release_if_hidden(now, RELEASE_HIDDEN_AFTER);
+        }
+        self.walk();
+        self.zoom_resolved();
+        // the view moves by itself until the animation or the framing is done
+        if self.view_core.is_animating() || self.pending_frame.is_some() {
+            self.forget_zoom_target();
+        }
+        self.view_core.animate(now);
+        let rotation = self.view_core.camera_look * self.view_core.trackball_angle;
+        if self.last_rotation != Some(rotation) {
+            self.last_rotation = Some(rotation);
+            if let Some(on_rotate) = &self.on_rotate {
+                on_rotate(rotation);
+            }
+        }
         if let Some(render) = self.render.borrow().as_ref() {
             let texture = render.surface.get_current_texture()?;
             let view = texture
@@ -98,6 +425,7 @@ impl Viewer {
             let mut encoder = render
                 .device
                 .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+            let depth_clear = self.view_core.depth_clear();
             {
                 let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                     label: None,
@@ -113,8 +441,9 @@ impl Viewer {
                     depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
                         view: &render.depth_texture_view,
                         depth_ops: Some(wgpu::Operations {
-                            load: wgpu::LoadOp::Clear(1.0),
-                            store: wgpu::StoreOp::Discard,
+                            load: wgpu::LoadOp::Clear(depth_clear),
+                            // the transparent models are tested against it
+                            store: wgpu::StoreOp::Store,
                         }),
                         stencil_ops: None,
                     }),
@@ -123,15 +452,26 @@ impl Viewer {
                     multiview_mask: None,
                 });
                 render_pass.set_viewport(0.0, 0.0, render.w() as f32, render.h() as f32, 0.0, 1.0);
-                self.view_core.render(
+                let upload_errors = self.view_core.render(
                     render,
                     &mut render_pass,
                     &mut self.data,
-                    self.data_dirty,
+                    self.pending_frame.take(),
                     true,
                 );
-                self.data_dirty = false;
+                for (id, error) in upload_errors {
+                    let on_upload_error = self.on_upload_error.clone();
+                    wasm_bindgen_futures::spawn_local(async move {
+                        if let Some(error) = error.await
+                            && let Some(on_upload_error) = on_upload_error
+                        {
+                            on_upload_error(format!("model {}: {}", id, error));
+                        }
+                    });
+                }
             }
+            self.view_core
+                .render_transparent(render, &mut encoder, &view, &self.data);
             render.queue.submit(std::iter::once(encoder.finish()));
             texture.present();
         } else {
@@ -142,15 +482,23 @@ impl Viewer {
 
     pub fn mouse_move(&mut self, pos: PhysicalPosition<f64>) {
         self.current_pos = pos;
+        self.forget_zoom_target();
+        if !matches!(self.pressed_state, MousePressed::None) {
+            self.view_core.stop_animation();
+        }
         match &mut self.pressed_state {
             MousePressed::Left(left_pos) => {
                 if left_pos.is_none() {
-                    left_pos.replace((pos, self.view_core.trackball_angle));
+                    let rotation = match self.navigation {
+                        Navigation::Fly => self.view_core.camera_look,
+                        _ => self.view_core.trackball_angle,
+                    };
+                    left_pos.replace((pos, rotation));
                 }
             }
             MousePressed::Right(right_pos) => {
                 if right_pos.is_none() {
-                    right_pos.replace(pos);
+                    right_pos.replace((pos, self.view_core.camera_translation));
                 }
             }
             MousePressed::None => {}
@@ -158,42 +506,207 @@ impl Viewer {
 
         self.current_pos = pos;
 
-        match &self.pressed_state {
-            MousePressed::Left(left) => {
-                let (press_pos, press_quat) = left.as_ref().unwrap();
-                let render = self.render.borrow();
-                let render = render.as_ref().unwrap();
-                let quat = two_axis_valuator_fixed_up(
-                    render.w(),
-                    render.h(),
-                    4.0,
-                    press_quat,
-                    press_pos,
-                    &pos,
-                );
-                self.view_core.trackball_angle = quat;
+        if let MousePressed::Left(left) = &self.pressed_state {
+            let (press_pos, press_quat) = left.as_ref().unwrap();
+            let render = self.render.borrow();
+            let Some(render) = render.as_ref() else {
+                return;
+            };
+            let (w, h) = (render.w(), render.h());
+            let sensitivity = self.sensitivity(self.navigation);
+            match self.navigation {
+                Navigation::TwoAxisValuator => {
+                    self.view_core.trackball_angle = two_axis_valuator_fixed_up(
+                        w,
+                        h,
+                        4.0 * sensitivity,
+                        press_quat,
+                        press_pos,
+                        &pos,
+                    );
+                }
+                Navigation::Trackball => {
+                    self.view_core.trackball_angle =
+                        trackball(w, h, sensitivity, press_quat, press_pos, &pos);
+                }
+                Navigation::TurntableZUp | Navigation::TurntableYUp => {
+                    let up = if self.navigation == Navigation::TurntableZUp {
+                        Vector3::unit_z()
+                    } else {
+                        Vector3::unit_y()
+                    };
+                    self.view_core.trackball_angle =
+                        turntable(w, h, 4.0 * sensitivity, up, press_quat, press_pos, &pos);
+                }
+                // the camera turns to where the cursor goes, the scene the other way
+                Navigation::Fly => {
+                    self.view_core.camera_look = turntable(
+                        w,
+                        h,
+                        -2.0 * sensitivity,
+                        Vector3::unit_y(),
+                        press_quat,
+                        press_pos,
+                        &pos,
+                    );
+                }
             }
-            _ => {}
+        }
+
+        if let MousePressed::Right(Some((press_pos, press_translation))) = &self.pressed_state {
+            let render = self.render.borrow();
+            let Some(render) = render.as_ref() else {
+                return;
+            };
+            self.view_core.pan(
+                render,
+                *press_translation,
+                pos.x - press_pos.x,
+                pos.y - press_pos.y,
+            );
+        }
+    }
+
+    /// Finds what is drawn at the pixel `pos` of the canvas, `None` being the background.
+    /// The GPU draws the model ids there, which is read back asynchronously. WebGL2 shaders
+    /// have no primitive index, so the triangle is the one of that model hit by the ray
+    /// through the pixel at the distance the GPU found, only the triangles of that model
+    /// being searched once it is known.
+    pub fn pick(
+        viewer: &Rc<RefCell<Self>>,
+        pos: PhysicalPosition<f64>,
+    ) -> impl Future<Output = Option<Pick>> + 'static {
+        let pixel = viewer.borrow().read_pixel(pos);
+        let viewer = viewer.clone();
+        async move {
+            let (ray, model, distance) = pixel.await?;
+            let viewer = viewer.borrow();
+            let data = viewer.data.get(&model)?;
+            let hit = data
+                .ray_hits(&ray.origin, &ray.dir)
+                .into_iter()
+                .min_by(|a, b| (a.t - distance).abs().total_cmp(&(b.t - distance).abs()))?;
+            Some(Pick {
+                model,
+                triangle: hit.triangle,
+                barycentric: hit.barycentric,
+                position: ray.at(hit.t).into(),
+            })
         }
     }
 
+    /// The ray through the pixel at `pos`, with the model the GPU drew there and its
+    /// distance along the ray, `None` being the background.
+    fn read_pixel(
+        &self,
+        pos: PhysicalPosition<f64>,
+    ) -> impl Future<Output = Option<(Ray, u32, f32)>> + use<> {
+        let render = self.render.borrow();
+        let picked = render.as_ref().and_then(|render| {
+            let buffer = self.view_core.pick(render, &self.data, pos)?;
+            let (ray, _) = self.view_core.cursor_ray(render, pos);
+            Some((buffer, ray))
+        });
+        async move {
+            let (buffer, ray) = picked?;
+            let mut callbacks = None;
+            let promise =
+                js_sys::Promise::new(&mut |resolve, reject| callbacks = Some((resolve, reject)));
+            let (resolve, reject) = callbacks.unwrap();
+            buffer.map_async(wgpu::MapMode::Read, .., move |result| {
+                let callback = if result.is_ok() { resolve } else { reject };
+                let _ = callback.call0(&wasm_bindgen::JsValue::NULL);
+            });
+            wasm_bindgen_futures::JsFuture::from(promise).await.ok()?;
+            let texel: [u32; 4] = bytemuck::pod_read_unaligned(&buffer.get_mapped_range(..16));
+            buffer.unmap();
+
+            let model = texel[0].checked_sub(1)?;
+            Some((ray, model, f32::from_bits(texel[1])))
+        }
+    }
+
+    /// Zooms towards the point of the models under the cursor by an amount proportional to
+    /// `delta_y`, in pixels, zooming in when it is positive. The point is picked by the GPU
+    /// once, the scroll received until it is read back being applied together at the next
+    /// frame after, and the following scrolls reuse it while the cursor and the view only
+    /// move by zooming, which keeps the point under the cursor.
     pub fn mouse_scroll(&mut self, delta_y: f64) {
-        if delta_y != 0.0 {
-            const MIN_ZOOM: f32 = 0.1;
-            let mult = 1.0 + if delta_y > 0.0 { 1.0 } else { -1.0 } * 0.05;
-            self.view_core.camera_zoom = MIN_ZOOM.max(self.view_core.camera_zoom * mult);
+        if delta_y == 0.0 {
+            return;
+        }
+        let mut target = self.zoom_target.borrow_mut();
+        if let Some(point) = target.point {
+            drop(target);
+            self.zoom_to(point, delta_y);
+            return;
+        }
+        target.pending_scroll += delta_y;
+        if target.picking {
+            return;
         }
+        target.picking = true;
+        let generation = target.generation;
+        drop(target);
+        let pixel = self.read_pixel(self.current_pos);
+        let zoom_target = self.zoom_target.clone();
+        wasm_bindgen_futures::spawn_local(async move {
+            let point = pixel.await.map(|(ray, _, distance)| ray.at(distance));
+            let mut target = zoom_target.borrow_mut();
+            target.picking = false;
+            target.resolved = Some(point);
+            if target.generation == generation {
+                target.point = Some(point);
+            }
+        });
+    }
+
+    /// Applies the scroll received while the point to zoom towards was picked, once it is.
+    fn zoom_resolved(&mut self) {
+        let mut target = self.zoom_target.borrow_mut();
+        let Some(point) = target.resolved.take() else {
+            return;
+        };
+        let scroll = std::mem::take(&mut target.pending_scroll);
+        drop(target);
+        self.zoom_to(point, scroll);
+    }
+
+    /// Zooms by `delta_y` pixels of scroll towards `point`, in the coordinates of the models,
+    /// or towards the plane of the center of the scene when `None`.
+    fn zoom_to(&mut self, point: Option<Vector3<f32>>, delta_y: f64) {
+        // zoom factor per pixel, about 10% for a notch of a mouse wheel
+        const ZOOM_SPEED: f32 = 0.001;
+        let render = self.render.borrow();
+        let Some(render) = render.as_ref() else {
+            return;
+        };
+        self.view_core.stop_animation();
+        let (_, ray_in_eye) = self.view_core.cursor_ray(render, self.current_pos);
+        // the parameter of a point along the ray is its distance to the eye along -Z
+        let view = self.view_core.view_matrix();
+        let hit = point.map(|point| -(view * point.extend(1.0)).z);
+        self.view_core
+            .zoom_towards(&ray_in_eye, hit, delta_y as f32 * ZOOM_SPEED);
+    }
+
+    /// Forgets the point scrolling zooms towards, as the cursor or the view moved.
+    fn forget_zoom_target(&mut self) {
+        let mut target = self.zoom_target.borrow_mut();
+        target.point = None;
+        target.generation = target.generation.wrapping_add(1);
     }
 
     pub fn remove_data(&mut self, id: u32) {
         self.data.remove(&id);
-        self.data_dirty = true;
+        self.forget_zoom_target();
     }
 
     pub fn set_visible(&mut self, id: u32, visible: bool) {
         if let Some(data) = self.data.get_mut(&id) {
-            data.set_visible(visible);
+            data.set_visible(visible, js_sys::Date::now());
         }
+        self.forget_zoom_target();
     }
 
     pub fn set_edge_width(&mut self, id: u32, width: f32) {
@@ -223,12 +736,75 @@ impl Viewer {
         }
     }
 
+    /// Hides the edges inside the polygons the mesh was triangulated from, `arities` being
+    /// the number of vertices of each polygon whose triangles are consecutive in the mesh.
+    pub fn set_polygons(&mut self, id: u32, arities: &[usize]) {
+        if let Some(data) = self.data.get_mut(&id) {
+            data.set_polygons(arities);
+        }
+    }
+
+    /// Colors the mesh with one RGB color per point, or with its face color when `None`.
+    pub fn set_vertex_colors(&mut self, id: u32, colors: Option<&[f32]>) {
+        if let Some(data) = self.data.get_mut(&id) {
+            data.set_vertex_colors(colors);
+        }
+    }
+
     pub fn set_face_alpha(&mut self, id: u32, alpha: f32) {
         if let Some(data) = self.data.get_mut(&id) {
             data.material.kd[3] = alpha;
             data.dirty.insert(crate::render::view_data::DirtyFlags::DIRTY_MATERIAL);
         }
     }
+
+    /// Overrides the ambient reflectance derived from the face color, the scene ambient
+    /// light intensity being 0.1.
+    pub fn set_ambient_color(&mut self, id: u32, color: [f32; 3]) {
+        if let Some(data) = self.data.get_mut(&id) {
+            data.material.ka = [color[0] * 0.1, color[1] * 0.1, color[2] * 0.1, 1.0];
+            data.dirty.insert(crate::render::view_data::DirtyFlags::DIRTY_MATERIAL);
+        }
+    }
+
+    /// Overrides the specular color derived from the face color.
+    pub fn set_specular_color(&mut self, id: u32, color: [f32; 3]) {
+        if let Some(data) = self.data.get_mut(&id) {
+            data.material.ks = [color[0], color[1], color[2], 1.0];
+            data.dirty.insert(crate::render::view_data::DirtyFlags::DIRTY_MATERIAL);
+        }
+    }
+
+    /// Shades the mesh with one normal per point, or with its face normals when `None`.
+    pub fn set_vertex_normals(&mut self, id: u32, normals: Option<&[f32]>) {
+        if let Some(data) = self.data.get_mut(&id) {
+            data.set_vertex_normals(normals);
+        }
+    }
+
+    /// Shades the mesh with one normal per triangle corner, three values per corner, which
+    /// can differ between the corners sharing a point.
+    pub fn set_corner_normals(&mut self, id: u32, normals: &[f32]) {
+        if let Some(data) = self.data.get_mut(&id) {
+            data.set_corner_normals(normals);
+        }
+    }
+
+    /// Sets the texture coordinates of the mesh, two values per triangle corner, which can
+    /// differ between the corners sharing a point.
+    pub fn set_uvs(&mut self, id: u32, uvs: &[f32]) {
+        if let Some(data) = self.data.get_mut(&id) {
+            data.set_uvs(uvs);
+        }
+    }
+
+    /// Modulates the diffuse color of the mesh with `texture`, or removes the texture
+    /// when `None`.
+    pub fn set_texture(&mut self, id: u32, texture: Option<web_sys::ImageBitmap>) {
+        if let Some(data) = self.data.get_mut(&id) {
+            data.set_texture(texture);
+        }
+    }
 }
 
 fn two_axis_valuator_fixed_up(
@@ -253,3 +829,63 @@ fn two_axis_valuator_fixed_up(
     quat /= len;
     quat
 }
+
+/// Rotates `press_quat` along the arc between the points of a virtual sphere under
+/// `press_pos` and `pos`, the sphere filling the smaller side of the canvas. The angle is
+/// multiplied by `speed`.
+fn trackball(
+    w: u32,
+    h: u32,
+    speed: f32,
+    press_quat: &Quaternion<f32>,
+    press_pos: &PhysicalPosition<f64>,
+    pos: &PhysicalPosition<f64>,
+) -> Quaternion<f32> {
+    let from = sphere_point(w, h, press_pos);
+    let to = sphere_point(w, h, pos);
+    let axis = from.cross(to);
+    if axis.magnitude() < 1e-6 {
+        return *press_quat;
+    }
+    let angle = from.dot(to).clamp(-1.0, 1.0).acos() * speed;
+    (Quaternion::from_axis_angle(axis.normalize(), Rad(angle)) * press_quat).normalize()
+}
+
+/// Point of the virtual sphere of [`trackball`] under `pos`, in eye space. Outside of the
+/// sphere, a hyperbolic sheet keeps the rotation smooth.
+fn sphere_point(w: u32, h: u32, pos: &PhysicalPosition<f64>) -> Vector3<f32> {
+    let size = w.min(h) as f32;
+    let x = (2.0 * pos.x as f32 - w as f32) / size;
+    let y = (h as f32 - 2.0 * pos.y as f32) / size;
+    let r2 = x * x + y * y;
+    let z = if r2 <= 0.5 {
+        (1.0 - r2).sqrt()
+    } else {
+        0.5 / r2.sqrt()
+    };
+    Vector3::new(x, y, z).normalize()
+}
+
+/// Spins `press_quat` around the axis `up` of the rotated space with the horizontal drag and
+/// tilts it around the horizontal axis of the screen with the vertical one, so that `up`
+/// stays in the vertical plane of the screen. The tilt stops when `up` points to or away
+/// from the viewer.
+fn turntable(
+    w: u32,
+    h: u32,
+    speed: f32,
+    up: Vector3<f32>,
+    press_quat: &Quaternion<f32>,
+    press_pos: &PhysicalPosition<f64>,
+    pos: &PhysicalPosition<f64>,
+) -> Quaternion<f32> {
+    let spin = ((pos.x - press_pos.x) as f32) / (w as f32) * speed;
+    let tilt = ((pos.y - press_pos.y) as f32) / (h as f32) * speed;
+    let up_in_eye = press_quat.rotate_vector(up);
+    let press_tilt = up_in_eye.z.atan2(up_in_eye.y);
+    let tilt = (press_tilt + tilt).clamp(-FRAC_PI_2, FRAC_PI_2) - press_tilt;
+    let quat = Quaternion::from_axis_angle(Vector3::unit_x(), Rad(tilt))
+        * press_quat
+        * Quaternion::from_axis_angle(up, Rad(spin));
+    quat.normalize()
+}
@@ -0,0 +1,576 @@
+//! Parsing of mesh files and computation of normals in a Web Worker, so that large meshes
+//! do not freeze the page.
+//!
+//! The page starts one worker per file with [`parse_in_worker`]. The worker says it is
+//! ready once its module is loaded, then receives the file, posts the progress of the
+//! reader and finally the meshes. Each array of a mesh is copied once out of the memory of
+//! the worker into a typed array, whose buffer is transferred to the page rather than
+//! copied, then once into the memory of the page. [`corner_normals_in_worker`] does the
+//! same the other way around with the points and triangles of a mesh, and gets back its
+//! normals, but from a single worker kept for all the normals, as they are computed again
+//! whenever the shading changes.
+
+use std::{
+    cell::{Cell, RefCell},
+    collections::{HashMap, VecDeque},
+    rc::Rc,
+};
+
+use js_sys::{Array, Float32Array, Float64Array, Function, Object, Promise, Reflect, Uint32Array};
+use wasm_bindgen::{JsCast, JsValue, prelude::Closure};
+use wasm_bindgen_futures::JsFuture;
+
+use crate::{
+    io,
+    mesh::{self, Attributes, Polygons, normals::Weighting},
+    read_file_bytes,
+};
+
+/// Script generated by trunk for the `worker` binary, see `index.html`.
+const WORKER_SCRIPT: &str = "./worker.js";
+
+// the index arrays are copied as they are between usize and u32
+const _: () = assert!(size_of::<usize>() == size_of::<u32>());
+
+#[derive(Debug)]
+pub enum WorkerError {
+    /// the worker could not be started, the work has to be done on the page
+    Unavailable,
+    Cancelled,
+    Failed(io::ReadError),
+}
+
+/// Stops the work started by [`parse_in_worker`] or [`corner_normals_in_worker`].
+#[derive(Clone)]
+pub struct Canceller {
+    /// `None` when the worker could not be created
+    worker: Option<web_sys::Worker>,
+    reject: Function,
+    cancelled: Rc<Cell<bool>>,
+}
+
+impl Canceller {
+    pub fn cancel(&self) {
+        self.cancelled.set(true);
+        if let Some(worker) = &self.worker {
+            worker.terminate();
+        }
+        let _ = self.reject.call1(&JsValue::NULL, &JsValue::NULL);
+    }
+}
+
+/// Parses `file` in a new worker, `resources` being the material libraries and buffers it
+/// may refer to, and merges its objects into one mesh if `merge` is set. `on_progress` is
+/// called with the fraction of the work done.
+pub fn parse_in_worker(
+    file: &web_sys::File,
+    resources: &[web_sys::File],
+    merge: bool,
+    on_progress: impl Fn(f64) + 'static,
+) -> (
+    Canceller,
+    impl Future<Output = Result<Vec<io::ImportedMesh>, WorkerError>>,
+) {
+    let request = Object::new();
+    set(&request, "file", file);
+    set(&request, "resources", &Array::from_iter(resources));
+    set(&request, "merge", &merge.into());
+
+    let (canceller, result) = start(request, Array::new(), on_progress);
+    let meshes = async move {
+        let message = result.await?;
+        Ok(Vec::from_iter(
+            Array::from(&get(&message, "meshes")).iter().map(|mesh| decode(&mesh)),
+        ))
+    };
+    (canceller, meshes)
+}
+
+/// Computes the normals of the corners of `triangles` in the worker shared by all the
+/// normals, see [`mesh::normals::corner_normals`]. The jobs are run one at a time, in order,
+/// a cancelled one being skipped if it has not started yet.
+pub fn corner_normals_in_worker(
+    points: &[f64],
+    triangles: &[usize],
+    weighting: Weighting,
+    crease_angle: Option<f64>,
+) -> (Canceller, impl Future<Output = Result<Vec<f32>, WorkerError>> + use<>) {
+    let request = Object::new();
+    let transfer = Array::new();
+    let points = Float64Array::from(points);
+    transfer.push(&points.buffer());
+    set(&request, "points", &points);
+    let triangles = Uint32Array::from(bytemuck::cast_slice::<usize, u32>(triangles));
+    transfer.push(&triangles.buffer());
+    set(&request, "triangles", &triangles);
+    let weighting = match weighting {
+        Weighting::Area => "area",
+        Weighting::Angle => "angle",
+    };
+    set(&request, "weighting", &weighting.into());
+    if let Some(angle) = crease_angle {
+        set(&request, "creaseAngle", &angle.into());
+    }
+
+    let mut callbacks = None;
+    let promise = Promise::new(&mut |resolve, reject| callbacks = Some((resolve, reject)));
+    let (resolve, reject) = callbacks.unwrap();
+    let cancelled = Rc::new(Cell::new(false));
+    let canceller = Canceller {
+        // the worker goes on with the next jobs
+        worker: None,
+        reject: reject.clone(),
+        cancelled: cancelled.clone(),
+    };
+    NORMALS_WORKER.with(|normals_worker| {
+        let mut state = normals_worker.borrow_mut();
+        let id = state.next_id;
+        state.next_id = state.next_id.wrapping_add(1);
+        set(&request, "id", &id.into());
+        state.waiting.push_back(NormalsJob {
+            id,
+            request,
+            transfer,
+            resolve,
+            reject,
+            cancelled: cancelled.clone(),
+        });
+        drop(state);
+        pump(normals_worker);
+    });
+    let normals = async move {
+        let message = match JsFuture::from(promise).await {
+            Ok(message) => message,
+            Err(_) if cancelled.get() => return Err(WorkerError::Cancelled),
+            Err(error) => {
+                return Err(match error.as_string() {
+                    Some(message) => WorkerError::Failed(message.into()),
+                    None => WorkerError::Unavailable,
+                });
+            }
+        };
+        get(&message, "normals")
+            .dyn_into::<Float32Array>()
+            .map(|normals| normals.to_vec())
+            .map_err(|_| WorkerError::Failed("the worker sent no normals".into()))
+    };
+    (canceller, normals)
+}
+
+/// A request of [`corner_normals_in_worker`], settled with the last message of the worker
+/// about it.
+struct NormalsJob {
+    id: u32,
+    request: Object,
+    transfer: Array,
+    resolve: Function,
+    /// called with `null` when no worker can run the job, and with a message when it failed
+    reject: Function,
+    cancelled: Rc<Cell<bool>>,
+}
+
+/// The worker computing the normals, started on the first job and kept for the next ones,
+/// since each worker loads the whole module.
+#[derive(Default)]
+struct NormalsWorker {
+    /// `None` before the first job and after a crash, the next job starting a new one
+    worker: Option<web_sys::Worker>,
+    /// the handlers of the messages and errors of `worker`
+    onmessage: Option<Closure<dyn Fn(web_sys::MessageEvent)>>,
+    onerror: Option<Closure<dyn Fn(JsValue)>>,
+    ready: bool,
+    /// the job the worker is running
+    running: Option<NormalsJob>,
+    waiting: VecDeque<NormalsJob>,
+    next_id: u32,
+}
+
+thread_local! {
+    static NORMALS_WORKER: Rc<RefCell<NormalsWorker>> = Default::default();
+}
+
+/// Starts the normals worker if there is none, and sends it the next job once it is ready
+/// and done with the previous one.
+fn pump(normals_worker: &Rc<RefCell<NormalsWorker>>) {
+    let mut state = normals_worker.borrow_mut();
+    if state.worker.is_none() {
+        if state.waiting.is_empty() {
+            return;
+        }
+        let Ok(worker) = web_sys::Worker::new(WORKER_SCRIPT) else {
+            for job in state.waiting.drain(..) {
+                let _ = job.reject.call1(&JsValue::NULL, &JsValue::NULL);
+            }
+            return;
+        };
+        let onmessage = {
+            let normals_worker = Rc::downgrade(normals_worker);
+            Closure::<dyn Fn(web_sys::MessageEvent)>::new(move |event: web_sys::MessageEvent| {
+                let Some(normals_worker) = normals_worker.upgrade() else {
+                    return;
+                };
+                let data = event.data();
+                {
+                    let mut state = normals_worker.borrow_mut();
+                    if get(&data, "ready").is_truthy() {
+                        state.ready = true;
+                    } else if let Some(job) = state
+                        .running
+                        .take_if(|job| get(&data, "id").as_f64() == Some(job.id.into()))
+                    {
+                        let _ = job.resolve.call1(&JsValue::NULL, &data);
+                    }
+                }
+                pump(&normals_worker);
+            })
+        };
+        // loading errors come before the worker is ready, the others are panics
+        let onerror = {
+            let normals_worker = Rc::downgrade(normals_worker);
+            Closure::<dyn Fn(JsValue)>::new(move |_| {
+                let Some(normals_worker) = normals_worker.upgrade() else {
+                    return;
+                };
+                let mut state = normals_worker.borrow_mut();
+                if let Some(worker) = state.worker.take() {
+                    worker.terminate();
+                }
+                if !state.ready {
+                    for job in state.running.take().into_iter().chain(state.waiting.drain(..)) {
+                        let _ = job.reject.call1(&JsValue::NULL, &JsValue::NULL);
+                    }
+                } else if let Some(job) = state.running.take() {
+                    let _ = job.reject.call1(&JsValue::NULL, &"the worker crashed".into());
+                }
+                state.ready = false;
+                // dropped once the handler returns
+                let callbacks = (state.onmessage.take(), state.onerror.take());
+                drop(state);
+                wasm_bindgen_futures::spawn_local(async move { drop(callbacks) });
+                pump(&normals_worker);
+            })
+        };
+        worker.set_onmessage(Some(onmessage.as_ref().unchecked_ref()));
+        worker.set_onerror(Some(onerror.as_ref().unchecked_ref()));
+        state.worker = Some(worker);
+        state.onmessage = Some(onmessage);
+        state.onerror = Some(onerror);
+        return;
+    }
+    if !state.ready || state.running.is_some() {
+        return;
+    }
+    while let Some(job) = state.waiting.pop_front() {
+        if job.cancelled.get() {
+            continue;
+        }
+        let worker = state.worker.as_ref().unwrap();
+        if worker.post_message_with_transfer(&job.request, &job.transfer).is_err() {
+            let _ = job.reject.call1(&JsValue::NULL, &"failed to send the request".into());
+            continue;
+        }
+        state.running = Some(job);
+        break;
+    }
+}
+
+/// Starts a new worker, sends it `request` with the buffers of `transfer` once it is ready,
+/// and gives the last message of the worker, `on_progress` being called with the progress
+/// it posts until then.
+fn start(
+    request: Object,
+    transfer: Array,
+    on_progress: impl Fn(f64) + 'static,
+) -> (Canceller, impl Future<Output = Result<JsValue, WorkerError>>) {
+    let mut callbacks = None;
+    let promise = Promise::new(&mut |resolve, reject| callbacks = Some((resolve, reject)));
+    let (resolve, reject) = callbacks.unwrap();
+    let ready = Rc::new(Cell::new(false));
+    let cancelled = Rc::new(Cell::new(false));
+
+    let worker = web_sys::Worker::new(WORKER_SCRIPT).ok();
+    let (onmessage, onerror) = match &worker {
+        Some(worker) => {
+            let onmessage = {
+                let worker = worker.clone();
+                let reject = reject.clone();
+                let ready = ready.clone();
+                Closure::<dyn Fn(web_sys::MessageEvent)>::new(
+                    move |event: web_sys::MessageEvent| {
+                        let data = event.data();
+                        if get(&data, "ready").is_truthy() {
+                            ready.set(true);
+                            if worker.post_message_with_transfer(&request, &transfer).is_err() {
+                                let _ = reject
+                                    .call1(&JsValue::NULL, &"failed to send the request".into());
+                            }
+                        } else if let Some(progress) = get(&data, "progress").as_f64() {
+                            on_progress(progress);
+                        } else if get(&data, "error").is_string() {
+                            let _ = reject.call1(&JsValue::NULL, &data);
+                        } else {
+                            let _ = resolve.call1(&JsValue::NULL, &data);
+                        }
+                    },
+                )
+            };
+            // loading errors come before the worker is ready, the others are panics
+            let onerror = {
+                let reject = reject.clone();
+                Closure::<dyn Fn(JsValue)>::new(move |_| {
+                    let _ = reject.call1(&JsValue::NULL, &"the worker crashed".into());
+                })
+            };
+            worker.set_onmessage(Some(onmessage.as_ref().unchecked_ref()));
+            worker.set_onerror(Some(onerror.as_ref().unchecked_ref()));
+            (Some(onmessage), Some(onerror))
+        }
+        None => {
+            let _ = reject.call1(&JsValue::NULL, &JsValue::NULL);
+            (None, None)
+        }
+    };
+
+    let canceller = Canceller {
+        worker: worker.clone(),
+        reject,
+        cancelled: cancelled.clone(),
+    };
+    let result = async move {
+        let result = JsFuture::from(promise).await;
+        if let Some(worker) = &worker {
+            worker.terminate();
+        }
+        drop((onmessage, onerror));
+        match result {
+            Ok(message) => Ok(message),
+            Err(_) if cancelled.get() => Err(WorkerError::Cancelled),
+            Err(_) if !ready.get() => Err(WorkerError::Unavailable),
+            Err(error) => Err(WorkerError::Failed(match error.as_string() {
+                Some(message) => message.into(),
+                None => io::ReadError {
+                    message: get(&error, "error")
+                        .as_string()
+                        .unwrap_or_else(|| "the worker failed".to_owned()),
+                    line: get(&error, "line").as_f64().map(|line| line as usize),
+                },
+            })),
+        }
+    };
+    (canceller, result)
+}
+
+/// Reads the meshes of `bytes` and merges them if asked to, on whichever thread runs it.
+/// `on_progress` is called with the fraction of the file read.
+pub fn parse(
+    name: &str,
+    bytes: &[u8],
+    resources: &HashMap<String, Vec<u8>>,
+    merge: bool,
+    on_progress: impl Fn(f64),
+) -> Result<Vec<io::ImportedMesh>, io::ReadError> {
+    let mut meshes = io::read_file(name, bytes, resources, &io::Progress::new(&on_progress))?;
+    if merge && meshes.len() > 1 {
+        meshes = vec![io::ImportedMesh::merge(meshes)];
+    }
+    Ok(meshes)
+}
+
+/// Entry point of the worker binary.
+pub fn run() {
+    let scope: web_sys::DedicatedWorkerGlobalScope = js_sys::global().unchecked_into();
+    let onmessage = {
+        let scope = scope.clone();
+        Closure::<dyn Fn(web_sys::MessageEvent)>::new(move |event: web_sys::MessageEvent| {
+            let scope = scope.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                let post = |message: &Object, transfer: &Array| {
+                    let _ = scope.post_message_with_transfer(message, transfer);
+                };
+                let on_progress = |progress: f64| {
+                    let message = Object::new();
+                    set(&message, "progress", &progress.into());
+                    post(&message, &Array::new());
+                };
+                let message = Object::new();
+                let transfer = Array::new();
+                let request = event.data();
+                if let Some(normals) = normals_request(&request) {
+                    let normals = Float32Array::from(normals.as_slice());
+                    transfer.push(&normals.buffer());
+                    set(&message, "id", &get(&request, "id"));
+                    set(&message, "normals", &normals);
+                    post(&message, &transfer);
+                    return;
+                }
+                match parse_request(&request, on_progress).await {
+                    Ok(meshes) => {
+                        let meshes = Array::from_iter(meshes.iter().map(|m| encode(m, &transfer)));
+                        set(&message, "meshes", &meshes);
+                    }
+                    Err(error) => {
+                        set(&message, "error", &error.message.into());
+                        if let Some(line) = error.line {
+                            set(&message, "line", &(line as u32).into());
+                        }
+                    }
+                }
+                post(&message, &transfer);
+            });
+        })
+    };
+    scope.set_onmessage(Some(onmessage.as_ref().unchecked_ref()));
+    onmessage.forget();
+
+    let ready = Object::new();
+    set(&ready, "ready", &true.into());
+    let _ = scope.post_message(&ready);
+}
+
+/// Computes the normals asked by `request`, or returns `None` if it is not about normals.
+fn normals_request(request: &JsValue) -> Option<Vec<f32>> {
+    let points = get(request, "points").dyn_into::<Float64Array>().ok()?.to_vec();
+    let triangles = get(request, "triangles").dyn_into::<Uint32Array>().ok()?;
+    let mut indices = vec![0usize; triangles.length() as usize];
+    triangles.copy_to(bytemuck::cast_slice_mut::<usize, u32>(&mut indices));
+    let weighting = match get(request, "weighting").as_string().as_deref() {
+        Some("angle") => Weighting::Angle,
+        _ => Weighting::Area,
+    };
+    let crease_angle = get(request, "creaseAngle").as_f64();
+    Some(mesh::normals::corner_normals(&points, &indices, weighting, crease_angle))
+}
+
+async fn parse_request(
+    request: &JsValue,
+    on_progress: impl Fn(f64),
+) -> Result<Vec<io::ImportedMesh>, io::ReadError> {
+    let file: web_sys::File = get(request, "file")
+        .dyn_into()
+        .map_err(|_| "missing file to parse")?;
+    let mut resources = HashMap::new();
+    for resource in Array::from(&get(request, "resources")).iter() {
+        let resource: web_sys::File = resource.unchecked_into();
+        resources.insert(resource.name(), read_file_bytes(&resource).await?);
+    }
+    let bytes = read_file_bytes(&file).await?;
+    let merge = get(request, "merge").is_truthy();
+    parse(&file.name(), &bytes, &resources, merge, on_progress)
+}
+
+/// Converts `mesh` to a message, adding the buffers of its arrays to `transfer`.
+fn encode(mesh: &io::ImportedMesh, transfer: &Array) -> Object {
+    let message = Object::new();
+    let add = |key: &str, array: &JsValue, buffer: JsValue| {
+        transfer.push(&buffer);
+        set(&message, key, array);
+    };
+    let f64s = |key: &str, values: &[f64]| {
+        let array = Float64Array::from(values);
+        add(key, &array, array.buffer().into());
+    };
+    let f32s = |key: &str, values: &[f32]| {
+        let array = Float32Array::from(values);
+        add(key, &array, array.buffer().into());
+    };
+    let indices = |key: &str, values: &[usize]| {
+        // usize is u32 on wasm32, the indices are copied as they are
+        let array = Uint32Array::from(bytemuck::cast_slice::<usize, u32>(values));
+        add(key, &array, array.buffer().into());
+    };
+
+    if let Some(name) = &mesh.name {
+        set(&message, "name", &name.into());
+    }
+    if let Some(parent) = mesh.parent {
+        set(&message, "parent", &(parent as u32).into());
+    }
+    if let Some(material) = mesh
+        .material
+        .as_ref()
+        .and_then(|m| serde_json::to_string(m).ok())
+    {
+        set(&message, "material", &material.into());
+    }
+    f64s("points", &mesh.points);
+    indices("triangles", &mesh.triangles);
+    if let Some(polygons) = &mesh.polygons {
+        indices("polygonIndices", &polygons.indices);
+        indices("polygonArities", &polygons.arities);
+    }
+    let attributes = &mesh.attributes;
+    for (key, values) in [
+        ("colors", &attributes.colors),
+        ("normals", &attributes.normals),
+        ("uvs", &attributes.uvs),
+    ] {
+        if let Some(values) = values {
+            f32s(key, values);
+        }
+    }
+    let names = Array::new();
+    for (name, values) in &attributes.scalars {
+        names.push(&name.into());
+        f64s(&format!("scalar{}", names.length() - 1), values);
+    }
+    set(&message, "scalarNames", &names);
+    message
+}
+
+fn decode(message: &JsValue) -> io::ImportedMesh {
+    let f64s = |key: &str| {
+        get(message, key)
+            .dyn_into::<Float64Array>()
+            .ok()
+            .map(|a| a.to_vec())
+    };
+    let f32s = |key: &str| {
+        get(message, key)
+            .dyn_into::<Float32Array>()
+            .ok()
+            .map(|a| a.to_vec())
+    };
+    let indices = |key: &str| {
+        get(message, key).dyn_into::<Uint32Array>().ok().map(|a| {
+            // straight into the final vector, usize being u32 on wasm32
+            let mut indices = vec![0usize; a.length() as usize];
+            a.copy_to(bytemuck::cast_slice_mut::<usize, u32>(&mut indices));
+            indices
+        })
+    };
+
+    let polygons = match (indices("polygonIndices"), indices("polygonArities")) {
+        (Some(indices), Some(arities)) => Some(Polygons { indices, arities }),
+        _ => None,
+    };
+    let scalars = Vec::from_iter(
+        Array::from(&get(message, "scalarNames"))
+            .iter()
+            .enumerate()
+            .filter_map(|(k, name)| Some((name.as_string()?, f64s(&format!("scalar{}", k))?))),
+    );
+    io::ImportedMesh {
+        name: get(message, "name").as_string(),
+        points: f64s("points").unwrap_or_default(),
+        triangles: indices("triangles").unwrap_or_default(),
+        polygons,
+        attributes: Attributes {
+            colors: f32s("colors"),
+            normals: f32s("normals"),
+            uvs: f32s("uvs"),
+            scalars,
+        },
+        material: get(message, "material")
+            .as_string()
+            .and_then(|m| serde_json::from_str(&m).ok()),
+        parent: get(message, "parent").as_f64().map(|p| p as usize),
+    }
+}
+
+fn set(target: &Object, key: &str, value: &JsValue) {
+    let _ = Reflect::set(target, &key.into(), value);
+}
+
+fn get(target: &JsValue, key: &str) -> JsValue {
+    Reflect::get(target, &key.into()).unwrap_or(JsValue::UNDEFINED)
+}